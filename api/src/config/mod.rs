@@ -0,0 +1,553 @@
+use crate::services::address::parse_address;
+use bitcoin::Network;
+use serde::{Deserialize, Deserializer};
+use std::{collections::BTreeMap, env, path::Path, str::FromStr, time::Duration};
+use thiserror::Error;
+
+#[derive(Error, Debug)]
+pub enum ConfigError {
+    #[error("Failed to read config file {0}: {1}")]
+    Io(String, std::io::Error),
+    #[error("Failed to parse config file {0}: {1}")]
+    Parse(String, toml::de::Error),
+    #[error("Invalid value for {0}: {1}")]
+    InvalidValue(String, String),
+    #[error("Invalid configuration: {0}")]
+    Invalid(String),
+}
+
+pub type ConfigResult<T> = Result<T, ConfigError>;
+
+/// Application configuration.
+///
+/// Loaded from a TOML file (`CONFIG_FILE`, default `config.toml` when
+/// present), then overridden by environment variables, then validated once
+/// at boot. Handlers never read the environment; what they need is built
+/// from this into `AppState`.
+///
+/// The top-level `chain`, `rpc`, `prover` and `signing` sections belong to
+/// the default `network`; each entry of `networks` configures another one
+/// with its own.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    #[serde(deserialize_with = "deserialize_network")]
+    pub network: Option<Network>,
+    pub server: ServerConfig,
+    pub chain: ChainConfig,
+    pub rpc: RpcConfig,
+    pub prover: ProverConfig,
+    pub signing: SigningConfig,
+    pub cors: CorsConfig,
+    pub limits: LimitsConfig,
+    pub auth: AuthConfig,
+    /// Additional networks keyed by name (`mainnet`, `testnet4`, ...)
+    pub networks: BTreeMap<String, NetworkConfig>,
+}
+
+/// Backends, prover and signing policy serving one network
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct NetworkConfig {
+    pub chain: ChainConfig,
+    pub rpc: RpcConfig,
+    pub prover: ProverConfig,
+    pub signing: SigningConfig,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ServerConfig {
+    pub host: String,
+    pub port: u16,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ChainConfig {
+    /// Chain sources in failover order
+    pub backends: Vec<String>,
+    /// Agreeing sources required for UTXO queries when several are set
+    pub quorum: usize,
+    pub esplora_url: Option<String>,
+    pub electrum_url: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RpcConfig {
+    pub host: String,
+    pub port: u16,
+    pub user: Option<String>,
+    pub password: Option<String>,
+    /// Takes precedence over user/password when set
+    pub cookie: Option<String>,
+    /// Wallet used by default for wallet-scoped calls
+    pub wallet: Option<String>,
+    /// Extra wallets selectable per request with `?wallet=`
+    pub wallets: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct ProverConfig {
+    pub url: String,
+}
+
+/// Policy for transactions signed with the node wallet
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SigningConfig {
+    /// Refuse to sign for requests that present no API key
+    pub require_auth: bool,
+    /// Addresses the wallet may pay; empty allows any. Change back to the
+    /// node wallet is always allowed.
+    pub allowed_destinations: Vec<String>,
+    /// Highest fee of a signed transaction, in sats
+    pub max_fee: Option<u64>,
+    /// Sats that may leave the wallet per UTC day, across all callers
+    pub daily_limit: Option<u64>,
+    /// Sats that each API key may spend per UTC day
+    pub key_daily_limit: Option<u64>,
+    /// File that receives a JSON line for every signing decision
+    pub audit_log: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CorsConfig {
+    /// Allowed origins; `*` allows any
+    pub allowed_origins: Vec<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct LimitsConfig {
+    pub max_body_bytes: usize,
+    pub bitcoin_cli_max_concurrency: usize,
+    pub bitcoin_cli_timeout_secs: u64,
+    /// Limit on one call to a chain backend, connecting included
+    pub chain_timeout_secs: u64,
+    /// Limit on opening a connection to a remote chain backend
+    pub chain_connect_timeout_secs: u64,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct AuthConfig {
+    /// Keys accepted as `Authorization: Bearer <key>`; empty disables auth
+    pub api_keys: Vec<String>,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            host: "0.0.0.0".to_string(),
+            port: 3333,
+        }
+    }
+}
+
+impl Default for ChainConfig {
+    fn default() -> Self {
+        Self {
+            backends: vec!["bitcoin-cli".to_string()],
+            quorum: 1,
+            esplora_url: None,
+            electrum_url: None,
+        }
+    }
+}
+
+impl Default for RpcConfig {
+    fn default() -> Self {
+        Self {
+            host: "localhost".to_string(),
+            port: 48332,
+            user: None,
+            password: None,
+            cookie: None,
+            wallet: None,
+            wallets: Vec::new(),
+        }
+    }
+}
+
+impl Default for ProverConfig {
+    fn default() -> Self {
+        Self {
+            url: "https://prove-t4.charms.dev/spells/prove".to_string(),
+        }
+    }
+}
+
+impl Default for SigningConfig {
+    fn default() -> Self {
+        Self {
+            require_auth: true,
+            allowed_destinations: Vec::new(),
+            max_fee: None,
+            daily_limit: None,
+            key_daily_limit: None,
+            audit_log: None,
+        }
+    }
+}
+
+impl Default for CorsConfig {
+    fn default() -> Self {
+        Self {
+            allowed_origins: vec!["*".to_string()],
+        }
+    }
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        Self {
+            max_body_bytes: 2 * 1024 * 1024,
+            bitcoin_cli_max_concurrency: 8,
+            bitcoin_cli_timeout_secs: 30,
+            chain_timeout_secs: 10,
+            chain_connect_timeout_secs: 5,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn chain_timeout(&self) -> Duration {
+        Duration::from_secs(self.chain_timeout_secs)
+    }
+
+    pub fn chain_connect_timeout(&self) -> Duration {
+        Duration::from_secs(self.chain_connect_timeout_secs)
+    }
+}
+
+/// Parse a network name, accepting the wallet's `mainnet` for `bitcoin`
+pub fn parse_network(name: &str) -> Result<Network, String> {
+    match name {
+        "mainnet" => Ok(Network::Bitcoin),
+        other => Network::from_str(other).map_err(|e| e.to_string()),
+    }
+}
+
+/// Name of a network as used by the wallet and in route prefixes
+pub fn network_name(network: Network) -> &'static str {
+    match network {
+        Network::Bitcoin => "mainnet",
+        Network::Testnet => "testnet",
+        Network::Testnet4 => "testnet4",
+        Network::Signet => "signet",
+        Network::Regtest => "regtest",
+    }
+}
+
+fn deserialize_network<'de, D: Deserializer<'de>>(d: D) -> Result<Option<Network>, D::Error> {
+    let name = Option::<String>::deserialize(d)?;
+    name.map(|name| parse_network(&name).map_err(serde::de::Error::custom))
+        .transpose()
+}
+
+// Split a comma-separated env value into trimmed, non-empty items
+fn env_list(value: &str) -> Vec<String> {
+    value
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn env_parse<T: FromStr>(name: &'static str, value: &str) -> ConfigResult<T> {
+    value
+        .parse()
+        .map_err(|_| ConfigError::InvalidValue(name.to_string(), value.to_string()))
+}
+
+impl Config {
+    /// Load, apply environment overrides and validate
+    pub fn load() -> ConfigResult<Self> {
+        let path = env::var("CONFIG_FILE").ok();
+        let mut config = match &path {
+            Some(path) => Self::from_file(path)?,
+            None if Path::new("config.toml").exists() => Self::from_file("config.toml")?,
+            None => Self::default(),
+        };
+
+        config.apply_env(|name| env::var(name).ok())?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    pub fn from_file(path: &str) -> ConfigResult<Self> {
+        let text =
+            std::fs::read_to_string(path).map_err(|e| ConfigError::Io(path.to_string(), e))?;
+        toml::from_str(&text).map_err(|e| ConfigError::Parse(path.to_string(), e))
+    }
+
+    /// Override file values with environment variables of the same meaning
+    pub fn apply_env(&mut self, var: impl Fn(&str) -> Option<String>) -> ConfigResult<()> {
+        if let Some(v) = var("BITCOIN_NETWORK") {
+            self.network = Some(
+                parse_network(&v)
+                    .map_err(|e| ConfigError::InvalidValue("BITCOIN_NETWORK".to_string(), e))?,
+            );
+        }
+        if let Some(v) = var("HOST") {
+            self.server.host = v;
+        }
+        if let Some(v) = var("PORT") {
+            self.server.port = env_parse("PORT", &v)?;
+        }
+        if let Some(v) = var("CHAIN_BACKEND") {
+            self.chain.backends = env_list(&v);
+        }
+        if let Some(v) = var("CHAIN_QUORUM") {
+            self.chain.quorum = env_parse("CHAIN_QUORUM", &v)?;
+        }
+        if let Some(v) = var("CHAIN_TIMEOUT_SECS") {
+            self.limits.chain_timeout_secs = env_parse("CHAIN_TIMEOUT_SECS", &v)?;
+        }
+        if let Some(v) = var("CHAIN_CONNECT_TIMEOUT_SECS") {
+            self.limits.chain_connect_timeout_secs = env_parse("CHAIN_CONNECT_TIMEOUT_SECS", &v)?;
+        }
+        if let Some(v) = var("ESPLORA_URL") {
+            self.chain.esplora_url = Some(v);
+        }
+        if let Some(v) = var("ELECTRUM_URL") {
+            self.chain.electrum_url = Some(v);
+        }
+        if let Some(v) = var("BITCOIN_RPC_HOST") {
+            self.rpc.host = v;
+        }
+        if let Some(v) = var("BITCOIN_RPC_PORT") {
+            self.rpc.port = env_parse("BITCOIN_RPC_PORT", &v)?;
+        }
+        if let Some(v) = var("BITCOIN_RPC_USER") {
+            self.rpc.user = Some(v);
+        }
+        if let Some(v) = var("BITCOIN_RPC_PASSWORD") {
+            self.rpc.password = Some(v);
+        }
+        if let Some(v) = var("BITCOIN_RPC_COOKIE") {
+            self.rpc.cookie = Some(v);
+        }
+        if let Some(v) = var("BITCOIN_RPC_WALLET") {
+            self.rpc.wallet = Some(v);
+        }
+        if let Some(v) = var("BITCOIN_RPC_WALLETS") {
+            self.rpc.wallets = env_list(&v);
+        }
+        if let Some(v) = var("BITCOIN_CLI_MAX_CONCURRENCY") {
+            self.limits.bitcoin_cli_max_concurrency = env_parse("BITCOIN_CLI_MAX_CONCURRENCY", &v)?;
+        }
+        if let Some(v) = var("BITCOIN_CLI_TIMEOUT_SECS") {
+            self.limits.bitcoin_cli_timeout_secs = env_parse("BITCOIN_CLI_TIMEOUT_SECS", &v)?;
+        }
+        if let Some(v) = var("MAX_BODY_BYTES") {
+            self.limits.max_body_bytes = env_parse("MAX_BODY_BYTES", &v)?;
+        }
+        if let Some(v) = var("PROVER_API_URL") {
+            self.prover.url = v;
+        }
+        if let Some(v) = var("SIGNING_REQUIRE_AUTH") {
+            self.signing.require_auth = env_parse("SIGNING_REQUIRE_AUTH", &v)?;
+        }
+        if let Some(v) = var("SIGNING_ALLOWED_DESTINATIONS") {
+            self.signing.allowed_destinations = env_list(&v);
+        }
+        if let Some(v) = var("SIGNING_MAX_FEE") {
+            self.signing.max_fee = Some(env_parse("SIGNING_MAX_FEE", &v)?);
+        }
+        if let Some(v) = var("SIGNING_DAILY_LIMIT") {
+            self.signing.daily_limit = Some(env_parse("SIGNING_DAILY_LIMIT", &v)?);
+        }
+        if let Some(v) = var("SIGNING_KEY_DAILY_LIMIT") {
+            self.signing.key_daily_limit = Some(env_parse("SIGNING_KEY_DAILY_LIMIT", &v)?);
+        }
+        if let Some(v) = var("SIGNING_AUDIT_LOG") {
+            self.signing.audit_log = Some(v);
+        }
+        if let Some(v) = var("CORS_ORIGINS") {
+            self.cors.allowed_origins = env_list(&v);
+        }
+        if let Some(v) = var("API_KEYS") {
+            self.auth.api_keys = env_list(&v);
+        }
+
+        Ok(())
+    }
+
+    /// Check the configuration is complete and consistent
+    pub fn validate(&self) -> ConfigResult<()> {
+        if self.network.is_none() {
+            return Err(ConfigError::Invalid(
+                "network is required (set BITCOIN_NETWORK or `network` in the config file)"
+                    .to_string(),
+            ));
+        }
+
+        format!("{}:{}", self.server.host, self.server.port)
+            .parse::<std::net::SocketAddr>()
+            .map_err(|e| {
+                ConfigError::InvalidValue(
+                    "server.host".to_string(),
+                    format!("{}: {}", self.server.host, e),
+                )
+            })?;
+
+        let mut seen = Vec::new();
+        for (network, settings) in self.network_configs()? {
+            if seen.contains(&network) {
+                return Err(ConfigError::Invalid(format!(
+                    "network {} is configured twice",
+                    network_name(network)
+                )));
+            }
+            seen.push(network);
+            settings.validate(network, &self.prefix(network))?;
+        }
+
+        if self.limits.bitcoin_cli_max_concurrency == 0 {
+            return Err(ConfigError::InvalidValue(
+                "limits.bitcoin_cli_max_concurrency".to_string(),
+                "must be at least 1".to_string(),
+            ));
+        }
+        if self.limits.bitcoin_cli_timeout_secs == 0
+            || self.limits.chain_timeout_secs == 0
+            || self.limits.chain_connect_timeout_secs == 0
+        {
+            return Err(ConfigError::Invalid(
+                "timeouts must be at least 1 second".to_string(),
+            ));
+        }
+
+        for origin in &self.cors.allowed_origins {
+            if origin != "*" && http::HeaderValue::from_str(origin).is_err() {
+                return Err(ConfigError::InvalidValue(
+                    "cors.allowed_origins".to_string(),
+                    origin.clone(),
+                ));
+            }
+        }
+        if self.auth.api_keys.iter().any(|key| key.len() < 16) {
+            return Err(ConfigError::InvalidValue(
+                "auth.api_keys".to_string(),
+                "keys must be at least 16 characters".to_string(),
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Every configured network with its settings, the default one first
+    pub fn network_configs(&self) -> ConfigResult<Vec<(Network, NetworkConfig)>> {
+        let mut networks = vec![(
+            self.network(),
+            NetworkConfig {
+                chain: self.chain.clone(),
+                rpc: self.rpc.clone(),
+                prover: self.prover.clone(),
+                signing: self.signing.clone(),
+            },
+        )];
+        for (name, settings) in &self.networks {
+            let network = parse_network(name)
+                .map_err(|e| ConfigError::InvalidValue(format!("networks.{}", name), e))?;
+            networks.push((network, settings.clone()));
+        }
+        Ok(networks)
+    }
+
+    // Key prefix of a network's settings, for error messages
+    fn prefix(&self, network: Network) -> String {
+        if Some(network) == self.network {
+            String::new()
+        } else {
+            format!("networks.{}.", network_name(network))
+        }
+    }
+
+    /// The validated default network
+    pub fn network(&self) -> Network {
+        self.network
+            .expect("network is checked by Config::validate")
+    }
+}
+
+impl NetworkConfig {
+    // `prefix` locates these settings in the file, e.g. `networks.mainnet.`
+    fn validate(&self, network: Network, prefix: &str) -> ConfigResult<()> {
+        const KNOWN_BACKENDS: [&str; 4] = ["bitcoin-cli", "bitcoin-rpc", "esplora", "electrum"];
+        if self.chain.backends.is_empty() {
+            return Err(ConfigError::Invalid(format!(
+                "{}chain.backends needs at least one backend",
+                prefix
+            )));
+        }
+        for backend in &self.chain.backends {
+            if !KNOWN_BACKENDS.contains(&backend.as_str()) {
+                return Err(ConfigError::InvalidValue(
+                    format!("{}chain.backends", prefix),
+                    format!(
+                        "unknown backend {} (expected one of {})",
+                        backend,
+                        KNOWN_BACKENDS.join(", ")
+                    ),
+                ));
+            }
+        }
+        if self.chain.quorum == 0 || self.chain.quorum > self.chain.backends.len() {
+            return Err(ConfigError::InvalidValue(
+                format!("{}chain.quorum", prefix),
+                format!(
+                    "{} must be between 1 and the {} configured backends",
+                    self.chain.quorum,
+                    self.chain.backends.len()
+                ),
+            ));
+        }
+
+        let uses = |kind: &str| self.chain.backends.iter().any(|b| b == kind);
+        if uses("esplora") && self.chain.esplora_url.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "the esplora backend requires {}chain.esplora_url",
+                prefix
+            )));
+        }
+        if uses("electrum") && self.chain.electrum_url.is_none() {
+            return Err(ConfigError::Invalid(format!(
+                "the electrum backend requires {}chain.electrum_url",
+                prefix
+            )));
+        }
+        if (uses("bitcoin-rpc") || !self.rpc.wallets.is_empty())
+            && self.rpc.cookie.is_none()
+            && (self.rpc.user.is_none() || self.rpc.password.is_none())
+        {
+            return Err(ConfigError::Invalid(format!(
+                "bitcoin-rpc requires {0}rpc.cookie or both {0}rpc.user and {0}rpc.password",
+                prefix
+            )));
+        }
+
+        reqwest::Url::parse(&self.prover.url).map_err(|e| {
+            ConfigError::InvalidValue(
+                format!("{}prover.url", prefix),
+                format!("{}: {}", self.prover.url, e),
+            )
+        })?;
+
+        for address in &self.signing.allowed_destinations {
+            parse_address(address, network).map_err(|e| {
+                ConfigError::InvalidValue(
+                    format!("{}signing.allowed_destinations", prefix),
+                    e.to_string(),
+                )
+            })?;
+        }
+        Ok(())
+    }
+}
@@ -6,18 +6,38 @@ use axum::{
 use serde_json::json;
 use thiserror::Error;
 
+use crate::models::PolicyViolation;
+
 #[derive(Error, Debug)]
 pub enum WalletError {
     #[error("Bitcoin error: {0}")]
     BitcoinError(String),
+    #[error("Invalid request: {0}")]
+    InvalidRequest(String),
     #[error("Invalid address: {0}")]
     InvalidAddress(String),
     #[error("Network error: {0}")]
     NetworkError(String),
+    #[error("Wrong network: {0}")]
+    WrongNetwork(String),
     #[error("Invalid spell: {0}")]
     InvalidSpell(String),
     #[error("Spell error: {0}")]
     SpellError(String),
+    #[error("Insufficient funds: {0}")]
+    InsufficientFunds(String),
+    #[error("Rejected by policy: {}", messages(.0))]
+    PolicyRejected(Vec<PolicyViolation>),
+    #[error("Signing refused: {}", messages(.0))]
+    SigningRefused(Vec<PolicyViolation>),
+}
+
+fn messages(violations: &[PolicyViolation]) -> String {
+    violations
+        .iter()
+        .map(|v| v.message.as_str())
+        .collect::<Vec<_>>()
+        .join("; ")
 }
 
 pub type WalletResult<T> = Result<T, WalletError>;
@@ -26,10 +46,27 @@ impl IntoResponse for WalletError {
     fn into_response(self) -> Response {
         let (status, err_msg) = match self {
             WalletError::BitcoinError(msg) => (StatusCode::BAD_REQUEST, msg),
+            WalletError::InvalidRequest(msg) => (StatusCode::BAD_REQUEST, msg),
             WalletError::InvalidAddress(msg) => (StatusCode::BAD_REQUEST, msg),
             WalletError::NetworkError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            WalletError::WrongNetwork(msg) => (StatusCode::BAD_REQUEST, msg),
             WalletError::InvalidSpell(msg) => (StatusCode::BAD_REQUEST, msg),
             WalletError::SpellError(msg) => (StatusCode::INTERNAL_SERVER_ERROR, msg),
+            WalletError::InsufficientFunds(msg) => (StatusCode::BAD_REQUEST, msg),
+            WalletError::PolicyRejected(violations) => {
+                let body = Json(json!({
+                    "error": messages(&violations),
+                    "reasons": violations
+                }));
+                return (StatusCode::BAD_REQUEST, body).into_response();
+            }
+            WalletError::SigningRefused(violations) => {
+                let body = Json(json!({
+                    "error": messages(&violations),
+                    "reasons": violations
+                }));
+                return (StatusCode::FORBIDDEN, body).into_response();
+            }
         };
 
         let body = Json(json!({
@@ -39,3 +76,28 @@ impl IntoResponse for WalletError {
         (status, body).into_response()
     }
 }
+
+impl From<crate::services::bitcoin_cli::BitcoinCliError> for WalletError {
+    fn from(err: crate::services::bitcoin_cli::BitcoinCliError) -> Self {
+        use crate::services::bitcoin_cli::{
+            BitcoinCliError, RPC_CLIENT_NOT_CONNECTED, RPC_IN_WARMUP,
+        };
+
+        match err {
+            BitcoinCliError::Rpc { code, .. }
+                if code == RPC_IN_WARMUP || code == RPC_CLIENT_NOT_CONNECTED =>
+            {
+                WalletError::NetworkError(err.to_string())
+            }
+            BitcoinCliError::Rpc { .. } => WalletError::BitcoinError(err.to_string()),
+            BitcoinCliError::Other(msg) => WalletError::BitcoinError(msg),
+            other => WalletError::NetworkError(other.to_string()),
+        }
+    }
+}
+
+impl From<axum::extract::rejection::JsonRejection> for WalletError {
+    fn from(rejection: axum::extract::rejection::JsonRejection) -> Self {
+        WalletError::InvalidRequest(rejection.body_text())
+    }
+}
@@ -0,0 +1,50 @@
+use axum::{
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use http::header;
+use serde_json::json;
+use std::sync::Arc;
+
+use crate::config::Config;
+
+/// API key a request was authenticated with, available to handlers as an
+/// extension
+#[derive(Debug, Clone)]
+pub struct ApiKey(pub String);
+
+// Reject requests without one of the configured API keys, sent either as
+// `Authorization: Bearer <key>` or `X-API-Key: <key>`
+pub async fn require_api_key(
+    State(config): State<Arc<Config>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let keys = &config.auth.api_keys;
+    if keys.is_empty() || request.uri().path() == "/health" {
+        return next.run(request).await;
+    }
+
+    let headers = request.headers();
+    let presented = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "))
+        .or_else(|| headers.get("x-api-key").and_then(|v| v.to_str().ok()))
+        .map(str::to_string);
+
+    match presented {
+        Some(key) if keys.contains(&key) => {
+            request.extensions_mut().insert(ApiKey(key));
+            next.run(request).await
+        }
+        _ => (
+            StatusCode::UNAUTHORIZED,
+            Json(json!({ "error": "Missing or invalid API key" })),
+        )
+            .into_response(),
+    }
+}
@@ -1,12 +1,17 @@
 use crate::{
+    handlers::ApiJson,
     models::{BroadcastTxRequest, BroadcastTxResponse},
-    services::bitcoin_cli,
+    services::broadcast,
+    state::AppState,
 };
-use axum::{response::IntoResponse, Json};
+use axum::{extract::State, response::IntoResponse, Json};
 
-pub async fn broadcast_btc_tx(Json(payload): Json<BroadcastTxRequest>) -> impl IntoResponse {
+pub async fn broadcast_btc_tx(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<BroadcastTxRequest>,
+) -> impl IntoResponse {
     // This handler assumes the transaction is already signed
-    match bitcoin_cli::send_raw_transaction(&payload) {
+    match broadcast::send_raw_transaction(state.chain.as_ref(), &payload).await {
         Ok(result) => Json::<BroadcastTxResponse>(result).into_response(),
         Err(e) => e.into_response(),
     }
@@ -0,0 +1,17 @@
+use crate::{
+    handlers::ApiJson,
+    models::{BumpFeeRequest, BumpFeeResponse},
+    services::rbf,
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+
+pub async fn bumpfee(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<BumpFeeRequest>,
+) -> impl IntoResponse {
+    match rbf::bump_fee(state.chain.as_ref(), state.network, &payload).await {
+        Ok(result) => Json::<BumpFeeResponse>(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
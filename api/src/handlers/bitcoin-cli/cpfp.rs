@@ -0,0 +1,32 @@
+use crate::{
+    handlers::ApiJson,
+    models::{BroadcastTxResponse, CpfpRequest, CpfpResponse, CpfpSubmitRequest},
+    services::{address::parse_address, cpfp},
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+
+pub async fn cpfp(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<CpfpRequest>,
+) -> impl IntoResponse {
+    let address = match parse_address(&payload.address, state.network) {
+        Ok(address) => address,
+        Err(e) => return e.into_response(),
+    };
+
+    match cpfp::plan(state.chain.as_ref(), state.network, &address, &payload).await {
+        Ok(result) => Json::<CpfpResponse>(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+pub async fn cpfp_submit(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<CpfpSubmitRequest>,
+) -> impl IntoResponse {
+    match cpfp::submit(state.chain.as_ref(), &payload.tx_hex).await {
+        Ok(result) => Json::<BroadcastTxResponse>(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
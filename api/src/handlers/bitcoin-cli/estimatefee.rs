@@ -1,37 +1,113 @@
-use crate::models::FeeEstimateResponse;
-use axum::{response::IntoResponse, Json};
+use crate::error::WalletError;
+use crate::handlers::ApiJson;
+use crate::services::fees;
+use crate::services::vsize::{self, InputType, OutputType};
+use crate::state::AppState;
+use axum::{extract::State, response::IntoResponse, Json};
 use serde::Deserialize;
 use serde_json::json;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
 pub struct EstimateFeeRequest {
-    input_count: Option<u32>,
-    output_count: Option<u32>,
-    fee_rate: Option<f64>,
+    /// Confirmation targets in blocks; defaults to 1, 3, 6 and 144
+    targets: Option<Vec<u16>>,
+    /// Inputs and outputs of a transaction to price; both or neither
+    inputs: Option<Vec<InputType>>,
+    outputs: Option<Vec<OutputType>>,
 }
 
-pub async fn estimatefee(Json(request): Json<EstimateFeeRequest>) -> impl IntoResponse {
-    // Set default values
-    let input_count = request.input_count.unwrap_or(1);
-    let output_count = request.output_count.unwrap_or(2);
-    let fee_rate = request.fee_rate.unwrap_or(1.0);
+pub async fn estimatefee(
+    State(state): State<AppState>,
+    ApiJson(request): ApiJson<EstimateFeeRequest>,
+) -> impl IntoResponse {
+    let targets = request
+        .targets
+        .filter(|targets| !targets.is_empty())
+        .unwrap_or_else(|| fees::DEFAULT_TARGETS.to_vec());
 
-    // Calculate transaction size
-    let estimated_size = (input_count * 148) + (output_count * 34) + 10;
+    if let Some(target) = targets.iter().find(|t| !(1..=1008).contains(*t)) {
+        return WalletError::InvalidRequest(format!(
+            "Confirmation target {} must be between 1 and 1008 blocks",
+            target
+        ))
+        .into_response();
+    }
 
-    // Calculate fee
-    let fee = (estimated_size as f64 * fee_rate) as u64;
-
-    // Generate fee options
-    let response = FeeEstimateResponse {
-        fast: (fee as f64 * 1.5) as u64,
-        medium: fee,
-        slow: (fee as f64 * 0.5) as u64,
+    let size = match (&request.inputs, &request.outputs) {
+        (Some(inputs), Some(outputs)) => Some(vsize::estimate_tx_size(inputs, outputs)),
+        (None, None) => None,
+        _ => {
+            return WalletError::InvalidRequest(
+                "inputs and outputs must be given together".to_string(),
+            )
+            .into_response()
+        }
     };
 
+    let response = fees::estimate_fees(state.chain.as_ref(), &targets, size).await;
+
     Json(json!({
         "status": "success",
         "fee_estimate": response
     }))
     .into_response()
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, post, NETWORK};
+    use crate::services::chain::MemoryBackend;
+    use axum::http::StatusCode;
+    use std::sync::Arc;
+
+    const URI: &str = "/bitcoin-cli/transaction/estimate-fee";
+
+    #[tokio::test]
+    async fn applies_the_policy_to_the_estimator() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        chain.set_fee_rate(Some(10.0));
+
+        let (status, _, body) = post(app(chain), URI, r#"{"targets": [6, 1, 6]}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        let estimates = body["fee_estimate"]["estimates"].as_array().unwrap();
+        assert_eq!(estimates.len(), 2);
+        assert_eq!(estimates[0]["target_blocks"], 1);
+        assert_eq!(estimates[0]["source"], "estimator");
+        assert_eq!(estimates[0]["raw_fee_rate"], 10.0);
+        assert_eq!(estimates[0]["fee_rate"], 11.0);
+    }
+
+    #[tokio::test]
+    async fn falls_back_to_an_empty_mempool() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        chain.set_fee_rate(None);
+
+        let (_, _, body) = post(app(chain), URI, "{}").await;
+        let estimates = body["fee_estimate"]["estimates"].as_array().unwrap();
+        assert_eq!(estimates.len(), 4);
+        assert!(estimates.iter().all(|e| e["source"] == "mempool"));
+        assert!(estimates.iter().all(|e| e["fee_rate"] == 2.5));
+    }
+
+    #[tokio::test]
+    async fn rejects_out_of_range_targets() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+
+        let (status, _, body) = post(app(chain), URI, r#"{"targets": [0]}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("between 1 and 1008"));
+    }
+
+    #[tokio::test]
+    async fn requires_inputs_and_outputs_together() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+
+        let (status, _, body) = post(app(chain), URI, r#"{"inputs": [{"type": "p2wpkh"}]}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "inputs and outputs must be given together");
+    }
+}
@@ -1,11 +1,13 @@
-use crate::services::bitcoin_cli;
+use crate::{error::WalletError, state::AppState};
 use axum::{
-    extract::{Path, Query},
+    extract::{Path, Query, State},
     response::IntoResponse,
     Json,
 };
+use bitcoin::Txid;
 use serde::{Deserialize, Serialize};
 use serde_json::json;
+use std::str::FromStr;
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct GetRawTransactionParams {
@@ -13,12 +15,18 @@ pub struct GetRawTransactionParams {
 }
 
 pub async fn getrawtransaction(
+    State(state): State<AppState>,
     Path(txid): Path<String>,
     Query(params): Query<GetRawTransactionParams>,
 ) -> impl IntoResponse {
     let verbose = params.verbose.unwrap_or(true);
 
-    match bitcoin_cli::get_raw_transaction(&txid, verbose).await {
+    let result = match Txid::from_str(&txid) {
+        Ok(txid) => state.chain.get_raw_transaction(&txid, verbose).await,
+        Err(e) => Err(WalletError::BitcoinError(format!("Invalid txid: {}", e))),
+    };
+
+    match result {
         Ok(tx_info) => Json(json!({
             "status": "success",
             "transaction": tx_info
@@ -31,3 +39,45 @@ pub async fn getrawtransaction(
         .into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, get, NETWORK};
+    use crate::services::chain::{memory::address, MemoryBackend};
+    use axum::http::StatusCode;
+    use bitcoin::Amount;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn returns_verbose_transaction_by_default() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let outpoint = chain.fund(&address(1, NETWORK), Amount::from_sat(10_000), Some(100));
+
+        let uri = format!("/bitcoin-cli/transaction/raw/{}", outpoint.txid);
+        let (status, _, body) = get(app(chain.clone()), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "success");
+        assert_eq!(body["transaction"]["txid"], outpoint.txid.to_string());
+        assert_eq!(body["transaction"]["confirmations"], 1);
+
+        let uri = format!(
+            "/bitcoin-cli/transaction/raw/{}?verbose=false",
+            outpoint.txid
+        );
+        let (_, _, body) = get(app(chain), &uri).await;
+        assert!(body["transaction"].is_string());
+    }
+
+    #[tokio::test]
+    async fn reports_unknown_transactions_as_errors() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+
+        let uri = format!("/bitcoin-cli/transaction/raw/{}", "00".repeat(32));
+        let (_, _, body) = get(app(chain.clone()), &uri).await;
+        assert_eq!(body["status"], "error");
+
+        let (_, _, body) = get(app(chain), "/bitcoin-cli/transaction/raw/nope").await;
+        assert_eq!(body["status"], "error");
+        assert!(body["message"].as_str().unwrap().contains("Invalid txid"));
+    }
+}
@@ -1,9 +1,28 @@
-use crate::services::bitcoin_cli;
-use axum::{extract::Path, response::IntoResponse, Json};
+use crate::{error::WalletError, models::WalletQuery, state::AppState};
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
+use bitcoin::Txid;
 use serde_json::json;
+use std::str::FromStr;
 
-pub async fn gettransaction(Path(txid): Path<String>) -> impl IntoResponse {
-    match bitcoin_cli::get_transaction(&txid).await {
+pub async fn gettransaction(
+    State(state): State<AppState>,
+    Path(txid): Path<String>,
+    Query(query): Query<WalletQuery>,
+) -> impl IntoResponse {
+    let result = match (
+        Txid::from_str(&txid),
+        state.chain_for(query.wallet.as_deref()),
+    ) {
+        (Ok(txid), Ok(chain)) => chain.get_transaction_status(&txid).await,
+        (Err(e), _) => Err(WalletError::BitcoinError(format!("Invalid txid: {}", e))),
+        (_, Err(e)) => Err(e),
+    };
+
+    match result {
         Ok(tx_info) => Json(json!({
             "status": "success",
             "transaction": tx_info
@@ -16,3 +35,38 @@ pub async fn gettransaction(Path(txid): Path<String>) -> impl IntoResponse {
         .into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, get, NETWORK};
+    use crate::services::chain::{memory::address, MemoryBackend};
+    use bitcoin::Amount;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn confirmations_follow_the_tip() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let outpoint = chain.fund(&address(1, NETWORK), Amount::from_sat(10_000), Some(100));
+        chain.set_tip_height(102);
+
+        let uri = format!("/bitcoin-cli/transaction/status/{}", outpoint.txid);
+        let (_, _, body) = get(app(chain), &uri).await;
+        assert_eq!(body["status"], "success");
+        assert_eq!(body["transaction"]["confirmed"], true);
+        assert_eq!(body["transaction"]["confirmations"], 3);
+    }
+
+    #[tokio::test]
+    async fn rejects_an_unknown_wallet() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let outpoint = chain.fund(&address(1, NETWORK), Amount::from_sat(10_000), None);
+
+        let uri = format!(
+            "/bitcoin-cli/transaction/status/{}?wallet=missing",
+            outpoint.txid
+        );
+        let (_, _, body) = get(app(chain), &uri).await;
+        assert_eq!(body["status"], "error");
+        assert!(body["message"].as_str().unwrap().contains("Unknown wallet"));
+    }
+}
@@ -1,10 +1,82 @@
-use crate::models::Utxo;
-use crate::services::bitcoin_cli;
-use axum::{extract::Path, response::IntoResponse, Json};
+use crate::models::{Utxo, UtxoQuery};
+use crate::services::address::parse_address;
+use crate::services::utxos;
+use crate::state::AppState;
+use axum::{
+    extract::{Path, Query, State},
+    response::IntoResponse,
+    Json,
+};
 
-pub async fn listunspent(Path(address): Path<String>) -> impl IntoResponse {
-    match bitcoin_cli::list_unspent(Some(&address)).await {
-        Ok(utxos) => Json::<Vec<Utxo>>(utxos).into_response(),
+// Total before paging, so clients know when they have seen every page
+const TOTAL_COUNT_HEADER: &str = "x-total-count";
+
+pub async fn listunspent(
+    State(state): State<AppState>,
+    Path(address): Path<String>,
+    Query(query): Query<UtxoQuery>,
+) -> impl IntoResponse {
+    let address = match parse_address(&address, state.network) {
+        Ok(address) => address,
+        Err(e) => return e.into_response(),
+    };
+
+    let result = match state.chain_for(query.wallet.as_deref()) {
+        Ok(chain) => utxos::list_unspent(chain.as_ref(), &address, &query).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok((utxos, total)) => (
+            [(TOTAL_COUNT_HEADER, total.to_string())],
+            Json::<Vec<Utxo>>(utxos),
+        )
+            .into_response(),
         Err(e) => e.into_response(),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, get, NETWORK};
+    use crate::services::chain::{memory::address, MemoryBackend};
+    use axum::http::StatusCode;
+    use bitcoin::Amount;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn filters_by_confirmations_and_pages_oldest_first() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let address = address(1, NETWORK);
+        let old = chain.fund(&address, Amount::from_sat(10_000), Some(100));
+        let recent = chain.fund(&address, Amount::from_sat(20_000), Some(105));
+        chain.fund(&address, Amount::from_sat(30_000), None);
+        chain.set_tip_height(110);
+
+        let uri = format!("/bitcoin-cli/utxos/{}?min_conf=1", address);
+        let (status, headers, body) = get(app(chain.clone()), &uri).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(headers["x-total-count"], "2");
+        assert_eq!(body[0]["txid"], old.txid.to_string());
+        assert_eq!(body[0]["confirmations"], 11);
+        assert_eq!(body[1]["txid"], recent.txid.to_string());
+        assert_eq!(body[1]["confirmations"], 6);
+
+        let uri = format!("/bitcoin-cli/utxos/{}?max_conf=6&limit=1", address);
+        let (_, headers, body) = get(app(chain), &uri).await;
+        assert_eq!(headers["x-total-count"], "2");
+        assert_eq!(body.as_array().unwrap().len(), 1);
+        assert_eq!(body[0]["txid"], recent.txid.to_string());
+    }
+
+    #[tokio::test]
+    async fn rejects_an_address_of_another_network() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let mainnet = address(1, bitcoin::Network::Bitcoin);
+
+        let uri = format!("/bitcoin-cli/utxos/{}", mainnet);
+        let (status, _, body) = get(app(chain), &uri).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+    }
+}
@@ -0,0 +1,111 @@
+use crate::handlers::ApiJson;
+use crate::models::{UtxoBatchRequest, UtxoBatchResponse, WalletQuery};
+use crate::services::utxos;
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+
+pub async fn listunspent_batch(
+    State(state): State<AppState>,
+    Query(query): Query<WalletQuery>,
+    ApiJson(payload): ApiJson<UtxoBatchRequest>,
+) -> impl IntoResponse {
+    let result = match state.chain_for(query.wallet.as_deref()) {
+        Ok(chain) => utxos::list_unspent_batch(chain.as_ref(), &payload, state.network).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(response) => Json::<UtxoBatchResponse>(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, post, NETWORK};
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use crate::services::descriptor::KeyDescriptor;
+    use axum::http::StatusCode;
+    use bitcoin::bip32::{Xpriv, Xpub};
+    use bitcoin::secp256k1::Secp256k1;
+    use bitcoin::{Address, Amount, TxOut};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const URI: &str = "/bitcoin-cli/utxos";
+
+    fn tpub() -> String {
+        let xpriv = Xpriv::new_master(NETWORK, &[7; 32]).unwrap();
+        Xpub::from_priv(&Secp256k1::new(), &xpriv).to_string()
+    }
+
+    fn receive_address(tpub: &str, index: u32) -> Address {
+        let descriptor = KeyDescriptor::from_xpub(tpub, None, NETWORK).unwrap();
+        descriptor.addresses(0, index..index + 1).unwrap().remove(0)
+    }
+
+    // Pay `address` and spend the payment again, leaving it empty
+    fn empty(chain: &MemoryBackend, paid: &Address) {
+        let outpoint = chain.fund(paid, Amount::from_sat(10_000), Some(1));
+        let spender = spending_tx(
+            &[outpoint],
+            vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: address(9, NETWORK).script_pubkey(),
+            }],
+        );
+        chain.insert_transaction(spender, Some(2));
+    }
+
+    #[tokio::test]
+    async fn counts_emptied_addresses_as_used() {
+        let tpub = tpub();
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        empty(&chain, &receive_address(&tpub, 0));
+        chain.fund(
+            &receive_address(&tpub, 3),
+            Amount::from_sat(20_000),
+            Some(1),
+        );
+
+        let body = json!({ "xpub": tpub, "gap_limit": 5 });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["addresses"].as_array().unwrap().len(), 1);
+        assert_eq!(body["addresses"][0]["index"], 3);
+        assert_eq!(body["addresses"][0]["keychain"], "receive");
+        assert_eq!(body["next_receive_index"], 4);
+        assert_eq!(body["next_change_index"], 0);
+        assert!(body.get("next_index_error").is_none());
+    }
+
+    #[tokio::test]
+    async fn gives_no_next_index_without_history() {
+        let tpub = tpub();
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        chain.hide_history();
+        empty(&chain, &receive_address(&tpub, 0));
+        // Far past the gap limit, but found all the same
+        chain.fund(
+            &receive_address(&tpub, 30),
+            Amount::from_sat(20_000),
+            Some(1),
+        );
+
+        // One keychain, as without history it is derived in full
+        let body = json!({ "descriptor": format!("wpkh({}/0/*)", tpub), "gap_limit": 5 });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["addresses"].as_array().unwrap().len(), 1);
+        assert_eq!(body["addresses"][0]["index"], 30);
+        assert!(body.get("next_receive_index").is_none());
+        assert!(body["next_index_error"].is_string());
+    }
+}
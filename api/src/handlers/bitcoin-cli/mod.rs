@@ -1,17 +1,25 @@
 mod broadcast_btc_tx;
+mod bumpfee;
+mod cpfp;
 mod estimatefee;
 mod getrawtransaction;
 mod gettransaction;
 mod listunspent;
+mod listunspent_batch;
 mod sendrawtransaction;
 mod sendrawtransactionbroadcast;
 mod submitpackagebroadcast;
+mod verify_utxos;
 
 pub use broadcast_btc_tx::broadcast_btc_tx;
+pub use bumpfee::bumpfee;
+pub use cpfp::{cpfp, cpfp_submit};
 pub use estimatefee::estimatefee;
 pub use getrawtransaction::getrawtransaction;
 pub use gettransaction::gettransaction;
 pub use listunspent::listunspent;
+pub use listunspent_batch::listunspent_batch;
 pub use sendrawtransaction::sendrawtransaction;
 pub use sendrawtransactionbroadcast::sendrawtransactionbroadcast;
 pub use submitpackagebroadcast::submitpackagebroadcast;
+pub use verify_utxos::verify_utxos;
@@ -1,11 +1,39 @@
 use crate::{
+    handlers::ApiJson,
+    handlers::ApiKey,
     models::{BroadcastTxResponse, SendHexTxRequest},
-    services::bitcoin_cli,
+    services::broadcast,
+    state::AppState,
 };
-use axum::{response::IntoResponse, Json};
+use axum::{
+    extract::{Extension, State},
+    response::IntoResponse,
+    Json,
+};
+
+pub async fn sendrawtransaction(
+    State(state): State<AppState>,
+    api_key: Option<Extension<ApiKey>>,
+    ApiJson(payload): ApiJson<SendHexTxRequest>,
+) -> impl IntoResponse {
+    if let Err(e) = state.check_network(&payload.network) {
+        return e.into_response();
+    }
+
+    let chain = match state.chain_for(payload.wallet.as_deref()) {
+        Ok(chain) => chain,
+        Err(e) => return e.into_response(),
+    };
 
-pub async fn sendrawtransaction(Json(payload): Json<SendHexTxRequest>) -> impl IntoResponse {
-    match bitcoin_cli::sign_and_broadcast_transaction(&payload) {
+    let api_key = api_key.as_ref().map(|Extension(ApiKey(key))| key.as_str());
+    match broadcast::sign_and_broadcast_transaction(
+        chain.as_ref(),
+        &state.signing,
+        api_key,
+        &payload,
+    )
+    .await
+    {
         Ok(result) => Json::<BroadcastTxResponse>(result).into_response(),
         Err(e) => e.into_response(),
     }
@@ -1,11 +1,16 @@
 use crate::{
+    handlers::ApiJson,
     models::{BroadcastTxRequest, BroadcastTxResponse},
-    services::bitcoin_cli,
+    services::broadcast,
+    state::AppState,
 };
-use axum::{response::IntoResponse, Json};
+use axum::{extract::State, response::IntoResponse, Json};
 
-pub async fn submitpackagebroadcast(Json(payload): Json<BroadcastTxRequest>) -> impl IntoResponse {
-    match bitcoin_cli::submit_package(&payload) {
+pub async fn submitpackagebroadcast(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<BroadcastTxRequest>,
+) -> impl IntoResponse {
+    match broadcast::send_raw_transaction(state.chain.as_ref(), &payload).await {
         Ok(result) => Json::<BroadcastTxResponse>(result).into_response(),
         Err(e) => e.into_response(),
     }
@@ -0,0 +1,84 @@
+use crate::handlers::ApiJson;
+use crate::models::{UtxoVerifyRequest, UtxoVerifyResponse, WalletQuery};
+use crate::services::utxos;
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+
+pub async fn verify_utxos(
+    State(state): State<AppState>,
+    Query(query): Query<WalletQuery>,
+    ApiJson(payload): ApiJson<UtxoVerifyRequest>,
+) -> impl IntoResponse {
+    let result = match state.chain_for(query.wallet.as_deref()) {
+        Ok(chain) => utxos::verify(chain.as_ref(), &payload.outpoints).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(response) => Json::<UtxoVerifyResponse>(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, post, NETWORK};
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use axum::http::StatusCode;
+    use bitcoin::{Amount, OutPoint, TxOut};
+    use serde_json::json;
+    use std::sync::Arc;
+
+    const URI: &str = "/bitcoin-cli/utxos/verify";
+
+    #[tokio::test]
+    async fn reports_each_outpoint_in_order() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let address = address(1, NETWORK);
+        let unspent = chain.fund(&address, Amount::from_sat(10_000), Some(100));
+        let spent = chain.fund(&address, Amount::from_sat(20_000), Some(100));
+        let spender = spending_tx(
+            &[spent],
+            vec![TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: address.script_pubkey(),
+            }],
+        );
+        chain.insert_transaction(spender.clone(), None);
+
+        let unknown = OutPoint::new(unspent.txid, 1);
+
+        let body =
+            json!({ "outpoints": [spent.to_string(), unspent.to_string(), unknown.to_string()] });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["outpoints"][0]["status"], "spent_in_mempool");
+        assert_eq!(
+            body["outpoints"][0]["spending_txid"],
+            spender.compute_txid().to_string()
+        );
+        assert_eq!(body["outpoints"][1]["outpoint"], unspent.to_string());
+        assert_eq!(body["outpoints"][1]["status"], "unspent");
+        assert_eq!(body["outpoints"][2]["status"], "not_found");
+    }
+
+    #[tokio::test]
+    async fn rejects_malformed_requests() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+
+        let (status, _, body) = post(app(chain.clone()), URI, r#"{"outpoints": ["x"]}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+
+        let (status, _, body) = post(app(chain), URI, "{").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+    }
+}
@@ -1,15 +1,20 @@
-use axum::{extract::Path, http::StatusCode, Json};
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
 use bitcoin::Txid;
-use bitcoincore_rpc::RpcApi;
 use serde_json::{json, Value};
 use std::str::FromStr;
 use tracing::{error, info};
 
 use crate::services::bitcoin_rpc;
+use crate::state::AppState;
 
 /// Get previous transactions for a transaction ID
 #[axum::debug_handler]
 pub async fn get_prev_txs(
+    State(state): State<AppState>,
     Path(txid): Path<String>,
 ) -> Result<Json<Vec<String>>, (StatusCode, Json<Value>)> {
     info!("Getting previous transactions for txid: {}", txid);
@@ -29,23 +34,8 @@ pub async fn get_prev_txs(
         }
     };
 
-    // Get RPC client
-    let client = match bitcoin_rpc::get_rpc_client() {
-        Ok(client) => client,
-        Err(e) => {
-            error!("Failed to get RPC client: {}", e);
-            return Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(json!({
-                    "status": "error",
-                    "message": "Failed to connect to Bitcoin node"
-                })),
-            ));
-        }
-    };
-
     // Get transaction
-    let tx = match client.get_raw_transaction(&tx_id, None) {
+    let tx = match state.chain.get_transaction(&tx_id).await {
         Ok(tx) => tx,
         Err(e) => {
             error!("Transaction not found: {}", e);
@@ -60,7 +50,7 @@ pub async fn get_prev_txs(
     };
 
     // Get previous transactions
-    let prev_txs = match bitcoin_rpc::get_prev_txs(&tx) {
+    let prev_txs = match bitcoin_rpc::get_prev_txs(state.chain.as_ref(), &tx).await {
         Ok(txs) => txs,
         Err(e) => {
             error!("Failed to get previous transactions: {}", e);
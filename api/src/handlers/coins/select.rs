@@ -0,0 +1,132 @@
+use crate::handlers::ApiJson;
+use crate::models::{CoinSelectRequest, CoinSelectResponse, WalletQuery};
+use crate::services::coin_selection;
+use crate::state::AppState;
+use axum::{
+    extract::{Query, State},
+    response::IntoResponse,
+    Json,
+};
+
+pub async fn select_coins(
+    State(state): State<AppState>,
+    Query(query): Query<WalletQuery>,
+    ApiJson(payload): ApiJson<CoinSelectRequest>,
+) -> impl IntoResponse {
+    let result = match state.chain_for(query.wallet.as_deref()) {
+        Ok(chain) => coin_selection::select(chain.as_ref(), payload, state.network).await,
+        Err(e) => Err(e),
+    };
+
+    match result {
+        Ok(response) => Json::<CoinSelectResponse>(response).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, post, NETWORK};
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use axum::http::StatusCode;
+    use bitcoin::{
+        opcodes::{all::OP_ENDIF, all::OP_IF, OP_FALSE},
+        script::Builder,
+        Amount, OutPoint, TxOut, Witness,
+    };
+    use serde_json::{json, Value};
+    use std::sync::Arc;
+
+    const URI: &str = "/coins/select";
+
+    #[tokio::test]
+    async fn funds_the_target_from_address_utxos() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let address = address(1, NETWORK);
+        chain.fund(&address, Amount::from_sat(50_000), Some(100));
+        chain.fund(&address, Amount::from_sat(80_000), Some(100));
+
+        let body = json!({
+            "target": 100_000,
+            "fee_rate": 2.0,
+            "addresses": [address.to_string()],
+            "change_type": "wpkh",
+        });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["inputs"].as_array().unwrap().len(), 2);
+        let fee = body["fee"].as_u64().unwrap();
+        let change = body["change"].as_u64().unwrap_or(0);
+        assert_eq!(130_000 - 100_000, fee + change);
+    }
+
+    #[tokio::test]
+    async fn reports_insufficient_funds() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let address = address(1, NETWORK);
+        chain.fund(&address, Amount::from_sat(50_000), Some(100));
+
+        let body = json!({
+            "target": 100_000,
+            "fee_rate": 2.0,
+            "addresses": [address.to_string()],
+            "change_type": "wpkh",
+        });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].is_string());
+    }
+
+    fn utxo(outpoint: OutPoint, value: u64) -> Value {
+        json!({
+            "txid": outpoint.txid,
+            "vout": outpoint.vout,
+            "value": value,
+            "script_pubkey": address(1, NETWORK).script_pubkey(),
+        })
+    }
+
+    #[tokio::test]
+    async fn leaves_out_inscribed_and_unchecked_utxos() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let plain = chain.fund(&address(1, NETWORK), Amount::from_sat(50_000), Some(100));
+
+        let envelope = Builder::new()
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let funding = chain.fund(&address(2, NETWORK), Amount::from_sat(90_000), Some(100));
+        let mut reveal = spending_tx(
+            &[funding],
+            vec![TxOut {
+                value: Amount::from_sat(80_000),
+                script_pubkey: address(1, NETWORK).script_pubkey(),
+            }],
+        );
+        reveal.input[0].witness =
+            Witness::from_slice(&[vec![0; 64], envelope.to_bytes(), vec![0xc0; 33]]);
+        chain.insert_transaction(reveal.clone(), Some(101));
+        let inscribed = OutPoint::new(reveal.compute_txid(), 0);
+        // Its transaction has no such output
+        let unknown = OutPoint::new(plain.txid, 7);
+
+        let body = json!({
+            "target": 20_000,
+            "fee_rate": 2.0,
+            "utxos": [utxo(inscribed, 80_000), utxo(unknown, 90_000), utxo(plain, 50_000)],
+            "change_type": "wpkh",
+        });
+        let (status, _, body) = post(app(chain), URI, &body.to_string()).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["inputs"].as_array().unwrap().len(), 1);
+        assert_eq!(body["inputs"][0]["txid"], plain.txid.to_string());
+        assert_eq!(body["excluded"], json!([inscribed.to_string()]));
+        assert_eq!(body["unchecked"], json!([unknown.to_string()]));
+        assert!(body["asset_detection"].is_string());
+    }
+}
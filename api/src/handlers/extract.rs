@@ -0,0 +1,9 @@
+use axum::extract::FromRequest;
+
+use crate::error::WalletError;
+
+/// JSON body that fails like the rest of the API: a body that does not
+/// parse, or fields that do not, are answered with a 400 `{"error": ...}`
+#[derive(FromRequest)]
+#[from_request(via(axum::Json), rejection(WalletError))]
+pub struct ApiJson<T>(pub T);
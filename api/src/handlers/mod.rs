@@ -1,18 +1,38 @@
+mod auth;
 #[path = "bitcoin-cli/mod.rs"]
 mod bitcoin_cli;
 #[path = "bitcoin-rpc/mod.rs"]
 mod bitcoin_rpc;
+mod coins;
+mod extract;
 mod health;
+mod psbt;
 mod spell;
+#[cfg(test)]
+mod testing;
 
+pub use auth::{require_api_key, ApiKey};
 pub use bitcoin_cli::broadcast_btc_tx;
+pub use bitcoin_cli::bumpfee;
+pub use bitcoin_cli::cpfp;
+pub use bitcoin_cli::cpfp_submit;
 pub use bitcoin_cli::estimatefee;
 pub use bitcoin_cli::getrawtransaction;
 pub use bitcoin_cli::gettransaction;
 pub use bitcoin_cli::listunspent;
+pub use bitcoin_cli::listunspent_batch;
 pub use bitcoin_cli::sendrawtransaction;
 pub use bitcoin_cli::sendrawtransactionbroadcast;
 pub use bitcoin_cli::submitpackagebroadcast;
+pub use bitcoin_cli::verify_utxos;
 pub use bitcoin_rpc::get_prev_txs;
+pub use coins::select_coins;
+pub use extract::ApiJson;
 pub use health::health_check;
-pub use spell::prove_spell;
+pub use psbt::analyze_psbt;
+pub use psbt::broadcast_psbt;
+pub use psbt::combine_psbt;
+pub use psbt::create_psbt;
+pub use psbt::finalize_psbt;
+pub use psbt::update_psbt;
+pub use spell::{prove_spell, prove_spell_psbt};
@@ -0,0 +1,13 @@
+use crate::{
+    handlers::ApiJson,
+    models::{AnalyzePsbtResponse, PsbtRequest},
+    services::psbt,
+};
+use axum::{response::IntoResponse, Json};
+
+pub async fn analyze_psbt(ApiJson(payload): ApiJson<PsbtRequest>) -> impl IntoResponse {
+    match psbt::parse_psbt(&payload.psbt) {
+        Ok(psbt) => Json::<AnalyzePsbtResponse>(psbt::analyze(&psbt)).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
@@ -0,0 +1,22 @@
+use crate::{
+    handlers::ApiJson,
+    models::{BroadcastPsbtRequest, BroadcastTxResponse},
+    services::psbt,
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+
+pub async fn broadcast_psbt(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<BroadcastPsbtRequest>,
+) -> impl IntoResponse {
+    let psbt = match psbt::parse_psbt(&payload.psbt) {
+        Ok(psbt) => psbt,
+        Err(e) => return e.into_response(),
+    };
+
+    match psbt::broadcast(state.chain.as_ref(), &psbt, payload.allow_charm_burn).await {
+        Ok(result) => Json::<BroadcastTxResponse>(result).into_response(),
+        Err(e) => e.into_response(),
+    }
+}
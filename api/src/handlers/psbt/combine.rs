@@ -0,0 +1,16 @@
+use crate::{
+    handlers::ApiJson,
+    models::{CombinePsbtRequest, PsbtResponse},
+    services::psbt,
+};
+use axum::{response::IntoResponse, Json};
+
+pub async fn combine_psbt(ApiJson(payload): ApiJson<CombinePsbtRequest>) -> impl IntoResponse {
+    match psbt::combine(&payload.psbts) {
+        Ok(psbt) => Json(PsbtResponse {
+            psbt: psbt.to_string(),
+        })
+        .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
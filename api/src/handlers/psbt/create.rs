@@ -0,0 +1,42 @@
+use crate::{
+    handlers::ApiJson,
+    models::{CreatePsbtRequest, PsbtResponse},
+    services::psbt,
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+
+pub async fn create_psbt(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<CreatePsbtRequest>,
+) -> impl IntoResponse {
+    match psbt::create(&payload, state.network) {
+        Ok(psbt) => Json(PsbtResponse {
+            psbt: psbt.to_string(),
+        })
+        .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::handlers::testing::{app, post, NETWORK};
+    use crate::services::chain::{memory::address, MemoryBackend};
+    use axum::http::StatusCode;
+    use serde_json::json;
+    use std::sync::Arc;
+
+    #[tokio::test]
+    async fn rejects_a_malformed_txid_as_a_bad_request() {
+        let chain = Arc::new(MemoryBackend::new(NETWORK));
+        let body = json!({
+            "inputs": [{ "txid": "not-a-txid", "vout": 0 }],
+            "outputs": [{ "address": address(1, NETWORK).to_string(), "value": 1_000 }],
+        });
+
+        let (status, _, body) = post(app(chain), "/psbt/create", &body.to_string()).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("txid"));
+    }
+}
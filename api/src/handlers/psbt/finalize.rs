@@ -0,0 +1,28 @@
+use crate::{
+    handlers::ApiJson,
+    models::{FinalizePsbtResponse, PsbtRequest},
+    services::psbt,
+};
+use axum::{response::IntoResponse, Json};
+use bitcoin::consensus::encode::serialize_hex;
+
+pub async fn finalize_psbt(ApiJson(payload): ApiJson<PsbtRequest>) -> impl IntoResponse {
+    let mut psbt = match psbt::parse_psbt(&payload.psbt) {
+        Ok(psbt) => psbt,
+        Err(e) => return e.into_response(),
+    };
+
+    let complete = psbt::finalize(&mut psbt);
+    let tx_hex = if complete {
+        psbt::extract(&psbt).ok().map(|tx| serialize_hex(&tx))
+    } else {
+        None
+    };
+
+    Json(FinalizePsbtResponse {
+        psbt: psbt.to_string(),
+        complete,
+        tx_hex,
+    })
+    .into_response()
+}
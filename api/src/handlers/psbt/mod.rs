@@ -0,0 +1,13 @@
+mod analyze;
+mod broadcast;
+mod combine;
+mod create;
+mod finalize;
+mod update;
+
+pub use analyze::analyze_psbt;
+pub use broadcast::broadcast_psbt;
+pub use combine::combine_psbt;
+pub use create::create_psbt;
+pub use finalize::finalize_psbt;
+pub use update::update_psbt;
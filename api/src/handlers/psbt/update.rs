@@ -0,0 +1,25 @@
+use crate::{
+    handlers::ApiJson,
+    models::{PsbtResponse, UpdatePsbtRequest},
+    services::psbt,
+    state::AppState,
+};
+use axum::{extract::State, response::IntoResponse, Json};
+
+pub async fn update_psbt(
+    State(state): State<AppState>,
+    ApiJson(payload): ApiJson<UpdatePsbtRequest>,
+) -> impl IntoResponse {
+    let mut psbt = match psbt::parse_psbt(&payload.psbt) {
+        Ok(psbt) => psbt,
+        Err(e) => return e.into_response(),
+    };
+
+    match psbt::update(state.chain.as_ref(), &mut psbt, &payload.taproot).await {
+        Ok(()) => Json(PsbtResponse {
+            psbt: psbt.to_string(),
+        })
+        .into_response(),
+        Err(e) => e.into_response(),
+    }
+}
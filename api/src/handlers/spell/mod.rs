@@ -1,3 +1,3 @@
 mod prove;
 
-pub use prove::prove_spell;
+pub use prove::{prove_spell, prove_spell_psbt};
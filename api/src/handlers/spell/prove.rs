@@ -1,15 +1,59 @@
-use axum::{body::Bytes, extract::Json as ExtractJson, http::StatusCode, Json};
+use axum::{
+    body::Bytes,
+    extract::{Json as ExtractJson, State},
+    http::StatusCode,
+    Json,
+};
 use serde_json::Value;
-use tracing::{debug, error, info};
+use tracing::{debug, error, info, warn};
 
-use crate::services::spell::SpellProver;
+use crate::models::ProveSpellResponse;
+use crate::services::spell::{self, SpellProofResult};
+use crate::state::AppState;
+
+type ProveError = (StatusCode, Json<Value>);
 
 #[axum::debug_handler]
-pub async fn prove_spell(body: Bytes) -> Result<Json<Value>, (StatusCode, Json<Value>)> {
+pub async fn prove_spell(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<Value>, ProveError> {
     info!("Received prove_spell request");
 
-    // Initialize SpellProver service
-    let prover = SpellProver::new();
+    // The prover's own response: the commit and spell transactions
+    let proof = prove(&state, body).await?;
+    Ok(Json(serde_json::json!([proof.commit_tx, proof.spell_tx])))
+}
+
+#[axum::debug_handler]
+pub async fn prove_spell_psbt(
+    State(state): State<AppState>,
+    body: Bytes,
+) -> Result<Json<ProveSpellResponse>, ProveError> {
+    info!("Received prove_spell_psbt request");
+
+    let proof = prove(&state, body).await?;
+
+    // A proof is costly, so it is returned even when the PSBTs fail
+    let (commit_psbt, spell_psbt) = match proof.psbts(state.chain.as_ref()).await {
+        Ok((commit_psbt, spell_psbt)) => {
+            (Some(commit_psbt.to_string()), Some(spell_psbt.to_string()))
+        }
+        Err(e) => {
+            warn!("Failed to build PSBTs for the proved spell: {}", e);
+            (None, None)
+        }
+    };
+    Ok(Json(ProveSpellResponse {
+        proof,
+        commit_psbt,
+        spell_psbt,
+    }))
+}
+
+async fn prove(state: &AppState, body: Bytes) -> Result<SpellProofResult, ProveError> {
+    // Prover for the network of this route
+    let prover = &state.prover;
 
     // Convert request body to string
     let payload_str = match String::from_utf8(body.to_vec()) {
@@ -26,33 +70,42 @@ pub async fn prove_spell(body: Bytes) -> Result<Json<Value>, (StatusCode, Json<V
     };
 
     // Validate JSON payload
-    if let Err(e) = serde_json::from_str::<Value>(&payload_str) {
-        error!("Invalid JSON payload: {}", e);
+    let payload = match serde_json::from_str::<Value>(&payload_str) {
+        Ok(payload) => payload,
+        Err(e) => {
+            error!("Invalid JSON payload: {}", e);
+            return Err((
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": format!("Invalid JSON payload: {}", e)
+                })),
+            ));
+        }
+    };
+
+    // Reject malformed fields and addresses of another network before
+    // paying for a proof
+    if let Err(e) = spell::check_payload(&payload, state.network) {
+        error!("Invalid prove payload: {}", e);
         return Err((
             StatusCode::BAD_REQUEST,
             Json(serde_json::json!({
-                "error": format!("Invalid JSON payload: {}", e)
+                "error": e.to_string()
             })),
         ));
     }
 
     // Process spell proof request
-    match prover.prove_spell(payload_str).await {
-        Ok(response) => {
-            // Return success response
-            Ok(Json(response))
-        }
-        Err(err) => {
-            // Log error details
-            error!("Error proving spell: {}", err);
+    prover.prove_spell(payload_str).await.map_err(|err| {
+        // Log error details
+        error!("Error proving spell: {}", err);
 
-            // Return error response
-            Err((
-                StatusCode::INTERNAL_SERVER_ERROR,
-                Json(serde_json::json!({
-                    "error": format!("Failed to prove spell: {}", err)
-                })),
-            ))
-        }
-    }
+        // Return error response
+        (
+            StatusCode::INTERNAL_SERVER_ERROR,
+            Json(serde_json::json!({
+                "error": format!("Failed to prove spell: {}", err)
+            })),
+        )
+    })
 }
@@ -0,0 +1,51 @@
+//! Drives the API routes against a [`MemoryBackend`] in handler tests
+
+use crate::config::Config;
+use crate::services::chain::MemoryBackend;
+use crate::services::signing::SigningPolicy;
+use crate::services::spell::SpellProver;
+use crate::state::AppState;
+use axum::{
+    body::{to_bytes, Body},
+    http::{header, HeaderMap, Request, StatusCode},
+    Router,
+};
+use bitcoin::Network;
+use serde_json::Value;
+use std::sync::Arc;
+use tower::ServiceExt;
+
+pub const NETWORK: Network = Network::Regtest;
+
+/// The API routes on regtest, served by `chain`
+pub fn app(chain: Arc<MemoryBackend>) -> Router {
+    let config = Config::default();
+    let state = AppState::new(
+        NETWORK,
+        chain,
+        SpellProver::new(&config.prover),
+        SigningPolicy::new(&config.signing, NETWORK).unwrap(),
+    );
+    crate::api_routes().with_state(state)
+}
+
+pub async fn get(app: Router, uri: &str) -> (StatusCode, HeaderMap, Value) {
+    send(app, Request::get(uri).body(Body::empty()).unwrap()).await
+}
+
+pub async fn post(app: Router, uri: &str, body: &str) -> (StatusCode, HeaderMap, Value) {
+    let request = Request::post(uri)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    send(app, request).await
+}
+
+async fn send(app: Router, request: Request<Body>) -> (StatusCode, HeaderMap, Value) {
+    let response = app.oneshot(request).await.unwrap();
+    let status = response.status();
+    let headers = response.headers().clone();
+    let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+    let body = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, headers, body)
+}
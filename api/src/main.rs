@@ -1,43 +1,29 @@
 // api/src/main.rs
+mod config;
 mod error;
 mod handlers;
 mod models;
 mod services;
+mod state;
 
 use axum::{
+    extract::DefaultBodyLimit,
+    middleware,
     routing::{get, post},
     Router,
 };
-use http::{header, Method};
-use std::{env, net::SocketAddr, str::FromStr, time::Duration};
-use tower_http::cors::{Any, CorsLayer};
+use config::Config;
+use http::{header, HeaderValue, Method};
+use services::signing::SigningPolicy;
+use services::spell::SpellProver;
+use state::AppState;
+use std::{net::SocketAddr, str::FromStr, sync::Arc, time::Duration};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
 
-fn load_env() {
-    dotenv::dotenv().ok();
-}
-
-#[tokio::main]
-async fn main() {
-    load_env();
-    tracing_subscriber::fmt::init();
-
-    let cors = CorsLayer::new()
-        .allow_origin(Any)
-        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
-        .allow_headers([
-            header::CONTENT_TYPE,
-            header::ACCEPT,
-            header::ORIGIN,
-            header::AUTHORIZATION,
-            header::ACCESS_CONTROL_ALLOW_HEADERS,
-            header::ACCESS_CONTROL_ALLOW_ORIGIN,
-            header::ACCESS_CONTROL_REQUEST_METHOD,
-        ])
-        .expose_headers([header::CONTENT_TYPE, header::CONTENT_LENGTH])
-        .max_age(Duration::from_secs(3600));
-
-    let app = Router::new()
-        .route("/health", get(handlers::health_check))
+// Routes served for each network, under `/{network}` and for the default
+// network also without a prefix
+fn api_routes() -> Router<AppState> {
+    Router::new()
         .route(
             "/bitcoin-cli/wallet/broadcast",
             post(handlers::submitpackagebroadcast),
@@ -62,16 +48,110 @@ async fn main() {
             "/bitcoin-cli/transaction/estimate-fee",
             post(handlers::estimatefee),
         )
+        .route("/bitcoin-cli/transaction/bump-fee", post(handlers::bumpfee))
+        .route("/bitcoin-cli/transaction/cpfp", post(handlers::cpfp))
+        .route(
+            "/bitcoin-cli/transaction/cpfp/submit",
+            post(handlers::cpfp_submit),
+        )
+        .route("/bitcoin-cli/utxos", post(handlers::listunspent_batch))
+        .route("/bitcoin-cli/utxos/verify", post(handlers::verify_utxos))
         .route("/bitcoin-cli/utxos/{address}", get(handlers::listunspent))
         .route("/bitcoin-rpc/prev-txs/{txid}", get(handlers::get_prev_txs))
+        .route("/coins/select", post(handlers::select_coins))
+        .route("/psbt/create", post(handlers::create_psbt))
+        .route("/psbt/analyze", post(handlers::analyze_psbt))
+        .route("/psbt/update", post(handlers::update_psbt))
+        .route("/psbt/combine", post(handlers::combine_psbt))
+        .route("/psbt/finalize", post(handlers::finalize_psbt))
+        .route("/psbt/broadcast", post(handlers::broadcast_psbt))
         .route("/spell/prove", post(handlers::prove_spell))
-        .layer(cors);
+        .route("/spell/prove/psbt", post(handlers::prove_spell_psbt))
+}
+
+fn load_env() {
+    dotenv::dotenv().ok();
+}
+
+#[tokio::main]
+async fn main() {
+    load_env();
+    tracing_subscriber::fmt::init();
+
+    let config = match Config::load() {
+        Ok(config) => Arc::new(config),
+        Err(e) => {
+            tracing::error!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    tracing::info!("Default network is {}", config.network());
+
+    // "*" allows any origin, otherwise only the listed ones
+    let origins = &config.cors.allowed_origins;
+    let allow_origin =
+        if origins.iter().any(|o| o == "*") {
+            AllowOrigin::from(Any)
+        } else {
+            AllowOrigin::list(origins.iter().map(|o| {
+                HeaderValue::from_str(o).expect("origins are checked by Config::validate")
+            }))
+        };
+
+    let cors = CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods([Method::GET, Method::POST, Method::OPTIONS])
+        .allow_headers([
+            header::CONTENT_TYPE,
+            header::ACCEPT,
+            header::ORIGIN,
+            header::AUTHORIZATION,
+            header::HeaderName::from_static("x-api-key"),
+            header::ACCESS_CONTROL_ALLOW_HEADERS,
+            header::ACCESS_CONTROL_ALLOW_ORIGIN,
+            header::ACCESS_CONTROL_REQUEST_METHOD,
+        ])
+        .expose_headers([
+            header::CONTENT_TYPE,
+            header::CONTENT_LENGTH,
+            header::HeaderName::from_static("x-total-count"),
+        ])
+        .max_age(Duration::from_secs(3600));
 
-    let host = env::var("HOST").unwrap_or_else(|_| "0.0.0.0".to_string());
-    let port = env::var("PORT").unwrap_or("3333".to_string());
-    let port: u16 = port.parse().expect("PORT must be a number");
+    let mut app = Router::new().route("/health", get(handlers::health_check));
+    let networks = config
+        .network_configs()
+        .expect("networks are checked by Config::validate");
+    for (i, (network, settings)) in networks.into_iter().enumerate() {
+        let chains = services::chain::from_config(network, &settings, &config.limits)
+            .expect("Failed to configure chain backend");
+        let state = AppState::new(
+            network,
+            chains.default,
+            SpellProver::new(&settings.prover),
+            SigningPolicy::new(&settings.signing, network)
+                .expect("Failed to open signing audit log"),
+        )
+        .with_wallets(chains.wallets);
+
+        // Unprefixed routes keep serving the default network
+        if i == 0 {
+            app = app.merge(api_routes().with_state(state.clone()));
+        }
+        let prefix = format!("/{}", config::network_name(network));
+        tracing::info!("Serving {} under {}", network, prefix);
+        app = app.nest(&prefix, api_routes().with_state(state));
+    }
+
+    let app = app
+        .layer(middleware::from_fn_with_state(
+            config.clone(),
+            handlers::require_api_key,
+        ))
+        .layer(DefaultBodyLimit::max(config.limits.max_body_bytes))
+        .layer(cors);
 
-    let addr = SocketAddr::from_str(&format!("{}:{}", host, port))
+    let addr = SocketAddr::from_str(&format!("{}:{}", config.server.host, config.server.port))
         .expect("Failed to create socket address");
     tracing::info!("Listening on {}", addr);
     axum::serve(tokio::net::TcpListener::bind(&addr).await.unwrap(), app)
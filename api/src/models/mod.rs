@@ -1,16 +1,266 @@
-use serde::{Deserialize, Serialize};
+use crate::services::spell::SpellProofResult;
+use crate::services::vsize::{OutputType, TxSize};
+use bitcoin::address::NetworkUnchecked;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Address, Amount, OutPoint, ScriptBuf, Transaction, Txid};
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct Utxo {
-    pub txid: String,
+    pub txid: Txid,
     pub vout: u32,
-    pub value: u64,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub value: Amount,
+    #[serde(default)]
     pub status: UtxoStatus,
+    /// Hex encoded
+    #[serde(default)]
+    pub script_pubkey: ScriptBuf,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(default)]
+    pub confirmations: u32,
+    /// Output descriptor, known to node wallets only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub desc: Option<String>,
+    #[serde(default)]
+    pub assets: UtxoAssets,
 }
 
-#[derive(Debug, Serialize, Deserialize)]
+impl Utxo {
+    pub fn outpoint(&self) -> OutPoint {
+        OutPoint::new(self.txid, self.vout)
+    }
+}
+
+/// Assets an output carries, as far as the transaction that created it
+/// tells: spending it without carrying them forward destroys them
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct UtxoAssets {
+    pub charms: Vec<CharmAsset>,
+    pub inscriptions: Vec<InscriptionAsset>,
+    /// Runes the runestone assigns to the output by edict
+    pub runes: Vec<RuneAsset>,
+    /// The runestone sends runes no edict assigns, mints and premines
+    /// here; which and how many takes a rune index to know
+    pub unassigned_runes: bool,
+}
+
+impl UtxoAssets {
+    pub fn is_empty(&self) -> bool {
+        self.charms.is_empty() && self.inscriptions.is_empty() && !self.carries_runes()
+    }
+
+    pub fn carries_runes(&self) -> bool {
+        !self.runes.is_empty() || self.unassigned_runes
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CharmAsset {
+    /// `tag/identity/vk`
+    pub app: String,
+    /// Held amount, for tokens
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InscriptionAsset {
+    /// `<reveal txid>i<index>`
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RuneAsset {
+    /// `block:tx`, absent for a rune etched by the same transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// Amount asked for by the edict, as a decimal string since it may
+    /// exceed 2^53; the output gets less if the inputs held less, and all
+    /// that is left when absent
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub amount: Option<String>,
+}
+
+#[derive(Debug, Default, Serialize, Deserialize)]
 pub struct UtxoStatus {
     pub confirmed: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub block_height: Option<u64>,
+}
+
+/// Filters and paging of a UTXO listing
+#[derive(Debug, Deserialize)]
+pub struct UtxoQuery {
+    pub wallet: Option<String>,
+    #[serde(default)]
+    pub min_conf: u32,
+    pub max_conf: Option<u32>,
+    /// Smallest value to list, in sats
+    pub min_value: Option<u64>,
+    /// Leave out outputs below the dust limit of their script
+    #[serde(default)]
+    pub exclude_dust: bool,
+    /// Leave out outputs that carry charms, so they are not spent as fees
+    #[serde(default)]
+    pub exclude_charms: bool,
+    /// UTXOs to skip, oldest first
+    #[serde(default)]
+    pub offset: usize,
+    pub limit: Option<usize>,
+}
+
+/// Script paying each key derived from an extended public key
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ScriptType {
+    Pkh,
+    ShWpkh,
+    Wpkh,
+    /// Key-path only, without a script tree
+    Tr,
+}
+
+/// Addresses to list UTXOs of in one request: a list, or the addresses
+/// derived from an output descriptor or extended public key
+#[derive(Debug, Deserialize)]
+pub struct UtxoBatchRequest {
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    /// Ranged descriptor, e.g. `wpkh([fp/84h/0h/0h]xpub.../<0;1>/*)`
+    pub descriptor: Option<String>,
+    /// Change keychain, for a `descriptor` without a `<0;1>` step
+    pub change_descriptor: Option<String>,
+    /// `xpub`, `tpub` or their SLIP-132 forms, scanned at `/0/*` for
+    /// receive and `/1/*` for change
+    pub xpub: Option<String>,
+    /// Script for `xpub`; defaults to the one its prefix implies, else `wpkh`
+    pub script_type: Option<ScriptType>,
+    /// Unused addresses in a row after which a keychain is done
+    #[serde(default = "default_gap_limit")]
+    pub gap_limit: u32,
+}
+
+fn default_gap_limit() -> u32 {
+    20
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Keychain {
+    Receive,
+    Change,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AddressUtxos {
+    pub address: String,
+    /// Where a derived address sits
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keychain: Option<Keychain>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub index: Option<u32>,
+    pub utxos: Vec<Utxo>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UtxoBatchResponse {
+    /// Every requested address, or the derived ones holding UTXOs
+    pub addresses: Vec<AddressUtxos>,
+    /// First receive index never paid, for descriptors and xpubs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_receive_index: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_change_index: Option<u32>,
+    /// Why a descriptor or xpub scan gave no next indexes
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub next_index_error: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UtxoVerifyRequest {
+    /// `txid:vout`
+    pub outpoints: Vec<String>,
+}
+
+/// Whether an output is still unspent, and if not where it went
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "status", rename_all = "snake_case")]
+pub enum SpendStatus {
+    Unspent,
+    SpentInMempool {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        spending_txid: Option<Txid>,
+    },
+    /// Backends without a spend index, bitcoind among them, know neither
+    /// the spender nor its height
+    SpentInBlock {
+        #[serde(skip_serializing_if = "Option::is_none")]
+        spending_txid: Option<Txid>,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        block_height: Option<u64>,
+    },
+    /// No known transaction has this output
+    NotFound,
+}
+
+#[derive(Debug, Serialize)]
+pub struct OutPointStatus {
+    pub outpoint: OutPoint,
+    #[serde(flatten)]
+    pub status: SpendStatus,
+}
+
+#[derive(Debug, Serialize)]
+pub struct UtxoVerifyResponse {
+    pub outpoints: Vec<OutPointStatus>,
+}
+
+/// Plain BTC payment to fund from `utxos`, or from the UTXOs of `addresses`
+#[derive(Debug, Deserialize)]
+pub struct CoinSelectRequest {
+    /// Sats to pay, fee not included
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub target: Amount,
+    /// sat/vB
+    pub fee_rate: f64,
+    #[serde(default)]
+    pub utxos: Vec<Utxo>,
+    #[serde(default)]
+    pub addresses: Vec<String>,
+    pub change_type: ScriptType,
+    /// Outputs paying `target`, for sizing; one of `change_type` by default
+    pub outputs: Option<Vec<OutputType>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SelectionAlgorithm {
+    BranchAndBound,
+    Knapsack,
+    LargestFirst,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CoinSelectResponse {
+    pub inputs: Vec<Utxo>,
+    /// Value of the change output, if the selection has one
+    #[serde(with = "bitcoin::amount::serde::as_sat::opt")]
+    pub change: Option<Amount>,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+    /// Of the whole transaction, change included
+    pub size: TxSize,
+    pub algorithm: SelectionAlgorithm,
+    /// Candidates left out because they carry charms, inscriptions or runes
+    pub excluded: Vec<OutPoint>,
+    /// Candidates left out because their transaction could not be checked
+    pub unchecked: Vec<OutPoint>,
+    /// What the check behind `excluded` can and cannot see
+    pub asset_detection: &'static str,
 }
 
 #[derive(Debug, Deserialize)]
@@ -24,23 +274,152 @@ pub struct TransactionResponse {
 
 #[derive(Debug, Serialize)]
 pub struct FeeEstimateResponse {
-    pub fast: u64,
-    pub medium: u64,
-    pub slow: u64,
+    /// Chain backend that was asked
+    pub backend: String,
+    pub min_fee_rate: f64,
+    pub margin: f64,
+    /// Size of the transaction described in the request, if any
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size: Option<TxSize>,
+    pub estimates: Vec<FeeRateEstimate>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FeeRateEstimate {
+    pub target_blocks: u16,
+    /// sat/vB to use, after the floor and margin
+    pub fee_rate: f64,
+    /// sat/vB reported by the source
+    pub raw_fee_rate: f64,
+    pub source: FeeSource,
+    /// Total fee in sats at `fee_rate` for the requested transaction
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fee: Option<u64>,
+}
+
+/// Where a fee rate came from
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeeSource {
+    /// The backend's estimator, e.g. `estimatesmartfee`
+    Estimator,
+    /// The backend's mempool fee histogram
+    Mempool,
+    /// Neither answered; a fixed fallback rate
+    Default,
+}
+
+/// Transaction that travels as consensus-encoded hex
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RawTx(pub Transaction);
+
+impl Serialize for RawTx {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&serialize_hex(&self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for RawTx {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let tx_hex = String::deserialize(deserializer)?;
+        deserialize_hex(&tx_hex)
+            .map(RawTx)
+            .map_err(|e| de::Error::custom(format!("invalid transaction: {}", e)))
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BroadcastTxRequest {
-    pub tx_hex: String,
-    pub tx_package: Option<Vec<String>>,
+    pub tx_hex: RawTx,
+    /// Several transactions to relay together, in any order; replaces
+    /// `tx_hex` when not empty
+    pub tx_package: Option<Vec<RawTx>>,
+    /// Broadcast even if inputs holding charms are spent without a spell
+    /// that carries them forward, destroying them
+    #[serde(default)]
+    pub allow_charm_burn: bool,
+}
+
+impl BroadcastTxRequest {
+    /// Transactions to broadcast
+    pub fn transactions(&self) -> Vec<Transaction> {
+        match &self.tx_package {
+            Some(package) if !package.is_empty() => package.iter().map(|tx| tx.0.clone()).collect(),
+            _ => vec![self.tx_hex.0.clone()],
+        }
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct BroadcastTxResponse {
-    pub txid: String,
+    pub txid: Txid,
     pub command: String,
     pub node_response: Option<String>,
-    pub txids: Option<Vec<String>>,
+    /// Every broadcast txid, parents first
+    pub txids: Option<Vec<Txid>>,
+    #[serde(default)]
+    pub results: Vec<BroadcastTxResult>,
+    /// Total fee over total vsize of the package, in sat/vB
+    #[serde(default)]
+    pub package_fee_rate: f64,
+}
+
+/// Why a transaction failed the checks before broadcast or signing
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PolicyReason {
+    /// Fee rate above the node's `-maxfeerate`
+    AbsurdFee,
+    OutputsExceedInputs,
+    Dust,
+    NonStandardScript,
+    TxTooLarge,
+    MissingInputs,
+    InputSpent,
+    /// Spends charms without a spell that carries them forward
+    CarriesCharms,
+    CarriesInscription,
+    /// Spends runes without a runestone that carries them forward
+    CarriesRunes,
+    /// Refused by the node's `testmempoolaccept`
+    MempoolRejected,
+    /// Signing needs an API key and none was presented
+    Unauthenticated,
+    /// Pays an address outside `signing.allowed_destinations`
+    DestinationNotAllowed,
+    /// Fee above `signing.max_fee`
+    FeeTooHigh,
+    /// Would exceed `signing.daily_limit`
+    DailyLimit,
+    /// Would exceed `signing.key_daily_limit` for the caller's key
+    KeyDailyLimit,
+}
+
+/// One failed check, pointing at the input or output at fault
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PolicyViolation {
+    pub txid: String,
+    pub reason: PolicyReason,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub input: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<u32>,
+    pub message: String,
+}
+
+/// Outcome of one broadcast transaction
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BroadcastTxResult {
+    pub txid: Txid,
+    pub vsize: u64,
+    /// Fee in sats
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub fee: Amount,
+    /// Own fee rate in sat/vB
+    pub fee_rate: f64,
+    /// Rate in sat/vB the node judged the transaction at, counting the
+    /// package members it was bundled with
+    pub effective_fee_rate: Option<f64>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -48,6 +427,194 @@ pub struct SendHexTxRequest {
     #[serde(rename = "txHex")]
     pub tx_hex: String,
     pub network: String,
+    /// Node wallet to sign with, when several are configured
+    #[serde(default)]
+    pub wallet: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BumpFeeRequest {
+    pub txid: Txid,
+    /// Target rate in sat/vB
+    pub fee_rate: f64,
+    /// Output whose value is reduced to pay the extra fee
+    pub change_vout: u32,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BumpFeeResponse {
+    /// Unsigned replacement, base64 encoded
+    pub psbt: String,
+    pub replaces: String,
+    pub original_fee: u64,
+    pub fee: u64,
+    pub fee_rate: f64,
+    pub vsize: u64,
+    pub change_value: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CpfpRequest {
+    /// Stuck parent transaction
+    pub txid: Txid,
+    /// Output of the parent spent by the child
+    pub vout: u32,
+    /// Target rate in sat/vB for the parent's package and the child together
+    pub fee_rate: f64,
+    /// Where the child sends what is left after its fee
+    pub address: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CpfpResponse {
+    /// Unsigned child, base64 encoded
+    pub psbt: String,
+    pub parent: String,
+    /// Unconfirmed transactions in the parent's package, the parent included
+    pub package_count: u64,
+    pub package_vsize: u64,
+    pub package_fee: u64,
+    pub package_fee_rate: f64,
+    pub child_vsize: u64,
+    pub child_fee: u64,
+    pub child_value: u64,
+    /// Rate of the package once the child is added
+    pub fee_rate: f64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CpfpSubmitRequest {
+    /// Signed child
+    pub tx_hex: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PsbtInputSpec {
+    pub txid: Txid,
+    pub vout: u32,
+    /// Defaults to signaling replaceability (BIP125)
+    #[serde(default)]
+    pub sequence: Option<u32>,
+}
+
+/// Output of a PSBT being created: a payment, or `OP_RETURN` data
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum PsbtOutputSpec {
+    Payment {
+        address: String,
+        value: u64,
+    },
+    /// Hex-encoded bytes pushed after `OP_RETURN`
+    Data {
+        data: String,
+    },
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreatePsbtRequest {
+    pub inputs: Vec<PsbtInputSpec>,
+    pub outputs: Vec<PsbtOutputSpec>,
+    #[serde(default)]
+    pub locktime: u32,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct PsbtRequest {
+    /// Base64 encoded
+    pub psbt: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PsbtResponse {
+    /// Base64 encoded
+    pub psbt: String,
+}
+
+/// Taproot spending data for one input, all hex encoded
+#[derive(Debug, Deserialize)]
+pub struct TaprootInputSpec {
+    pub index: usize,
+    /// For key-path spends of outputs without a script tree
+    #[serde(default)]
+    pub internal_key: Option<String>,
+    /// For script-path spends, together with `control_block`
+    #[serde(default)]
+    pub leaf_script: Option<String>,
+    #[serde(default)]
+    pub control_block: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct UpdatePsbtRequest {
+    pub psbt: String,
+    #[serde(default)]
+    pub taproot: Vec<TaprootInputSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CombinePsbtRequest {
+    pub psbts: Vec<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BroadcastPsbtRequest {
+    pub psbt: String,
+    #[serde(default)]
+    pub allow_charm_burn: bool,
+}
+
+/// BIP174 role that has to act next on a PSBT
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PsbtRole {
+    Updater,
+    Signer,
+    Finalizer,
+    Extractor,
+}
+
+#[derive(Debug, Serialize)]
+pub struct PsbtInputAnalysis {
+    pub has_utxo: bool,
+    pub is_final: bool,
+    /// Keys, hex encoded, known to the PSBT that have yet to sign
+    pub missing_signatures: Vec<String>,
+    pub next: PsbtRole,
+}
+
+#[derive(Debug, Serialize)]
+pub struct AnalyzePsbtResponse {
+    pub inputs: Vec<PsbtInputAnalysis>,
+    pub next: PsbtRole,
+    /// Known once every input has its UTXO
+    pub fee: Option<u64>,
+    /// Size once signed, when every input and output type is known
+    pub estimated_vsize: Option<u64>,
+    pub estimated_fee_rate: Option<f64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalizePsbtResponse {
+    pub psbt: String,
+    pub complete: bool,
+    /// The signed transaction, once every input is final
+    pub tx_hex: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ProveSpellResponse {
+    #[serde(flatten)]
+    pub proof: SpellProofResult,
+    /// Base64 PSBTs for signing the commit and spell transactions, absent
+    /// when their inputs could not be looked up
+    pub commit_psbt: Option<String>,
+    pub spell_psbt: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WalletQuery {
+    pub wallet: Option<String>,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -58,13 +625,24 @@ pub struct SpellData {
     pub outs: Vec<serde_json::Value>,
 }
 
+/// Bitcoin fields of a prove payload, as far as they are checked here; the
+/// payload itself goes to the prover untouched
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ProveSpellRequest {
-    pub spell: SpellData,
+    /// JSON spell, or its CBOR hex
+    pub spell: serde_json::Value,
     pub binaries: serde_json::Value,
-    pub prev_txs: Vec<String>,
-    pub funding_utxo: String,
-    pub funding_utxo_value: u64,
-    pub change_address: String,
+    /// Hex strings, or `{"bitcoin": hex}` objects
+    pub prev_txs: Vec<serde_json::Value>,
+    /// `txid:vout`
+    pub funding_utxo: OutPoint,
+    #[serde(with = "bitcoin::amount::serde::as_sat")]
+    pub funding_utxo_value: Amount,
+    /// Checked against the network of the route
+    pub change_address: Address<NetworkUnchecked>,
+    /// sat/vB
     pub fee_rate: f64,
+    /// Chain the spell is proven for, Bitcoin unless given
+    #[serde(default)]
+    pub chain: Option<String>,
 }
@@ -0,0 +1,21 @@
+use crate::config::network_name;
+use crate::error::{WalletError, WalletResult};
+use bitcoin::{address::NetworkUnchecked, Address, Network};
+
+/// Parse an address and require it to belong to `network`.
+///
+/// Malformed input is an `InvalidAddress` error and a valid address of
+/// another network a `WrongNetwork` one, both answered with a 400.
+pub fn parse_address(address: &str, network: Network) -> WalletResult<Address> {
+    let unchecked: Address<NetworkUnchecked> = address
+        .parse()
+        .map_err(|e| WalletError::InvalidAddress(format!("{}: {}", address, e)))?;
+
+    unchecked.require_network(network).map_err(|_| {
+        WalletError::WrongNetwork(format!(
+            "{} is not a {} address",
+            address,
+            network_name(network)
+        ))
+    })
+}
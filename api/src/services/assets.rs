@@ -0,0 +1,536 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{InscriptionAsset, RuneAsset, Utxo, UtxoAssets};
+use crate::services::chain::ChainBackend;
+use crate::services::charm_outputs::output_charms;
+use bitcoin::opcodes::all::{OP_ENDIF, OP_IF, OP_PUSHNUM_1, OP_PUSHNUM_16};
+use bitcoin::script::Instruction;
+use bitcoin::{Amount, OutPoint, Script, Transaction, TxIn, Txid};
+use futures::future;
+use std::collections::{HashMap, HashSet};
+
+// OP_RETURN OP_13, which starts a runestone
+const RUNESTONE_PREFIX: [u8; 2] = [0x6a, 0x5d];
+// Protocol tag pushed after OP_FALSE OP_IF in an inscription envelope
+const ORD_TAG: &[u8] = b"ord";
+// Inscription fields, pushed as a tag followed by the value
+const CONTENT_TYPE_TAG: u8 = 1;
+const POINTER_TAG: u8 = 2;
+// Runestone fields; other even tags are unknown and make a cenotaph
+const BODY_TAG: u128 = 0;
+const FLAGS_TAG: u128 = 2;
+const POINTER_FIELD: u128 = 22;
+const KNOWN_EVEN_TAGS: [u128; 12] = [0, 2, 4, 6, 8, 10, 12, 14, 16, 18, 20, 22];
+// Etching, terms and turbo; any other flag makes a cenotaph
+const KNOWN_FLAGS: u128 = 0b111;
+
+/// Assets carried by each output of `tx`, going by `tx` and the values of
+/// its inputs.
+///
+/// Charms come from the spell, which must verify. Inscriptions come from
+/// the envelopes in the inputs' tapscripts and land on the first sat of
+/// their input, or the one their pointer names; one that lands past the
+/// outputs goes to the fee. Placing them takes the values of the inputs
+/// before theirs, from `input_values`. Runes come from the runestone: its
+/// edicts and the output it sends everything else to, unless it is a
+/// cenotaph, which burns them. Assets that merely passed through `tx`,
+/// without a spell, envelope or runestone, take an index to follow.
+pub async fn output_assets(
+    tx: &Transaction,
+    input_values: &HashMap<OutPoint, Amount>,
+) -> Vec<UtxoAssets> {
+    let mut assets = vec![UtxoAssets::default(); tx.output.len()];
+    add_charms(tx, &mut assets).await;
+    add_inscriptions(tx, input_values, &mut assets);
+    add_runes(tx, &mut assets);
+    assets
+}
+
+/// Values of the inputs that [`output_assets`] needs to place the
+/// inscriptions of `txs`: those before the last input with an envelope.
+/// Their transactions are taken from `known`, else fetched in one call.
+pub async fn input_values<'a>(
+    chain: &dyn ChainBackend,
+    txs: impl IntoIterator<Item = &'a Transaction>,
+    known: &HashMap<Txid, Transaction>,
+) -> WalletResult<HashMap<OutPoint, Amount>> {
+    let outpoints: Vec<OutPoint> = txs
+        .into_iter()
+        .flat_map(|tx| {
+            let inscribed = tx
+                .input
+                .iter()
+                .rposition(|input| !inscription_envelopes(input).is_empty())
+                .unwrap_or(0);
+            tx.input[..inscribed]
+                .iter()
+                .map(|input| input.previous_output)
+        })
+        .collect();
+
+    let mut txids: Vec<Txid> = outpoints
+        .iter()
+        .map(|outpoint| outpoint.txid)
+        .filter(|txid| !known.contains_key(txid))
+        .collect();
+    txids.sort();
+    txids.dedup();
+    let fetched: HashMap<Txid, Transaction> = chain
+        .get_transactions(&txids)
+        .await?
+        .into_iter()
+        .map(|tx| (tx.compute_txid(), tx))
+        .collect();
+
+    outpoints
+        .into_iter()
+        .map(|outpoint| {
+            let value = known
+                .get(&outpoint.txid)
+                .or_else(|| fetched.get(&outpoint.txid))
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+                .map(|output| output.value)
+                .ok_or_else(|| {
+                    WalletError::BitcoinError(format!("Previous output {} not found", outpoint))
+                })?;
+            Ok((outpoint, value))
+        })
+        .collect()
+}
+
+/// Whether `tx` has a runestone that deciphers. A cenotaph burns the runes
+/// its transaction spends instead of carrying them on.
+pub fn has_valid_runestone(tx: &Transaction) -> bool {
+    runestone_output(tx).is_some_and(|script| decipher(script, tx.output.len()).is_some())
+}
+
+/// Tag each UTXO with the assets it carries, fetching every transaction
+/// that created one once, and the inputs its inscriptions need. Returns
+/// the UTXOs left untagged because their output was not found, which may
+/// carry anything.
+pub async fn classify<'a>(
+    chain: &dyn ChainBackend,
+    utxos: impl IntoIterator<Item = &'a mut Utxo>,
+) -> WalletResult<HashSet<OutPoint>> {
+    let mut utxos: Vec<&mut Utxo> = utxos.into_iter().collect();
+    if utxos.is_empty() {
+        return Ok(HashSet::new());
+    }
+    let mut txids: Vec<Txid> = utxos.iter().map(|utxo| utxo.txid).collect();
+    txids.sort();
+    txids.dedup();
+
+    // Keyed by what was fetched, so a backend that leaves some out, or a
+    // UTXO naming an output its transaction lacks, leaves it unmatched
+    let txs: HashMap<Txid, Transaction> = chain
+        .get_transactions(&txids)
+        .await?
+        .into_iter()
+        .map(|tx| (tx.compute_txid(), tx))
+        .collect();
+    let input_values = input_values(chain, txs.values(), &txs).await?;
+    let assets: HashMap<Txid, Vec<UtxoAssets>> = future::join_all(
+        txs.iter()
+            .map(|(txid, tx)| async { (*txid, output_assets(tx, &input_values).await) }),
+    )
+    .await
+    .into_iter()
+    .collect();
+
+    let mut unmatched = HashSet::new();
+    for utxo in utxos.iter_mut() {
+        let output = assets
+            .get(&utxo.txid)
+            .and_then(|outputs| outputs.get(utxo.vout as usize));
+        match output {
+            Some(output) => utxo.assets = output.clone(),
+            None => {
+                unmatched.insert(utxo.outpoint());
+            }
+        }
+    }
+    Ok(unmatched)
+}
+
+async fn add_charms(tx: &Transaction, assets: &mut [UtxoAssets]) {
+    let charms = output_charms(tx).await;
+    for (output, held) in assets.iter_mut().zip(charms.iter()) {
+        output.charms.extend(held.iter().cloned());
+    }
+}
+
+// Pushes inside each `OP_FALSE OP_IF "ord" ... OP_ENDIF` envelope of a
+// tapscript, OP_1 to OP_16 counting as pushes of their number
+fn envelopes(script: &Script) -> Vec<Vec<Vec<u8>>> {
+    let mut envelopes = Vec::new();
+    let mut instructions = script.instructions().peekable();
+    let mut previous: [Option<Instruction>; 2] = [None, None];
+
+    while let Some(Ok(instruction)) = instructions.next() {
+        let opens = matches!(previous[0], Some(Instruction::PushBytes(bytes)) if bytes.is_empty())
+            && previous[1] == Some(Instruction::Op(OP_IF))
+            && matches!(instruction, Instruction::PushBytes(bytes) if bytes.as_bytes() == ORD_TAG);
+        previous = [previous[1], Some(instruction)];
+        if !opens {
+            continue;
+        }
+
+        let mut pushes = Vec::new();
+        for instruction in instructions.by_ref() {
+            match instruction {
+                Ok(Instruction::PushBytes(bytes)) => pushes.push(bytes.as_bytes().to_vec()),
+                Ok(Instruction::Op(op)) if op == OP_ENDIF => {
+                    envelopes.push(pushes);
+                    break;
+                }
+                Ok(Instruction::Op(op))
+                    if (OP_PUSHNUM_1.to_u8()..=OP_PUSHNUM_16.to_u8()).contains(&op.to_u8()) =>
+                {
+                    pushes.push(vec![op.to_u8() - OP_PUSHNUM_1.to_u8() + 1])
+                }
+                _ => break,
+            }
+        }
+        previous = [None, None];
+    }
+    envelopes
+}
+
+// Envelopes in the tapscript an input reveals, if any
+fn inscription_envelopes(input: &TxIn) -> Vec<Vec<Vec<u8>>> {
+    input
+        .witness
+        .taproot_leaf_script()
+        .map(|leaf| envelopes(leaf.script))
+        .unwrap_or_default()
+}
+
+fn add_inscriptions(
+    tx: &Transaction,
+    input_values: &HashMap<OutPoint, Amount>,
+    assets: &mut [UtxoAssets],
+) {
+    let txid = tx.compute_txid();
+    let total: u64 = tx.output.iter().map(|output| output.value.to_sat()).sum();
+    let mut index = 0;
+    // First sat of the input, counting the values of those before it
+    let mut start = Some(0u64);
+
+    for input in &tx.input {
+        for pushes in inscription_envelopes(input) {
+            let mut content_type = None;
+            let mut pointer = None;
+            // Tag and value pairs, up to the empty push that opens the body
+            for field in pushes.chunks(2).take_while(|field| !field[0].is_empty()) {
+                match (field[0].as_slice(), field.get(1)) {
+                    ([CONTENT_TYPE_TAG], Some(value)) => {
+                        content_type = Some(String::from_utf8_lossy(value).into_owned())
+                    }
+                    ([POINTER_TAG], Some(value)) if value.len() <= 8 => {
+                        let mut bytes = [0; 8];
+                        bytes[..value.len()].copy_from_slice(value);
+                        pointer = Some(u64::from_le_bytes(bytes));
+                    }
+                    _ => {}
+                }
+            }
+
+            // Sat offset the inscription lands on, then the output holding
+            // it; past the last output, it goes to the fee
+            let id = format!("{}i{}", txid, index);
+            index += 1;
+            let Some(offset) = pointer.filter(|pointer| *pointer < total).or(start) else {
+                tracing::warn!("Cannot place inscription {}: input values unknown", id);
+                continue;
+            };
+            let mut end = 0;
+            let vout = tx.output.iter().position(|output| {
+                end += output.value.to_sat();
+                offset < end
+            });
+            if let Some(output) = vout.and_then(|vout| assets.get_mut(vout)) {
+                output
+                    .inscriptions
+                    .push(InscriptionAsset { id, content_type });
+            }
+        }
+
+        start = start
+            .zip(input_values.get(&input.previous_output))
+            .map(|(start, value)| start + value.to_sat());
+    }
+}
+
+fn runestone_output(tx: &Transaction) -> Option<&Script> {
+    tx.output
+        .iter()
+        .map(|output| output.script_pubkey.as_script())
+        .find(|script| script.as_bytes().starts_with(&RUNESTONE_PREFIX))
+}
+
+// LEB128, as runestones encode their integers
+fn read_varint(bytes: &[u8]) -> Option<(u128, usize)> {
+    let mut n = 0u128;
+    for (i, byte) in bytes.iter().enumerate() {
+        let value = u128::from(byte & 0x7f);
+        if i > 18 || (i == 18 && value > 0b11) {
+            return None;
+        }
+        n |= value << (7 * i);
+        if byte & 0x80 == 0 {
+            return Some((n, i + 1));
+        }
+    }
+    None
+}
+
+// Edicts and pointer of a runestone, or `None` for a cenotaph
+fn decipher(script: &Script, outputs: usize) -> Option<(Vec<[u128; 4]>, Option<usize>)> {
+    let mut payload = Vec::new();
+    for instruction in script.instructions().skip(2) {
+        match instruction {
+            Ok(Instruction::PushBytes(bytes)) => payload.extend_from_slice(bytes.as_bytes()),
+            _ => return None,
+        }
+    }
+
+    let mut integers = Vec::new();
+    let mut rest = payload.as_slice();
+    while !rest.is_empty() {
+        let (n, len) = read_varint(rest)?;
+        integers.push(n);
+        rest = &rest[len..];
+    }
+
+    // Tag and value pairs, up to the body tag
+    let mut pointer = None;
+    let mut i = 0;
+    while let Some(&tag) = integers.get(i) {
+        i += 1;
+        if tag == BODY_TAG {
+            break;
+        }
+        let &value = integers.get(i)?;
+        i += 1;
+        match tag {
+            POINTER_FIELD => pointer = Some(usize::try_from(value).ok().filter(|p| *p < outputs)?),
+            FLAGS_TAG if value & !KNOWN_FLAGS != 0 => return None,
+            tag if tag % 2 == 0 && !KNOWN_EVEN_TAGS.contains(&tag) => return None,
+            _ => {}
+        }
+    }
+
+    // Edicts follow the body tag, each `block, tx, amount, output`, with
+    // rune ids as deltas from the previous edict
+    let body = &integers[i..];
+    if body.len() % 4 != 0 {
+        return None;
+    }
+    let (mut block, mut tx) = (0u128, 0u128);
+    let mut edicts = Vec::new();
+    for edict in body.chunks(4) {
+        if edict[0] == 0 {
+            tx = tx.checked_add(edict[1])?;
+        } else {
+            block = block.checked_add(edict[0])?;
+            tx = edict[1];
+        }
+        if edict[3] > outputs as u128 {
+            return None;
+        }
+        edicts.push([block, tx, edict[2], edict[3]]);
+    }
+    Some((edicts, pointer))
+}
+
+fn add_runes(tx: &Transaction, assets: &mut [UtxoAssets]) {
+    let Some(script) = runestone_output(tx) else {
+        return;
+    };
+    let Some((edicts, pointer)) = decipher(script, tx.output.len()) else {
+        return;
+    };
+    // Runes sent to OP_RETURN outputs are burned
+    let holds = |vout: usize| !tx.output[vout].script_pubkey.is_op_return();
+
+    for [block, tx_index, amount, output] in edicts {
+        let rune = RuneAsset {
+            id: (block != 0 || tx_index != 0).then(|| format!("{}:{}", block, tx_index)),
+            amount: (amount != 0).then(|| amount.to_string()),
+        };
+        // An edict to one past the last output splits among all of them
+        let vouts: Vec<usize> = if output as usize == tx.output.len() {
+            (0..tx.output.len()).filter(|vout| holds(*vout)).collect()
+        } else {
+            vec![output as usize]
+        };
+        for vout in vouts.into_iter().filter(|vout| holds(*vout)) {
+            assets[vout].runes.push(rune.clone());
+        }
+    }
+
+    let default = pointer.or_else(|| (0..tx.output.len()).find(|vout| holds(*vout)));
+    if let Some(vout) = default.filter(|vout| holds(*vout)) {
+        assets[vout].unassigned_runes = true;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use crate::services::chain::MemoryBackend;
+    use bitcoin::{
+        absolute::LockTime,
+        opcodes::{
+            all::{OP_PUSHNUM_13, OP_RETURN},
+            OP_FALSE,
+        },
+        script::{Builder, PushBytes},
+        transaction::Version,
+        Network, ScriptBuf, TxOut, Witness,
+    };
+
+    fn pay(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: address(1, Network::Regtest).script_pubkey(),
+        }
+    }
+
+    // Reveal spending `outpoints` into `outputs`, with an inscription in
+    // the tapscript of the second input
+    fn reveal(outpoints: &[OutPoint], outputs: Vec<TxOut>) -> Transaction {
+        let envelope = Builder::new()
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut tx = spending_tx(outpoints, outputs);
+        tx.input[1].witness =
+            Witness::from_slice(&[vec![0; 64], envelope.to_bytes(), vec![0xc0; 33]]);
+        tx
+    }
+
+    fn inscribed(assets: &[UtxoAssets]) -> Vec<usize> {
+        (0..assets.len())
+            .filter(|vout| !assets[*vout].inscriptions.is_empty())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn inscriptions_land_on_the_first_sat_of_their_input() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let first = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(10_000),
+            Some(1),
+        );
+        let second = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(20_000),
+            Some(1),
+        );
+        // The first 10_000 sats come from the first input
+        let tx = reveal(&[first, second], vec![pay(5_000), pay(5_000), pay(15_000)]);
+
+        let values = input_values(&chain, [&tx], &HashMap::new()).await.unwrap();
+        assert_eq!(values.keys().collect::<Vec<_>>(), vec![&first]);
+        let assets = output_assets(&tx, &values).await;
+        assert_eq!(inscribed(&assets), vec![2]);
+        assert_eq!(
+            assets[2].inscriptions[0].id,
+            format!("{}i0", tx.compute_txid())
+        );
+    }
+
+    #[tokio::test]
+    async fn inscriptions_past_the_outputs_go_to_the_fee() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let first = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(30_000),
+            Some(1),
+        );
+        let second = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(1_000),
+            Some(1),
+        );
+        let tx = reveal(&[first, second], vec![pay(5_000), pay(20_000)]);
+
+        let values = input_values(&chain, [&tx], &HashMap::new()).await.unwrap();
+        assert!(inscribed(&output_assets(&tx, &values).await).is_empty());
+    }
+
+    #[tokio::test]
+    async fn classify_places_inscriptions_of_later_inputs() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let first = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(10_000),
+            Some(1),
+        );
+        let second = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(20_000),
+            Some(1),
+        );
+        let tx = reveal(&[first, second], vec![pay(10_000), pay(19_000)]);
+        chain.insert_transaction(tx.clone(), Some(2));
+
+        let mut utxos = chain
+            .list_unspent(&address(1, Network::Regtest))
+            .await
+            .unwrap();
+        utxos.sort_by_key(|utxo| utxo.vout);
+        classify(&chain, &mut utxos).await.unwrap();
+        assert!(utxos[0].assets.inscriptions.is_empty());
+        assert_eq!(utxos[1].assets.inscriptions.len(), 1);
+    }
+
+    // Runestone with only the given tag and value pairs, no edicts
+    fn runestone_tx(fields: &[u8]) -> Transaction {
+        let runestone = Builder::new()
+            .push_opcode(OP_RETURN)
+            .push_opcode(OP_PUSHNUM_13)
+            .push_slice(<&PushBytes>::try_from(fields).unwrap())
+            .into_script();
+        Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: vec![],
+            output: vec![
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: ScriptBuf::new_op_return([]),
+                },
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: ScriptBuf::from_bytes(vec![0x51, 0x20]),
+                },
+                TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: runestone,
+                },
+            ],
+        }
+    }
+
+    #[tokio::test]
+    async fn turbo_flag_is_not_a_cenotaph() {
+        // Etching, terms and turbo
+        let tx = runestone_tx(&[FLAGS_TAG as u8, 0b111]);
+        let assets = output_assets(&tx, &HashMap::new()).await;
+        assert!(assets[1].unassigned_runes);
+    }
+
+    #[tokio::test]
+    async fn unknown_flag_makes_a_cenotaph() {
+        let cenotaph = runestone_tx(&[FLAGS_TAG as u8, 0b1000]);
+        let assets = output_assets(&cenotaph, &HashMap::new()).await;
+        assert!(assets.iter().all(|output| !output.unassigned_runes));
+        assert!(!has_valid_runestone(&cenotaph));
+        assert!(has_valid_runestone(&runestone_tx(&[FLAGS_TAG as u8, 0b1])));
+    }
+}
@@ -1,6 +1,9 @@
 use axum::response::{IntoResponse, Response};
+use bitcoin::Network;
 use http::StatusCode;
+use std::{process::Stdio, time::Duration};
 use thiserror::Error;
+use tokio::{io::AsyncReadExt, process::Command, sync::Semaphore};
 
 #[derive(Error, Debug)]
 pub enum BitcoinCliError {
@@ -10,15 +13,34 @@ pub enum BitcoinCliError {
     #[error("JSON parsing failed: {0}")]
     JsonError(#[from] serde_json::Error),
 
+    #[error("bitcoind error {code}: {message}")]
+    Rpc { code: i32, message: String },
+
+    #[error("bitcoin-cli timed out after {0:?}")]
+    Timeout(Duration),
+
     #[error("Other error: {0}")]
     Other(String),
 }
 
+// bitcoind RPC error codes that mean the node itself is unavailable
+pub const RPC_IN_WARMUP: i32 = -28;
+pub const RPC_CLIENT_NOT_CONNECTED: i32 = -9;
+// Returned for unknown transactions, among other missing things
+pub const RPC_INVALID_ADDRESS_OR_KEY: i32 = -5;
+
 impl IntoResponse for BitcoinCliError {
     fn into_response(self) -> Response {
         let status = match self {
             BitcoinCliError::CommandError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             BitcoinCliError::JsonError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            BitcoinCliError::Rpc { code, .. }
+                if code == RPC_IN_WARMUP || code == RPC_CLIENT_NOT_CONNECTED =>
+            {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
+            BitcoinCliError::Rpc { .. } => StatusCode::BAD_REQUEST,
+            BitcoinCliError::Timeout(_) => StatusCode::GATEWAY_TIMEOUT,
             BitcoinCliError::Other(_) => StatusCode::INTERNAL_SERVER_ERROR,
         };
 
@@ -30,41 +52,100 @@ impl IntoResponse for BitcoinCliError {
 
 pub type Result<T> = std::result::Result<T, BitcoinCliError>;
 
-// Execute bitcoin-cli command
-pub fn execute_bitcoin_cli(args: &[&str]) -> Result<Vec<u8>> {
-    use std::{
-        env,
-        process::{Command, Stdio},
-    };
-
-    // Get network from environment
-    let network = env::var("BITCOIN_NETWORK").unwrap_or_else(|_| "testnet4".to_string());
-    let network_arg = format!("-{}", network);
-
-    // Log command for debugging
-    tracing::debug!(
-        "Executing bitcoin-cli command: bitcoin-cli {} {}",
-        network_arg,
-        args.join(" ")
-    );
-
-    let b_cli = Command::new("bitcoin-cli")
-        .arg(&network_arg)
-        .args(args)
-        .stdout(Stdio::piped())
-        .stderr(Stdio::piped())
-        .spawn()?;
-
-    let output = b_cli.wait_with_output()?;
-
-    if !output.status.success() {
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        tracing::error!("bitcoin-cli command failed: {}", stderr);
-        return Err(BitcoinCliError::Other(format!(
-            "bitcoin-cli command failed: {}",
-            stderr
-        )));
+// Parse bitcoin-cli's "error code: N\nerror message:\n..." stderr
+fn parse_rpc_error(stderr: &str) -> Option<BitcoinCliError> {
+    let mut lines = stderr.lines();
+    let code = lines
+        .next()?
+        .strip_prefix("error code:")?
+        .trim()
+        .parse::<i32>()
+        .ok()?;
+    let message = lines
+        .filter(|line| line.trim() != "error message:")
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Some(BitcoinCliError::Rpc {
+        code,
+        message: message.trim().to_string(),
+    })
+}
+
+/// Runs `bitcoin-cli` against the configured network.
+///
+/// Bounds the number of concurrent processes and kills any that run past
+/// the timeout. One executor is shared by every call of a backend.
+pub struct CliExecutor {
+    network_arg: String,
+    permits: Semaphore,
+    timeout: Duration,
+}
+
+impl CliExecutor {
+    pub fn new(network: Network, max_concurrency: usize, timeout: Duration) -> Self {
+        Self {
+            network_arg: format!("-chain={}", network.to_core_arg()),
+            permits: Semaphore::new(max_concurrency),
+            timeout,
+        }
     }
 
-    Ok(output.stdout)
+    // Execute bitcoin-cli command
+    pub async fn execute(&self, args: &[&str]) -> Result<Vec<u8>> {
+        // Log command for debugging
+        tracing::debug!(
+            "Executing bitcoin-cli command: bitcoin-cli {} {}",
+            self.network_arg,
+            args.join(" ")
+        );
+
+        // Bound the number of concurrent bitcoin-cli processes
+        let _permit = self
+            .permits
+            .acquire()
+            .await
+            .map_err(|e| BitcoinCliError::Other(e.to_string()))?;
+
+        let mut b_cli = Command::new("bitcoin-cli")
+            .arg(&self.network_arg)
+            .args(args)
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()?;
+
+        let mut stdout = b_cli.stdout.take().expect("stdout is piped");
+        let mut stderr = b_cli.stderr.take().expect("stderr is piped");
+
+        let run = async {
+            let mut out = Vec::new();
+            let mut err = Vec::new();
+            let (status, _, _) = tokio::try_join!(
+                b_cli.wait(),
+                stdout.read_to_end(&mut out),
+                stderr.read_to_end(&mut err)
+            )?;
+            Ok::<_, std::io::Error>((status, out, err))
+        };
+
+        let (status, stdout, stderr) = match tokio::time::timeout(self.timeout, run).await {
+            Ok(result) => result?,
+            Err(_) => {
+                tracing::error!("bitcoin-cli timed out: {}", args.join(" "));
+                let _ = b_cli.kill().await;
+                return Err(BitcoinCliError::Timeout(self.timeout));
+            }
+        };
+
+        if !status.success() {
+            let stderr = String::from_utf8_lossy(&stderr);
+            tracing::error!("bitcoin-cli command failed: {}", stderr);
+            return Err(parse_rpc_error(&stderr).unwrap_or_else(|| {
+                BitcoinCliError::Other(format!("bitcoin-cli command failed: {}", stderr))
+            }));
+        }
+
+        Ok(stdout)
+    }
 }
@@ -0,0 +1,12 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+// Estimate fee rate in BTC/kvB for a confirmation target
+pub async fn estimate_smart_fee(cli: &CliExecutor, conf_target: u16) -> Result<Option<f64>> {
+    let target = conf_target.to_string();
+    let args = vec!["estimatesmartfee", &target];
+    let output = cli.execute(&args).await?;
+
+    let estimate: Value = serde_json::from_slice(&output)?;
+    Ok(estimate["feerate"].as_f64())
+}
@@ -0,0 +1,11 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+// What the node wallet knows about an address, including `ismine`
+pub async fn get_address_info(cli: &CliExecutor, address: &str) -> Result<Value> {
+    let args = vec!["getaddressinfo", address];
+    let output = cli.execute(&args).await?;
+
+    let info: Value = serde_json::from_slice(&output)?;
+    Ok(info)
+}
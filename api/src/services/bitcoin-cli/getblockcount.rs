@@ -0,0 +1,12 @@
+use super::error::{BitcoinCliError, CliExecutor, Result};
+
+// Get the height of the chain tip
+pub async fn get_block_count(cli: &CliExecutor) -> Result<u64> {
+    let args = vec!["getblockcount"];
+    let output = cli.execute(&args).await?;
+
+    String::from_utf8_lossy(&output)
+        .trim()
+        .parse::<u64>()
+        .map_err(|e| BitcoinCliError::Other(format!("Invalid block count: {}", e)))
+}
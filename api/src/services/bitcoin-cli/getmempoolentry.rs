@@ -0,0 +1,10 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+pub async fn get_mempool_entry(cli: &CliExecutor, txid: &str) -> Result<Value> {
+    let args = vec!["getmempoolentry", txid];
+    let output = cli.execute(&args).await?;
+
+    let entry: Value = serde_json::from_slice(&output)?;
+    Ok(entry)
+}
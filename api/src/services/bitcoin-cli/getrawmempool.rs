@@ -0,0 +1,11 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+// Every mempool entry keyed by txid, as `getmempoolentry` reports them
+pub async fn get_raw_mempool_verbose(cli: &CliExecutor) -> Result<Value> {
+    let args = vec!["getrawmempool", "true"];
+    let output = cli.execute(&args).await?;
+
+    let mempool: Value = serde_json::from_slice(&output)?;
+    Ok(mempool)
+}
@@ -1,11 +1,11 @@
-use super::error::{execute_bitcoin_cli, Result};
+use super::error::{CliExecutor, Result};
 use serde_json::Value;
 
-pub async fn get_raw_transaction(txid: &str, verbose: bool) -> Result<Value> {
+pub async fn get_raw_transaction(cli: &CliExecutor, txid: &str, verbose: bool) -> Result<Value> {
     let verbose_arg = if verbose { "1" } else { "0" };
 
     let args = vec!["getrawtransaction", txid, verbose_arg];
-    let output = execute_bitcoin_cli(&args)?;
+    let output = cli.execute(&args).await?;
 
     if output.is_empty() {
         return Err(super::error::BitcoinCliError::Other(
@@ -1,9 +1,9 @@
-use super::error::{execute_bitcoin_cli, Result};
+use super::error::{CliExecutor, Result};
 use serde_json::Value;
 
-pub async fn get_transaction(txid: &str) -> Result<Value> {
+pub async fn get_transaction(cli: &CliExecutor, txid: &str) -> Result<Value> {
     let args = vec!["gettransaction", txid];
-    let output = execute_bitcoin_cli(&args)?;
+    let output = cli.execute(&args).await?;
 
     if output.is_empty() {
         return Err(super::error::BitcoinCliError::Other(
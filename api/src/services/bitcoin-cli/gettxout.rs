@@ -0,0 +1,23 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+// Get an unspent output, or None when it is spent or never existed
+pub async fn get_tx_out(
+    cli: &CliExecutor,
+    txid: &str,
+    vout: u32,
+    include_mempool: bool,
+) -> Result<Option<Value>> {
+    let vout_arg = vout.to_string();
+    let mempool_arg = if include_mempool { "true" } else { "false" };
+
+    let args = vec!["gettxout", txid, &vout_arg, mempool_arg];
+    let output = cli.execute(&args).await?;
+
+    // bitcoin-cli prints nothing for spent outputs
+    if output.iter().all(u8::is_ascii_whitespace) {
+        return Ok(None);
+    }
+
+    Ok(Some(serde_json::from_slice(&output)?))
+}
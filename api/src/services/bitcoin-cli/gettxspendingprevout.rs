@@ -0,0 +1,155 @@
+use super::error::{CliExecutor, Result};
+use crate::models::SpendStatus;
+use bitcoin::{OutPoint, Txid};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, Deserialize)]
+pub struct BTxSpendingPrevout {
+    /// Absent when no mempool transaction spends the output
+    #[serde(rename = "spendingtxid")]
+    pub spending_txid: Option<Txid>,
+}
+
+// Outputs in the form `gettxspendingprevout` takes them
+pub fn prevouts(outpoints: &[OutPoint]) -> Vec<Value> {
+    outpoints
+        .iter()
+        .map(|outpoint| json!({ "txid": outpoint.txid, "vout": outpoint.vout }))
+        .collect()
+}
+
+// Find the mempool transactions spending the given outputs
+pub async fn get_tx_spending_prevout(
+    cli: &CliExecutor,
+    outpoints: &[OutPoint],
+) -> Result<Vec<BTxSpendingPrevout>> {
+    let outputs_arg = serde_json::to_string(&prevouts(outpoints))?;
+    let args = vec!["gettxspendingprevout", &outputs_arg];
+    let output = cli.execute(&args).await?;
+
+    let spending: Vec<BTxSpendingPrevout> = serde_json::from_slice(&output)?;
+    Ok(spending)
+}
+
+// Transactions of the outputs that are neither unspent nor spent in the
+// mempool: only looking them up tells spent outputs from made-up ones
+pub fn unresolved_txids(
+    outpoints: &[OutPoint],
+    unspent: &[bool],
+    spending: &[BTxSpendingPrevout],
+) -> Vec<Txid> {
+    let mut spending = spending.iter();
+    let mut txids: Vec<Txid> = outpoints
+        .iter()
+        .zip(unspent)
+        .filter(|(_, unspent)| !**unspent)
+        .filter(|_| {
+            spending
+                .next()
+                .is_none_or(|prevout| prevout.spending_txid.is_none())
+        })
+        .map(|(outpoint, _)| outpoint.txid)
+        .collect();
+    txids.sort();
+    txids.dedup();
+    txids
+}
+
+// Status of each output from whether `gettxout` found it unspent, what
+// `gettxspendingprevout` said of the others, in order, and how many
+// outputs each unresolved transaction has, absent when the node does not
+// know it. What no mempool transaction spends went in a block, if the
+// output ever existed.
+pub fn spend_status(
+    outpoints: &[OutPoint],
+    unspent: &[bool],
+    spending: Vec<BTxSpendingPrevout>,
+    output_counts: &HashMap<Txid, usize>,
+) -> Vec<SpendStatus> {
+    let mut spending = spending.into_iter();
+    outpoints
+        .iter()
+        .zip(unspent)
+        .map(|(outpoint, unspent)| {
+            if *unspent {
+                return SpendStatus::Unspent;
+            }
+            if let Some(txid) = spending.next().and_then(|prevout| prevout.spending_txid) {
+                return SpendStatus::SpentInMempool {
+                    spending_txid: Some(txid),
+                };
+            }
+            match output_counts.get(&outpoint.txid) {
+                Some(count) if (outpoint.vout as usize) < *count => SpendStatus::SpentInBlock {
+                    spending_txid: None,
+                    block_height: None,
+                },
+                _ => SpendStatus::NotFound,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::hashes::Hash;
+
+    fn outpoint(n: u8, vout: u32) -> OutPoint {
+        OutPoint::new(Txid::from_byte_array([n; 32]), vout)
+    }
+
+    fn spent_by(n: Option<u8>) -> BTxSpendingPrevout {
+        BTxSpendingPrevout {
+            spending_txid: n.map(|n| Txid::from_byte_array([n; 32])),
+        }
+    }
+
+    #[test]
+    fn tells_spent_outputs_from_unknown_ones() {
+        let outpoints = [
+            outpoint(1, 0),
+            outpoint(2, 0),
+            outpoint(3, 1),
+            outpoint(4, 0),
+        ];
+        let unspent = [true, false, false, false];
+        let spending = vec![spent_by(Some(9)), spent_by(None), spent_by(None)];
+
+        // Only outputs no mempool transaction spends need their transaction
+        let txids = unresolved_txids(&outpoints, &unspent, &spending);
+        assert_eq!(txids, vec![outpoints[2].txid, outpoints[3].txid]);
+
+        // The third transaction has a single output, the fourth is unknown
+        let output_counts = HashMap::from([(outpoints[2].txid, 1)]);
+        let statuses = spend_status(&outpoints, &unspent, spending, &output_counts);
+        assert_eq!(
+            statuses,
+            vec![
+                SpendStatus::Unspent,
+                SpendStatus::SpentInMempool {
+                    spending_txid: Some(Txid::from_byte_array([9; 32])),
+                },
+                SpendStatus::NotFound,
+                SpendStatus::NotFound,
+            ]
+        );
+
+        let output_counts = HashMap::from([(outpoints[2].txid, 2)]);
+        let statuses = spend_status(
+            &outpoints,
+            &unspent,
+            vec![spent_by(Some(9)), spent_by(None), spent_by(None)],
+            &output_counts,
+        );
+        assert_eq!(
+            statuses[2],
+            SpendStatus::SpentInBlock {
+                spending_txid: None,
+                block_height: None,
+            }
+        );
+    }
+}
@@ -1,13 +1,19 @@
-use super::error::{execute_bitcoin_cli, Result};
+use super::error::{CliExecutor, Result};
+use super::get_block_count;
 use crate::models::Utxo;
+use bitcoin::{Address, Amount, ScriptBuf, Txid};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Serialize, Deserialize)]
-struct BListUnspentItem {
-    pub txid: String,
+pub struct BListUnspentItem {
+    pub txid: Txid,
     pub vout: u32,
     pub address: Option<String>,
-    pub amount: f64,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptBuf,
+    /// BTC on the wire, read exactly rather than through a float
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
     pub confirmations: u32,
     pub spendable: bool,
     pub solvable: bool,
@@ -16,7 +22,7 @@ struct BListUnspentItem {
 }
 
 // List unspent UTXOs
-pub async fn list_unspent(address: Option<&str>) -> Result<Vec<Utxo>> {
+pub async fn list_unspent(cli: &CliExecutor, address: Option<&Address>) -> Result<Vec<Utxo>> {
     // Use String for args
     let mut args: Vec<&str> = vec!["listunspent", "0"];
 
@@ -25,11 +31,11 @@ pub async fn list_unspent(address: Option<&str>) -> Result<Vec<Utxo>> {
     if let Some(addr) = address {
         args.push("9999999"); // max confirmations
                               // Format address array
-        address_arg = format!("[\"{}\"]", addr);
+        address_arg = serde_json::to_string(&[addr.to_string()])?;
         args.push(&address_arg);
     }
 
-    let output = execute_bitcoin_cli(&args)?;
+    let output = cli.execute(&args).await?;
 
     if output.is_empty() {
         tracing::warn!("bitcoin-cli returned empty output");
@@ -37,24 +43,30 @@ pub async fn list_unspent(address: Option<&str>) -> Result<Vec<Utxo>> {
     }
 
     let b_list_unspent: Vec<BListUnspentItem> = serde_json::from_slice(&output)?;
+    let tip_height = get_block_count(cli).await?;
 
-    // Convert to Utxo format
-    let utxos = b_list_unspent
+    Ok(to_utxos(b_list_unspent, tip_height))
+}
+
+// Convert listunspent items to Utxo format; `listunspent` has no block
+// heights, so they are worked out from the tip at `tip_height`
+pub fn to_utxos(items: Vec<BListUnspentItem>, tip_height: u64) -> Vec<Utxo> {
+    items
         .into_iter()
-        .map(|item| {
-            // Convert BTC to satoshis
-            let value = (item.amount * 100_000_000.0) as u64;
-
-            Utxo {
-                txid: item.txid,
-                vout: item.vout,
-                value,
-                status: crate::models::UtxoStatus {
-                    confirmed: item.confirmations > 0,
-                },
-            }
+        .map(|item| Utxo {
+            txid: item.txid,
+            vout: item.vout,
+            value: item.amount,
+            status: crate::models::UtxoStatus {
+                confirmed: item.confirmations > 0,
+                block_height: (item.confirmations > 0)
+                    .then(|| (tip_height + 1).saturating_sub(item.confirmations.into())),
+            },
+            script_pubkey: item.script_pubkey,
+            address: item.address,
+            confirmations: item.confirmations,
+            desc: item.desc,
+            assets: Default::default(),
         })
-        .collect();
-
-    Ok(utxos)
+        .collect()
 }
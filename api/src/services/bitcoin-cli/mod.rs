@@ -1,15 +1,39 @@
 mod error;
+mod estimatesmartfee;
+mod getaddressinfo;
+mod getblockcount;
+mod getmempoolentry;
+mod getrawmempool;
 mod getrawtransaction;
 mod gettransaction;
+mod gettxout;
+mod gettxspendingprevout;
 mod listunspent;
+mod scantxoutset;
 mod sendrawtransaction;
 mod signrawtransactionwithwallet;
 mod submitpackage;
+mod testmempoolaccept;
 
-pub use error::{BitcoinCliError, Result};
+pub use error::{
+    BitcoinCliError, CliExecutor, Result, RPC_CLIENT_NOT_CONNECTED, RPC_INVALID_ADDRESS_OR_KEY,
+    RPC_IN_WARMUP,
+};
+pub use estimatesmartfee::estimate_smart_fee;
+pub use getaddressinfo::get_address_info;
+pub use getblockcount::get_block_count;
+pub use getmempoolentry::get_mempool_entry;
+pub use getrawmempool::get_raw_mempool_verbose;
 pub use getrawtransaction::get_raw_transaction;
 pub use gettransaction::get_transaction;
-pub use listunspent::list_unspent;
+pub use gettxout::get_tx_out;
+pub use gettxspendingprevout::{get_tx_spending_prevout, prevouts, spend_status, unresolved_txids};
+pub use listunspent::{list_unspent, to_utxos, BListUnspentItem};
+pub use scantxoutset::{
+    address_descriptors, group_by_address, ranged_descriptor, scan_tx_out_set, scan_utxos,
+    BScanTxOutSet,
+};
 pub use sendrawtransaction::send_raw_transaction;
-pub use signrawtransactionwithwallet::sign_and_broadcast_transaction;
+pub use signrawtransactionwithwallet::sign_raw_transaction_with_wallet;
 pub use submitpackage::submit_package;
+pub use testmempoolaccept::test_mempool_accept;
@@ -0,0 +1,88 @@
+use super::error::{CliExecutor, Result};
+use crate::models::{Utxo, UtxoStatus};
+use bitcoin::{Address, Amount, ScriptBuf, Txid};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BScanTxOutSet {
+    /// Height of the chain tip the scan ran at
+    pub height: u64,
+    pub unspents: Vec<BScanTxOutItem>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BScanTxOutItem {
+    pub txid: Txid,
+    pub vout: u32,
+    #[serde(rename = "scriptPubKey")]
+    pub script_pubkey: ScriptBuf,
+    pub desc: Option<String>,
+    #[serde(with = "bitcoin::amount::serde::as_btc")]
+    pub amount: Amount,
+    pub height: u64,
+}
+
+// Scan the UTXO set for outputs of the given descriptors, without the
+// node wallet. Each is a descriptor string or a ranged descriptor object.
+pub async fn scan_tx_out_set<D: Serialize>(
+    cli: &CliExecutor,
+    descriptors: &[D],
+) -> Result<BScanTxOutSet> {
+    let descriptors_arg = serde_json::to_string(descriptors)?;
+    let args = vec!["scantxoutset", "start", &descriptors_arg];
+    let output = cli.execute(&args).await?;
+
+    let scan: BScanTxOutSet = serde_json::from_slice(&output)?;
+    Ok(scan)
+}
+
+// `addr()` descriptors to scan for each address
+pub fn address_descriptors(addresses: &[Address]) -> Vec<String> {
+    addresses
+        .iter()
+        .map(|address| format!("addr({})", address))
+        .collect()
+}
+
+// Scan object for the first `count` indexes of a ranged descriptor
+pub fn ranged_descriptor(descriptor: &str, count: u32) -> Value {
+    json!({ "desc": descriptor, "range": [0, count.saturating_sub(1)] })
+}
+
+// Outputs found by a scan, for no address in particular. The UTXO set
+// only holds mined outputs, so every one is confirmed.
+pub fn scan_utxos(scan: BScanTxOutSet) -> Vec<Utxo> {
+    scan.unspents
+        .into_iter()
+        .map(|item| Utxo {
+            txid: item.txid,
+            vout: item.vout,
+            value: item.amount,
+            status: UtxoStatus {
+                confirmed: true,
+                block_height: Some(item.height),
+            },
+            script_pubkey: item.script_pubkey,
+            address: None,
+            confirmations: (scan.height + 1).saturating_sub(item.height) as u32,
+            desc: item.desc,
+            assets: Default::default(),
+        })
+        .collect()
+}
+
+// Group a scan by address, in the order of `addresses`
+pub fn group_by_address(scan: BScanTxOutSet, addresses: &[Address]) -> Vec<Vec<Utxo>> {
+    let scripts: Vec<ScriptBuf> = addresses.iter().map(Address::script_pubkey).collect();
+    let mut grouped: Vec<Vec<Utxo>> = addresses.iter().map(|_| Vec::new()).collect();
+
+    for mut utxo in scan_utxos(scan) {
+        let Some(i) = scripts.iter().position(|s| *s == utxo.script_pubkey) else {
+            continue;
+        };
+        utxo.address = Some(addresses[i].to_string());
+        grouped[i].push(utxo);
+    }
+    grouped
+}
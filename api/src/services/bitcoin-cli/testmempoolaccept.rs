@@ -0,0 +1,14 @@
+use super::error::{CliExecutor, Result};
+use serde_json::Value;
+
+// Check raw transactions (parents first) against mempool policy without
+// broadcasting them
+pub async fn test_mempool_accept(cli: &CliExecutor, tx_hexes: &[String]) -> Result<Vec<Value>> {
+    let json_array = serde_json::to_string(tx_hexes)?;
+
+    let args = vec!["testmempoolaccept", &json_array];
+    let output = cli.execute(&args).await?;
+
+    let results: Vec<Value> = serde_json::from_slice(&output)?;
+    Ok(results)
+}
@@ -1,12 +1,15 @@
-use super::client::get_rpc_client;
+use super::client::RpcClient;
 use crate::error::{WalletError, WalletResult};
-use bitcoincore_rpc::RpcApi;
+use bitcoin::{address::NetworkUnchecked, Address};
 
 // Get change address
-pub fn get_change_address() -> WalletResult<String> {
-    let rpc_client = get_rpc_client()?;
-    let address = rpc_client
-        .get_new_address(None, None)
+pub async fn get_change_address(
+    rpc_client: &RpcClient,
+    wallet: Option<&str>,
+) -> WalletResult<String> {
+    let address: Address<NetworkUnchecked> = rpc_client
+        .call(wallet, "getnewaddress", &[])
+        .await
         .map_err(|e| WalletError::BitcoinError(format!("Failed to get change address: {}", e)))?;
 
     Ok(address.assume_checked().to_string())
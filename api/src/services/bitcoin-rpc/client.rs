@@ -1,17 +1,213 @@
+use crate::config::{LimitsConfig, RpcConfig};
 use crate::error::{WalletError, WalletResult};
-use bitcoincore_rpc::{Auth, Client as RpcClient};
-use std::env;
-
-// Create Bitcoin RPC client
-pub fn get_rpc_client() -> WalletResult<RpcClient> {
-    let host = env::var("BITCOIN_RPC_HOST").unwrap_or_else(|_| "localhost".to_string());
-    let port = env::var("BITCOIN_RPC_PORT").unwrap_or_else(|_| "48332".to_string());
-    let user = env::var("BITCOIN_RPC_USER").unwrap_or_else(|_| "hello".to_string());
-    let password = env::var("BITCOIN_RPC_PASSWORD").unwrap_or_else(|_| "world".to_string());
-
-    RpcClient::new(
-        &format!("http://{}:{}", host, port),
-        Auth::UserPass(user, password),
-    )
-    .map_err(|e| WalletError::BitcoinError(e.to_string()))
+use reqwest::StatusCode;
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{
+    path::PathBuf,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        RwLock,
+    },
+};
+
+/// How the client authenticates to bitcoind
+#[derive(Debug, Clone)]
+pub enum RpcAuth {
+    UserPass(String, String),
+    /// Path to the node's `.cookie` file, re-read when the node restarts
+    CookieFile(PathBuf),
+}
+
+/// Async JSON-RPC client for bitcoind.
+///
+/// One instance is shared by the whole process so connections are kept
+/// alive; wallet-scoped calls go to `/wallet/<name>` on the same pool.
+pub struct RpcClient {
+    http: reqwest::Client,
+    url: String,
+    auth: RpcAuth,
+    credentials: RwLock<Option<(String, String)>>,
+    next_id: AtomicU64,
+}
+
+impl RpcClient {
+    pub fn new(url: &str, auth: RpcAuth, limits: &LimitsConfig) -> WalletResult<Self> {
+        let http = reqwest::Client::builder()
+            .connect_timeout(limits.chain_connect_timeout())
+            .timeout(limits.chain_timeout())
+            .build()
+            .map_err(|e| WalletError::NetworkError(format!("Failed to build RPC client: {}", e)))?;
+
+        Ok(Self {
+            http,
+            url: url.trim_end_matches('/').to_string(),
+            auth,
+            credentials: RwLock::new(None),
+            next_id: AtomicU64::new(0),
+        })
+    }
+
+    /// Build a client from the `[rpc]` config section, preferring the cookie
+    /// file over user/password
+    pub fn from_config(config: &RpcConfig, limits: &LimitsConfig) -> WalletResult<Self> {
+        let auth = match (&config.cookie, &config.user, &config.password) {
+            (Some(cookie), _, _) => RpcAuth::CookieFile(PathBuf::from(cookie)),
+            (None, Some(user), Some(password)) => RpcAuth::UserPass(user.clone(), password.clone()),
+            _ => {
+                return Err(WalletError::NetworkError(
+                    "Set rpc.cookie or rpc.user and rpc.password".to_string(),
+                ))
+            }
+        };
+
+        Self::new(
+            &format!("http://{}:{}", config.host, config.port),
+            auth,
+            limits,
+        )
+    }
+
+    fn credentials(&self, refresh: bool) -> WalletResult<(String, String)> {
+        if !refresh {
+            if let Some(credentials) = self.credentials.read().unwrap().clone() {
+                return Ok(credentials);
+            }
+        }
+
+        let credentials = match &self.auth {
+            RpcAuth::UserPass(user, password) => (user.clone(), password.clone()),
+            RpcAuth::CookieFile(path) => {
+                let cookie = std::fs::read_to_string(path).map_err(|e| {
+                    WalletError::NetworkError(format!(
+                        "Failed to read RPC cookie {}: {}",
+                        path.display(),
+                        e
+                    ))
+                })?;
+                let (user, password) = cookie.trim().split_once(':').ok_or_else(|| {
+                    WalletError::NetworkError(format!("Malformed RPC cookie {}", path.display()))
+                })?;
+                (user.to_string(), password.to_string())
+            }
+        };
+
+        *self.credentials.write().unwrap() = Some(credentials.clone());
+        Ok(credentials)
+    }
+
+    fn endpoint(&self, wallet: Option<&str>) -> String {
+        match wallet {
+            Some(wallet) => format!("{}/wallet/{}", self.url, wallet),
+            None => self.url.clone(),
+        }
+    }
+
+    async fn post(&self, wallet: Option<&str>, body: &Value) -> WalletResult<Value> {
+        let mut refresh = false;
+        loop {
+            let (user, password) = self.credentials(refresh)?;
+            let response = self
+                .http
+                .post(self.endpoint(wallet))
+                .basic_auth(user, Some(password))
+                .json(body)
+                .send()
+                .await
+                .map_err(|e| WalletError::NetworkError(format!("RPC request failed: {}", e)))?;
+
+            // A restarted node writes a new cookie; retry once with it
+            if response.status() == StatusCode::UNAUTHORIZED {
+                if !refresh && matches!(self.auth, RpcAuth::CookieFile(_)) {
+                    refresh = true;
+                    continue;
+                }
+                return Err(WalletError::NetworkError(
+                    "RPC authentication failed".to_string(),
+                ));
+            }
+
+            // bitcoind reports RPC errors with non-2xx statuses and a JSON body
+            let status = response.status();
+            let text = response.text().await.map_err(|e| {
+                WalletError::NetworkError(format!("Failed to read RPC response: {}", e))
+            })?;
+            return serde_json::from_str(&text).map_err(|_| {
+                WalletError::NetworkError(format!("RPC returned {}: {}", status, text))
+            });
+        }
+    }
+
+    fn request(&self, method: &str, params: &[Value]) -> Value {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        json!({ "jsonrpc": "1.0", "id": id, "method": method, "params": params })
+    }
+
+    /// Call a method, on the node or on a loaded wallet
+    pub async fn call<T: DeserializeOwned>(
+        &self,
+        wallet: Option<&str>,
+        method: &str,
+        params: &[Value],
+    ) -> WalletResult<T> {
+        let response = self.post(wallet, &self.request(method, params)).await?;
+        parse_result(method, response)
+    }
+
+    /// Send several calls in one JSON-RPC batch; results keep the call order
+    pub async fn batch(
+        &self,
+        wallet: Option<&str>,
+        calls: &[(&str, Vec<Value>)],
+    ) -> WalletResult<Vec<WalletResult<Value>>> {
+        if calls.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let requests: Vec<Value> = calls
+            .iter()
+            .map(|(method, params)| self.request(method, params))
+            .collect();
+        let response = self.post(wallet, &Value::Array(requests.clone())).await?;
+
+        let responses = match response {
+            Value::Array(responses) => responses,
+            other => {
+                return Err(WalletError::NetworkError(format!(
+                    "Invalid RPC batch response: {}",
+                    other
+                )))
+            }
+        };
+
+        // Replies may come back in any order; match them up by id
+        Ok(requests
+            .iter()
+            .zip(calls)
+            .map(|(request, (method, _))| {
+                responses
+                    .iter()
+                    .find(|response| response["id"] == request["id"])
+                    .cloned()
+                    .ok_or_else(|| {
+                        WalletError::NetworkError(format!("Missing RPC batch reply for {}", method))
+                    })
+                    .and_then(|response| parse_result(method, response))
+            })
+            .collect())
+    }
+}
+
+fn parse_result<T: DeserializeOwned>(method: &str, response: Value) -> WalletResult<T> {
+    if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+        return Err(WalletError::BitcoinError(format!(
+            "{} failed: {} (code {})",
+            method,
+            error["message"].as_str().unwrap_or("unknown error"),
+            error["code"]
+        )));
+    }
+
+    serde_json::from_value(response["result"].clone())
+        .map_err(|e| WalletError::NetworkError(format!("Invalid {} result: {}", method, e)))
 }
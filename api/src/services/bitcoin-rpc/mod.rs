@@ -4,6 +4,6 @@ mod parse_outpoint;
 mod prev_txs;
 
 pub use change_address::get_change_address;
-pub use client::get_rpc_client;
+pub use client::RpcClient;
 pub use parse_outpoint::parse_outpoint;
 pub use prev_txs::get_prev_txs;
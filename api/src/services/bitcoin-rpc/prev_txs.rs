@@ -1,21 +1,17 @@
-use super::client::get_rpc_client;
-use crate::error::{WalletError, WalletResult};
-use bitcoin::Transaction;
-use bitcoincore_rpc::RpcApi;
+use crate::error::WalletResult;
+use crate::services::chain::ChainBackend;
+use bitcoin::{Transaction, Txid};
 
 // Get previous transactions
-pub fn get_prev_txs(tx: &Transaction) -> WalletResult<Vec<Transaction>> {
-    let rpc_client = get_rpc_client()?;
-    let mut prev_txs = Vec::new();
+pub async fn get_prev_txs(
+    chain: &dyn ChainBackend,
+    tx: &Transaction,
+) -> WalletResult<Vec<Transaction>> {
+    let txids: Vec<Txid> = tx
+        .input
+        .iter()
+        .map(|input| input.previous_output.txid)
+        .collect();
 
-    for input in &tx.input {
-        let raw_tx = rpc_client
-            .get_raw_transaction(&input.previous_output.txid, None)
-            .map_err(|e| {
-                WalletError::BitcoinError(format!("Failed to get raw transaction: {}", e))
-            })?;
-        prev_txs.push(raw_tx);
-    }
-
-    Ok(prev_txs)
+    chain.get_transactions(&txids).await
 }
@@ -0,0 +1,220 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::*;
+use crate::services::chain::{ChainBackend, MempoolAccept};
+use crate::services::preflight;
+use crate::services::signing::SigningPolicy;
+use bitcoin::consensus::encode::{deserialize_hex, serialize_hex};
+use bitcoin::{Amount, Transaction, Txid};
+use serde_json::Value;
+use std::collections::HashSet;
+
+// Decode a transaction hex, rejecting malformed input before it reaches the backend
+fn decode_tx(tx_hex: &str) -> WalletResult<Transaction> {
+    deserialize_hex(tx_hex)
+        .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+}
+
+// Order transactions so that parents come before their children, keeping the
+// given order otherwise
+fn sort_topologically(txs: Vec<Transaction>) -> WalletResult<Vec<Transaction>> {
+    let mut pending: HashSet<Txid> = txs.iter().map(Transaction::compute_txid).collect();
+    if pending.len() != txs.len() {
+        return Err(WalletError::BitcoinError(
+            "Transaction package contains duplicates".to_string(),
+        ));
+    }
+
+    let mut remaining = txs;
+    let mut sorted = Vec::with_capacity(remaining.len());
+    while !remaining.is_empty() {
+        let ready = remaining
+            .iter()
+            .position(|tx| {
+                tx.input
+                    .iter()
+                    .all(|input| !pending.contains(&input.previous_output.txid))
+            })
+            .ok_or_else(|| {
+                WalletError::BitcoinError("Transaction package cannot be ordered".to_string())
+            })?;
+        let tx = remaining.remove(ready);
+        pending.remove(&tx.compute_txid());
+        sorted.push(tx);
+    }
+    Ok(sorted)
+}
+
+// Package relay only accepts one child together with its parents
+fn check_child_with_parents(txs: &[Transaction]) -> WalletResult<()> {
+    let Some((child, parents)) = txs.split_last() else {
+        return Ok(());
+    };
+    let spent: HashSet<Txid> = child.input.iter().map(|i| i.previous_output.txid).collect();
+
+    match parents.iter().find(|p| !spent.contains(&p.compute_txid())) {
+        Some(parent) => Err(WalletError::BitcoinError(format!(
+            "Transaction package must be one child with its parents, but {} is not spent by {}",
+            parent.compute_txid(),
+            child.compute_txid()
+        ))),
+        None => Ok(()),
+    }
+}
+
+// Per-transaction results of `submitpackage`, failing when the node
+// rejected any of them
+fn package_results(response: &Value) -> WalletResult<Option<Vec<MempoolAccept>>> {
+    let results: Option<Vec<MempoolAccept>> = response["tx-results"]
+        .as_object()
+        .map(|results| results.values().map(MempoolAccept::from_node).collect());
+
+    match response["package_msg"].as_str() {
+        Some(msg) if msg != "success" => {
+            let errors: Vec<String> = results
+                .iter()
+                .flatten()
+                .filter_map(|r| Some(format!("{}: {}", r.txid, r.reject_reason.as_ref()?)))
+                .collect();
+            Err(WalletError::BitcoinError(format!(
+                "Package rejected: {} {}",
+                msg,
+                errors.join("; ")
+            )))
+        }
+        _ => Ok(results),
+    }
+}
+
+/// Broadcast one or more signed transactions.
+///
+/// Transactions are sorted parents first and, when there are several,
+/// must form one child with its parents. They then go through the
+/// [`preflight`] checks, which refuse to burn charms unless
+/// `allow_charm_burn` is set. A single transaction goes out with
+/// `sendrawtransaction`, a package with `submitpackage`.
+pub async fn broadcast_transactions(
+    chain: &dyn ChainBackend,
+    txs: Vec<Transaction>,
+    allow_charm_burn: bool,
+) -> WalletResult<BroadcastTxResponse> {
+    if txs.is_empty() {
+        return Err(WalletError::BitcoinError(
+            "No transaction to broadcast".to_string(),
+        ));
+    }
+
+    let txs = sort_topologically(txs)?;
+    check_child_with_parents(&txs)?;
+    let hexes: Vec<String> = txs.iter().map(serialize_hex).collect();
+    let txids: Vec<Txid> = txs.iter().map(Transaction::compute_txid).collect();
+    let is_package = txs.len() > 1;
+
+    let preflight = preflight::check(chain, &txs, &hexes, allow_charm_burn).await?;
+
+    let (command, node_response, node_results) = if is_package {
+        let response = chain.submit_package(&hexes).await?;
+        let json_array = serde_json::to_string(&hexes).unwrap_or_default();
+        (
+            format!("{} submitpackage '{}'", chain.name(), json_array),
+            format!(
+                "Transaction package broadcast successful, first txid: {}",
+                txids[0]
+            ),
+            package_results(&response)?.or(preflight.accepted),
+        )
+    } else {
+        let txid = chain.broadcast(&hexes[0]).await?;
+        (
+            format!("{} sendrawtransaction {}", chain.name(), hexes[0]),
+            format!("Transaction broadcast successful, txid: {}", txid),
+            preflight.accepted,
+        )
+    };
+
+    let node_results = node_results.unwrap_or_default();
+    let results: Vec<BroadcastTxResult> = txs
+        .iter()
+        .zip(&txids)
+        .zip(preflight.fees)
+        .map(|((tx, txid), fee)| {
+            let node = node_results.iter().find(|r| r.txid == txid.to_string());
+            let vsize = node.and_then(|r| r.vsize).unwrap_or(tx.vsize() as u64);
+            BroadcastTxResult {
+                txid: *txid,
+                vsize,
+                fee: Amount::from_sat(fee),
+                fee_rate: fee as f64 / vsize as f64,
+                effective_fee_rate: node.and_then(|r| r.effective_fee_rate),
+            }
+        })
+        .collect();
+    let total_fee: u64 = results.iter().map(|r| r.fee.to_sat()).sum();
+    let total_vsize: u64 = results.iter().map(|r| r.vsize).sum();
+
+    Ok(BroadcastTxResponse {
+        txid: txids[0],
+        command,
+        node_response: Some(node_response),
+        txids: is_package.then_some(txids),
+        results,
+        package_fee_rate: total_fee as f64 / total_vsize as f64,
+    })
+}
+
+// Broadcast the transaction or package of a request
+pub async fn send_raw_transaction(
+    chain: &dyn ChainBackend,
+    request: &BroadcastTxRequest,
+) -> WalletResult<BroadcastTxResponse> {
+    broadcast_transactions(chain, request.transactions(), request.allow_charm_burn).await
+}
+
+// Sign with the node wallet, if the signing policy approves, and broadcast
+pub async fn sign_and_broadcast_transaction(
+    chain: &dyn ChainBackend,
+    policy: &SigningPolicy,
+    api_key: Option<&str>,
+    request: &SendHexTxRequest,
+) -> WalletResult<BroadcastTxResponse> {
+    // Validate transaction
+    let tx = decode_tx(&request.tx_hex)?;
+    let approval = policy.approve(chain, &tx, api_key).await?;
+
+    // Sign tx
+    let sign_command = format!(
+        "{} signrawtransactionwithwallet {}",
+        chain.name(),
+        request.tx_hex
+    );
+    let signed = async {
+        let signed_tx = decode_tx(&chain.sign_with_wallet(&request.tx_hex).await?)?;
+
+        // Broadcast signed tx
+        broadcast_transactions(chain, vec![signed_tx], false).await
+    }
+    .await;
+    // Nothing left the wallet, so the approved spend is handed back
+    let broadcast_result = match signed {
+        Ok(result) => result,
+        Err(e) => {
+            policy.release(approval);
+            return Err(e);
+        }
+    };
+
+    // Get transaction ID
+    let txid = broadcast_result.txid;
+    let response_str = format!(
+        "Transaction signed and broadcast successfully, txid: {}",
+        txid
+    );
+
+    Ok(BroadcastTxResponse {
+        txid,
+        command: format!("{} && {}", sign_command, broadcast_result.command),
+        node_response: Some(response_str),
+        txids: None,
+        results: broadcast_result.results,
+        package_fee_rate: broadcast_result.package_fee_rate,
+    })
+}
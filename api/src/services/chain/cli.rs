@@ -0,0 +1,179 @@
+use super::{fee_histogram, AncestorPackage, ChainBackend, MempoolAccept};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo};
+use crate::services::bitcoin_cli::{
+    self, BitcoinCliError, CliExecutor, RPC_INVALID_ADDRESS_OR_KEY,
+};
+use async_trait::async_trait;
+use bitcoin::{consensus::encode::deserialize_hex, Address, OutPoint, Transaction, Txid};
+use futures::stream::{self, StreamExt};
+use serde_json::Value;
+use std::{collections::HashMap, str::FromStr};
+
+// bitcoin-cli processes run at once for calls made per item
+const CONCURRENT_CALLS: usize = 8;
+
+/// Backend that shells out to the local `bitcoin-cli` binary
+pub struct CliBackend {
+    cli: CliExecutor,
+}
+
+impl CliBackend {
+    pub fn new(cli: CliExecutor) -> Self {
+        Self { cli }
+    }
+}
+
+#[async_trait]
+impl ChainBackend for CliBackend {
+    fn name(&self) -> &'static str {
+        "bitcoin-cli"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let raw = self.get_raw_transaction(txid, false).await?;
+        let hex = raw
+            .as_str()
+            .ok_or_else(|| WalletError::BitcoinError("Expected raw transaction hex".into()))?;
+
+        deserialize_hex(hex)
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        Ok(bitcoin_cli::get_raw_transaction(&self.cli, &txid.to_string(), verbose).await?)
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        Ok(bitcoin_cli::get_transaction(&self.cli, &txid.to_string()).await?)
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        Ok(bitcoin_cli::list_unspent(&self.cli, Some(address)).await?)
+    }
+
+    // One scan of the UTXO set, so the addresses need not be in the wallet,
+    // but outputs still in the mempool are not seen
+    async fn list_unspent_many(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        let descriptors = bitcoin_cli::address_descriptors(addresses);
+        let scan = bitcoin_cli::scan_tx_out_set(&self.cli, &descriptors).await?;
+        Ok(bitcoin_cli::group_by_address(scan, addresses))
+    }
+
+    async fn list_unspent_descriptor(
+        &self,
+        descriptor: &str,
+        count: u32,
+    ) -> WalletResult<Option<Vec<Utxo>>> {
+        let range = bitcoin_cli::ranged_descriptor(descriptor, count);
+        let scan = bitcoin_cli::scan_tx_out_set(&self.cli, &[range]).await?;
+        Ok(Some(bitcoin_cli::scan_utxos(scan)))
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        let tx_out =
+            bitcoin_cli::get_tx_out(&self.cli, &outpoint.txid.to_string(), outpoint.vout, true)
+                .await?;
+        Ok(tx_out.is_some())
+    }
+
+    // `gettxout` takes one output per call and bitcoin-cli cannot batch,
+    // so every outpoint costs a process, a few at a time. Large batches are
+    // far cheaper through the bitcoin-rpc backend.
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        let unspent = stream::iter(outpoints.iter().copied())
+            .map(|outpoint| async move { self.is_unspent(&outpoint).await })
+            .buffered(CONCURRENT_CALLS)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<WalletResult<Vec<bool>>>()?;
+
+        let spent: Vec<OutPoint> = outpoints
+            .iter()
+            .zip(&unspent)
+            .filter(|(_, unspent)| !**unspent)
+            .map(|(outpoint, _)| *outpoint)
+            .collect();
+        let spending = if spent.is_empty() {
+            Vec::new()
+        } else {
+            bitcoin_cli::get_tx_spending_prevout(&self.cli, &spent).await?
+        };
+
+        let txids = bitcoin_cli::unresolved_txids(outpoints, &unspent, &spending);
+        let output_counts = stream::iter(txids)
+            .map(|txid| async move {
+                match bitcoin_cli::get_raw_transaction(&self.cli, &txid.to_string(), true).await {
+                    Ok(tx) => Ok(Some((txid, tx["vout"].as_array().map_or(0, Vec::len)))),
+                    Err(BitcoinCliError::Rpc { code, .. })
+                        if code == RPC_INVALID_ADDRESS_OR_KEY =>
+                    {
+                        Ok(None)
+                    }
+                    Err(e) => Err(WalletError::from(e)),
+                }
+            })
+            .buffered(CONCURRENT_CALLS)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .filter_map(Result::transpose)
+            .collect::<WalletResult<HashMap<_, _>>>()?;
+
+        Ok(bitcoin_cli::spend_status(
+            outpoints,
+            &unspent,
+            spending,
+            &output_counts,
+        ))
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        let txid = bitcoin_cli::send_raw_transaction(&self.cli, tx_hex).await?;
+
+        Txid::from_str(&txid)
+            .map_err(|e| WalletError::BitcoinError(format!("Invalid txid from node: {}", e)))
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        Ok(bitcoin_cli::submit_package(&self.cli, tx_hexes).await?)
+    }
+
+    async fn test_mempool_accept(
+        &self,
+        tx_hexes: &[String],
+    ) -> WalletResult<Option<Vec<MempoolAccept>>> {
+        let results = bitcoin_cli::test_mempool_accept(&self.cli, tx_hexes).await?;
+        Ok(Some(results.iter().map(MempoolAccept::from_node).collect()))
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>> {
+        // bitcoind reports BTC/kvB
+        let btc_per_kvb = bitcoin_cli::estimate_smart_fee(&self.cli, conf_target).await?;
+        Ok(btc_per_kvb.map(|rate| rate * 100_000.0))
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        let mempool = bitcoin_cli::get_raw_mempool_verbose(&self.cli).await?;
+        Ok(Some(fee_histogram(&mempool)))
+    }
+
+    async fn mempool_ancestors(&self, txid: &Txid) -> WalletResult<AncestorPackage> {
+        let entry = bitcoin_cli::get_mempool_entry(&self.cli, &txid.to_string()).await?;
+        AncestorPackage::from_mempool_entry(&entry)
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        Ok(bitcoin_cli::get_block_count(&self.cli).await?)
+    }
+
+    async fn sign_with_wallet(&self, tx_hex: &str) -> WalletResult<String> {
+        Ok(bitcoin_cli::sign_raw_transaction_with_wallet(&self.cli, tx_hex).await?)
+    }
+
+    async fn is_wallet_address(&self, address: &Address) -> WalletResult<bool> {
+        let info = bitcoin_cli::get_address_info(&self.cli, &address.to_string()).await?;
+        Ok(info["ismine"].as_bool().unwrap_or(false))
+    }
+}
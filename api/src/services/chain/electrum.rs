@@ -0,0 +1,658 @@
+use super::{confirmations, ChainBackend};
+use crate::config::LimitsConfig;
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo, UtxoStatus};
+use async_trait::async_trait;
+use bitcoin::{
+    consensus::encode::deserialize_hex,
+    hashes::{sha256, Hash},
+    Address, Amount, OutPoint, Script, ScriptBuf, Transaction, Txid,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    future::Future,
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::{
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+        TcpStream,
+    },
+    sync::Mutex,
+    time::timeout,
+};
+
+/// Backend for Electrum protocol servers (electrs, Fulcrum) over plain TCP.
+///
+/// Unlike `listunspent` on a node wallet this works for any address, since
+/// the server indexes every script.
+pub struct ElectrumBackend {
+    addr: String,
+    connect_timeout: Duration,
+    read_timeout: Duration,
+    conn: Mutex<Option<Connection>>,
+}
+
+struct Connection {
+    reader: BufReader<OwnedReadHalf>,
+    writer: OwnedWriteHalf,
+    next_id: u64,
+    /// Limit on each write and read, so a silent server fails the call
+    /// instead of holding the connection forever
+    read_timeout: Duration,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElectrumUnspent {
+    tx_hash: Txid,
+    tx_pos: u32,
+    height: i64,
+    value: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ElectrumHistoryItem {
+    tx_hash: Txid,
+    height: i64,
+}
+
+/// Electrum script hash: sha256 of the script, byte-reversed, as hex
+pub fn script_hash(script: &Script) -> String {
+    let mut hash = sha256::Hash::hash(script.as_bytes()).to_byte_array();
+    hash.reverse();
+    hex::encode(hash)
+}
+
+// Fails with a network error, which drops the connection, once `limit` passes
+async fn within<T>(
+    limit: Duration,
+    what: &str,
+    io: impl Future<Output = WalletResult<T>>,
+) -> WalletResult<T> {
+    timeout(limit, io).await.unwrap_or_else(|_| {
+        Err(WalletError::NetworkError(format!(
+            "Electrum {} timed out after {:?}",
+            what, limit
+        )))
+    })
+}
+
+impl Connection {
+    async fn open(
+        addr: &str,
+        connect_timeout: Duration,
+        read_timeout: Duration,
+    ) -> WalletResult<Self> {
+        let stream = within(connect_timeout, "connect", async {
+            TcpStream::connect(addr).await.map_err(|e| {
+                WalletError::NetworkError(format!("Failed to connect to Electrum server: {}", e))
+            })
+        })
+        .await?;
+        let (reader, writer) = stream.into_split();
+
+        let mut conn = Self {
+            reader: BufReader::new(reader),
+            writer,
+            next_id: 0,
+            read_timeout,
+        };
+        // Servers expect the version handshake before anything else
+        conn.request("server.version", json!(["charms-wallet-api", "1.4"]))
+            .await?;
+        Ok(conn)
+    }
+
+    async fn request(&mut self, method: &str, params: Value) -> WalletResult<Value> {
+        self.next_id += 1;
+        let id = self.next_id;
+        let mut line =
+            json!({ "jsonrpc": "2.0", "id": id, "method": method, "params": params }).to_string();
+        line.push('\n');
+
+        within(self.read_timeout, "write", async {
+            self.writer
+                .write_all(line.as_bytes())
+                .await
+                .map_err(|e| WalletError::NetworkError(format!("Electrum write failed: {}", e)))
+        })
+        .await?;
+
+        loop {
+            let mut response = String::new();
+            let read = within(self.read_timeout, "read", async {
+                self.reader
+                    .read_line(&mut response)
+                    .await
+                    .map_err(|e| WalletError::NetworkError(format!("Electrum read failed: {}", e)))
+            })
+            .await?;
+            if read == 0 {
+                return Err(WalletError::NetworkError(
+                    "Electrum server closed the connection".to_string(),
+                ));
+            }
+
+            let response: Value = serde_json::from_str(&response).map_err(|e| {
+                WalletError::NetworkError(format!("Invalid Electrum response: {}", e))
+            })?;
+            // Skip subscription notifications and stale replies
+            if response["id"].as_u64() != Some(id) {
+                continue;
+            }
+
+            if let Some(error) = response.get("error").filter(|e| !e.is_null()) {
+                let message = error["message"]
+                    .as_str()
+                    .map(str::to_string)
+                    .unwrap_or_else(|| error.to_string());
+                return Err(WalletError::BitcoinError(format!(
+                    "{} failed: {}",
+                    method, message
+                )));
+            }
+
+            return Ok(response["result"].clone());
+        }
+    }
+}
+
+impl ElectrumBackend {
+    /// `addr` is the server's `host:port`
+    pub fn new(addr: &str, limits: &LimitsConfig) -> Self {
+        Self {
+            addr: addr.trim_start_matches("tcp://").to_string(),
+            connect_timeout: limits.chain_connect_timeout(),
+            read_timeout: limits.chain_timeout(),
+            conn: Mutex::new(None),
+        }
+    }
+
+    // Calls are serialized over one connection, reopened after any failure
+    async fn call<T: serde::de::DeserializeOwned>(
+        &self,
+        method: &str,
+        params: Value,
+    ) -> WalletResult<T> {
+        let mut guard = self.conn.lock().await;
+        if guard.is_none() {
+            *guard =
+                Some(Connection::open(&self.addr, self.connect_timeout, self.read_timeout).await?);
+        }
+
+        let result = guard.as_mut().unwrap().request(method, params).await;
+        if let Err(WalletError::NetworkError(_)) = &result {
+            *guard = None;
+        }
+
+        serde_json::from_value(result?)
+            .map_err(|e| WalletError::NetworkError(format!("Invalid {} result: {}", method, e)))
+    }
+
+    // Confirmation height of a transaction, looked up through its first output's history
+    async fn confirmation_height(&self, tx: &Transaction) -> WalletResult<Option<u64>> {
+        let txid = tx.compute_txid();
+        let Some(output) = tx.output.first() else {
+            return Ok(None);
+        };
+
+        let history: Vec<ElectrumHistoryItem> = self
+            .call(
+                "blockchain.scripthash.get_history",
+                json!([script_hash(&output.script_pubkey)]),
+            )
+            .await?;
+
+        Ok(history
+            .into_iter()
+            .find(|item| item.tx_hash == txid)
+            .filter(|item| item.height > 0)
+            .map(|item| item.height as u64))
+    }
+
+    async fn status_json(&self, tx: &Transaction) -> WalletResult<Value> {
+        let height = self.confirmation_height(tx).await?;
+        let confirmations = match height {
+            Some(height) => self.tip_height().await?.saturating_sub(height) + 1,
+            None => 0,
+        };
+
+        Ok(json!({
+            "txid": tx.compute_txid(),
+            "confirmed": height.is_some(),
+            "confirmations": confirmations,
+            "blockheight": height,
+        }))
+    }
+}
+
+#[async_trait]
+impl ChainBackend for ElectrumBackend {
+    fn name(&self) -> &'static str {
+        "electrum"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let hex: String = self
+            .call("blockchain.transaction.get", json!([txid]))
+            .await?;
+
+        deserialize_hex(&hex)
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        let hex: String = self
+            .call("blockchain.transaction.get", json!([txid]))
+            .await?;
+        if !verbose {
+            return Ok(Value::String(hex));
+        }
+
+        // electrs has no verbose mode, so decode locally
+        let tx: Transaction = deserialize_hex(&hex)
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))?;
+        let mut info = self.status_json(&tx).await?;
+        info["hex"] = json!(hex);
+        info["size"] = json!(tx.total_size());
+        info["vsize"] = json!(tx.vsize());
+        info["weight"] = json!(tx.weight().to_wu());
+        Ok(info)
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        let tx = self.get_transaction(txid).await?;
+        self.status_json(&tx).await
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let unspent: Vec<ElectrumUnspent> = self
+            .call(
+                "blockchain.scripthash.listunspent",
+                json!([script_hash(&address.script_pubkey())]),
+            )
+            .await?;
+        let tip_height = if unspent.iter().any(|item| item.height > 0) {
+            self.tip_height().await?
+        } else {
+            0
+        };
+
+        Ok(unspent
+            .into_iter()
+            .map(|item| {
+                // Unconfirmed outputs have a height of 0, or -1 when they
+                // have unconfirmed parents
+                let block_height = u64::try_from(item.height).ok().filter(|h| *h > 0);
+                Utxo {
+                    txid: item.tx_hash,
+                    vout: item.tx_pos,
+                    value: Amount::from_sat(item.value),
+                    status: UtxoStatus {
+                        confirmed: block_height.is_some(),
+                        block_height,
+                    },
+                    script_pubkey: address.script_pubkey(),
+                    address: Some(address.to_string()),
+                    confirmations: confirmations(block_height, tip_height),
+                    desc: None,
+                    assets: Default::default(),
+                }
+            })
+            .collect())
+    }
+
+    async fn addresses_used(&self, addresses: &[Address]) -> WalletResult<Option<Vec<bool>>> {
+        let mut used = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let history: Vec<Value> = self
+                .call(
+                    "blockchain.scripthash.get_history",
+                    json!([script_hash(&address.script_pubkey())]),
+                )
+                .await?;
+            used.push(!history.is_empty());
+        }
+        Ok(Some(used))
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        // Electrum indexes by script, so look the output up through its owner
+        let tx = self.get_transaction(&outpoint.txid).await?;
+        let Some(output) = tx.output.get(outpoint.vout as usize) else {
+            return Ok(false);
+        };
+
+        let unspent: Vec<ElectrumUnspent> = self
+            .call(
+                "blockchain.scripthash.listunspent",
+                json!([script_hash(&output.script_pubkey)]),
+            )
+            .await?;
+
+        Ok(unspent
+            .iter()
+            .any(|item| item.tx_hash == outpoint.txid && item.tx_pos == outpoint.vout))
+    }
+
+    // Electrum indexes by script: an output missing from its script's
+    // unspent list was spent, and the spender is in the script's history.
+    // Only histories of scripts with spent outputs are walked.
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        let mut txids: Vec<Txid> = outpoints.iter().map(|outpoint| outpoint.txid).collect();
+        txids.sort();
+        txids.dedup();
+        let mut txs: HashMap<Txid, Transaction> = HashMap::new();
+        for txid in txids {
+            match self.get_transaction(&txid).await {
+                Ok(tx) => {
+                    txs.insert(txid, tx);
+                }
+                // The server knows no such transaction
+                Err(WalletError::BitcoinError(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        let mut unspent: HashMap<ScriptBuf, Vec<ElectrumUnspent>> = HashMap::new();
+        let mut histories: HashMap<ScriptBuf, Vec<ElectrumHistoryItem>> = HashMap::new();
+        let mut statuses = Vec::with_capacity(outpoints.len());
+        for outpoint in outpoints {
+            let Some(script) = txs
+                .get(&outpoint.txid)
+                .and_then(|tx| tx.output.get(outpoint.vout as usize))
+                .map(|output| output.script_pubkey.clone())
+            else {
+                statuses.push(SpendStatus::NotFound);
+                continue;
+            };
+
+            if let Entry::Vacant(entry) = unspent.entry(script.clone()) {
+                entry.insert(
+                    self.call(
+                        "blockchain.scripthash.listunspent",
+                        json!([script_hash(&script)]),
+                    )
+                    .await?,
+                );
+            }
+            if unspent[&script]
+                .iter()
+                .any(|item| item.tx_hash == outpoint.txid && item.tx_pos == outpoint.vout)
+            {
+                statuses.push(SpendStatus::Unspent);
+                continue;
+            }
+
+            if let Entry::Vacant(entry) = histories.entry(script.clone()) {
+                entry.insert(
+                    self.call(
+                        "blockchain.scripthash.get_history",
+                        json!([script_hash(&script)]),
+                    )
+                    .await?,
+                );
+            }
+            let history = &histories[&script];
+            // Nothing mined before the output was created can spend it
+            let created = history
+                .iter()
+                .find(|item| item.tx_hash == outpoint.txid)
+                .map_or(0, |item| item.height);
+
+            // Spent, though the history may not show the spender yet
+            let mut status = SpendStatus::SpentInBlock {
+                spending_txid: None,
+                block_height: None,
+            };
+            for item in history {
+                if item.tx_hash == outpoint.txid || (item.height > 0 && item.height < created) {
+                    continue;
+                }
+                let tx = match txs.entry(item.tx_hash) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        entry.insert(self.get_transaction(&item.tx_hash).await?)
+                    }
+                };
+                if !tx
+                    .input
+                    .iter()
+                    .any(|input| input.previous_output == *outpoint)
+                {
+                    continue;
+                }
+                // Unconfirmed transactions have a height of 0, or -1 when
+                // they have unconfirmed parents
+                status = match u64::try_from(item.height).ok().filter(|h| *h > 0) {
+                    Some(height) => SpendStatus::SpentInBlock {
+                        spending_txid: Some(item.tx_hash),
+                        block_height: Some(height),
+                    },
+                    None => SpendStatus::SpentInMempool {
+                        spending_txid: Some(item.tx_hash),
+                    },
+                };
+                break;
+            }
+            statuses.push(status);
+        }
+        Ok(statuses)
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        let txid: String = self
+            .call("blockchain.transaction.broadcast", json!([tx_hex]))
+            .await?;
+
+        Txid::from_str(&txid)
+            .map_err(|e| WalletError::BitcoinError(format!("Invalid txid from Electrum: {}", e)))
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        // The Electrum protocol has no package relay, so broadcast parents first
+        let mut txids = Vec::with_capacity(tx_hexes.len());
+        for tx_hex in tx_hexes {
+            txids.push(self.broadcast(tx_hex).await?);
+        }
+
+        Ok(json!({ "txids": txids }))
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>> {
+        // BTC/kB, or -1 when the server has no estimate
+        let btc_per_kb: f64 = self
+            .call("blockchain.estimatefee", json!([conf_target]))
+            .await?;
+
+        Ok((btc_per_kb > 0.0).then_some(btc_per_kb * 100_000.0))
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        let histogram: Vec<(f64, u64)> = self.call("mempool.get_fee_histogram", json!([])).await?;
+        Ok(Some(histogram))
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        let header: Value = self.call("blockchain.headers.subscribe", json!([])).await?;
+
+        header["height"]
+            .as_u64()
+            .ok_or_else(|| WalletError::NetworkError("Missing tip height".to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use crate::services::chain::{FakeElectrum, MemoryBackend};
+    use bitcoin::{consensus::encode::serialize_hex, Network, TxOut};
+    use std::sync::Arc;
+
+    fn serve() -> (FakeElectrum, ElectrumBackend) {
+        let fake = FakeElectrum::spawn(Arc::new(MemoryBackend::new(Network::Regtest))).unwrap();
+        let backend =
+            ElectrumBackend::new(&format!("tcp://{}", fake.addr()), &LimitsConfig::default());
+        (fake, backend)
+    }
+
+    #[tokio::test]
+    async fn lists_utxos_of_any_address() {
+        let (fake, electrum) = serve();
+        let owner = address(1, Network::Regtest);
+        let confirmed = fake
+            .chain()
+            .fund(&owner, Amount::from_sat(10_000), Some(100));
+        fake.chain()
+            .fund(&address(2, Network::Regtest), Amount::from_sat(1), None);
+        fake.chain().set_tip_height(109);
+
+        let utxos = electrum.list_unspent(&owner).await.unwrap();
+
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint(), confirmed);
+        assert_eq!(utxos[0].value, Amount::from_sat(10_000));
+        assert_eq!(utxos[0].status.block_height, Some(100));
+        assert_eq!(utxos[0].confirmations, 10);
+    }
+
+    #[tokio::test]
+    async fn decodes_verbose_transactions_locally() {
+        let (fake, electrum) = serve();
+        let owner = address(1, Network::Regtest);
+        let outpoint = fake.chain().fund(&owner, Amount::from_sat(10_000), Some(5));
+        fake.chain().set_tip_height(5);
+
+        let info = electrum
+            .get_raw_transaction(&outpoint.txid, true)
+            .await
+            .unwrap();
+        let tx = electrum.get_transaction(&outpoint.txid).await.unwrap();
+
+        assert_eq!(info["hex"], json!(serialize_hex(&tx)));
+        assert_eq!(info["confirmed"], json!(true));
+        assert_eq!(info["confirmations"], json!(1));
+        assert_eq!(info["vsize"], json!(tx.vsize()));
+    }
+
+    #[tokio::test]
+    async fn broadcast_spends_the_output() {
+        let (fake, electrum) = serve();
+        let owner = address(1, Network::Regtest);
+        let outpoint = fake.chain().fund(&owner, Amount::from_sat(10_000), Some(1));
+        let tx = spending_tx(
+            &[outpoint],
+            vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: owner.script_pubkey(),
+            }],
+        );
+
+        let txid = electrum.broadcast(&serialize_hex(&tx)).await.unwrap();
+
+        assert_eq!(txid, tx.compute_txid());
+        assert!(!electrum.is_unspent(&outpoint).await.unwrap());
+        let utxos = electrum.list_unspent(&owner).await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint(), OutPoint::new(txid, 0));
+    }
+
+    #[tokio::test]
+    async fn server_errors_name_the_method() {
+        let (fake, electrum) = serve();
+        fake.script(
+            "blockchain.transaction.broadcast",
+            Err("min relay fee not met".to_string()),
+        );
+
+        let result = electrum.broadcast("00").await;
+
+        match result {
+            Err(WalletError::BitcoinError(message)) => {
+                assert!(message.contains("blockchain.transaction.broadcast"));
+                assert!(message.contains("min relay fee not met"));
+            }
+            other => panic!("expected a BitcoinError, got {:?}", other),
+        }
+        // The connection stays usable after an error reply
+        assert_eq!(electrum.tip_height().await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn a_silent_server_times_out() {
+        // Accepts connections but never answers the handshake
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((stream, _)) = listener.accept().await {
+                held.push(stream);
+            }
+        });
+        let limits = LimitsConfig {
+            chain_timeout_secs: 1,
+            ..LimitsConfig::default()
+        };
+        let electrum = ElectrumBackend::new(&addr.to_string(), &limits);
+
+        for _ in 0..2 {
+            match electrum.tip_height().await {
+                Err(WalletError::NetworkError(message)) => assert!(message.contains("timed out")),
+                other => panic!("expected a timeout, got {:?}", other),
+            }
+            assert!(electrum.conn.lock().await.is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn estimates_fee_in_sat_per_vbyte() {
+        let (fake, electrum) = serve();
+        fake.chain().set_fee_rate(Some(20.0));
+        assert_eq!(electrum.estimate_fee(6).await.unwrap(), Some(20.0));
+
+        // Servers answer -1 when they have no estimate
+        fake.script("blockchain.estimatefee", Ok(json!(-1)));
+        assert_eq!(electrum.estimate_fee(6).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn spend_status_tells_spent_from_unknown_outputs() {
+        let (fake, electrum) = serve();
+        let owner = address(1, Network::Regtest);
+        let unspent = fake.chain().fund(&owner, Amount::from_sat(10_000), Some(1));
+        let spent = fake.chain().fund(&owner, Amount::from_sat(20_000), Some(1));
+        let spender = spending_tx(
+            &[spent],
+            vec![TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: address(2, Network::Regtest).script_pubkey(),
+            }],
+        );
+        fake.chain().insert_transaction(spender.clone(), None);
+
+        let outpoints = [
+            unspent,
+            spent,
+            OutPoint::new(unspent.txid, 1),
+            OutPoint::new(Txid::all_zeros(), 0),
+        ];
+        let statuses = electrum.spend_status(&outpoints).await.unwrap();
+
+        assert_eq!(
+            statuses,
+            vec![
+                SpendStatus::Unspent,
+                SpendStatus::SpentInMempool {
+                    spending_txid: Some(spender.compute_txid()),
+                },
+                SpendStatus::NotFound,
+                SpendStatus::NotFound,
+            ]
+        );
+    }
+}
@@ -0,0 +1,476 @@
+use super::ChainBackend;
+use crate::config::LimitsConfig;
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo, UtxoStatus};
+use async_trait::async_trait;
+use bitcoin::{consensus::encode::deserialize_hex, Address, Amount, OutPoint, Transaction, Txid};
+use futures::stream::{self, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{collections::HashMap, str::FromStr};
+
+// Requests in flight at once for lookups Esplora has no batch endpoint for
+const CONCURRENT_REQUESTS: usize = 16;
+
+/// Backend for Esplora-compatible REST APIs (blockstream.info, mempool.space)
+pub struct EsploraBackend {
+    base_url: String,
+    client: reqwest::Client,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraUtxo {
+    txid: Txid,
+    vout: u32,
+    value: u64,
+    status: EsploraStatus,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraStatus {
+    confirmed: bool,
+    block_height: Option<u64>,
+    block_hash: Option<String>,
+    block_time: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraOutspend {
+    spent: bool,
+    /// Spending transaction
+    txid: Option<Txid>,
+    status: Option<EsploraStatus>,
+}
+
+#[derive(Debug, Deserialize)]
+struct EsploraMempool {
+    fee_histogram: Vec<(f64, u64)>,
+}
+
+impl EsploraBackend {
+    pub fn new(base_url: &str, limits: &LimitsConfig) -> WalletResult<Self> {
+        let client = reqwest::Client::builder()
+            .connect_timeout(limits.chain_connect_timeout())
+            .timeout(limits.chain_timeout())
+            .build()
+            .map_err(|e| {
+                WalletError::NetworkError(format!("Failed to build Esplora client: {}", e))
+            })?;
+
+        Ok(Self {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            client,
+        })
+    }
+
+    async fn get(&self, path: &str) -> WalletResult<reqwest::Response> {
+        let url = format!("{}{}", self.base_url, path);
+        let response = self
+            .client
+            .get(&url)
+            .send()
+            .await
+            .map_err(|e| WalletError::NetworkError(format!("Esplora request failed: {}", e)))?;
+
+        check_status(response).await
+    }
+
+    async fn get_status(&self, txid: &Txid) -> WalletResult<EsploraStatus> {
+        self.get_json(&format!("/tx/{}/status", txid)).await
+    }
+
+    async fn get_text(&self, path: &str) -> WalletResult<String> {
+        self.get(path).await?.text().await.map_err(|e| {
+            WalletError::NetworkError(format!("Failed to read Esplora response: {}", e))
+        })
+    }
+
+    async fn get_json<T: serde::de::DeserializeOwned>(&self, path: &str) -> WalletResult<T> {
+        self.get(path)
+            .await?
+            .json()
+            .await
+            .map_err(|e| WalletError::NetworkError(format!("Invalid Esplora response: {}", e)))
+    }
+}
+
+// Confirmations of a transaction given its status and the current tip
+fn confirmations(status: &EsploraStatus, tip_height: u64) -> u64 {
+    match (status.confirmed, status.block_height) {
+        (true, Some(height)) => tip_height.saturating_sub(height) + 1,
+        _ => 0,
+    }
+}
+
+// Turn non-2xx responses into errors carrying the body text
+async fn check_status(response: reqwest::Response) -> WalletResult<reqwest::Response> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    let body = response.text().await.unwrap_or_default();
+    if status.is_client_error() {
+        Err(WalletError::BitcoinError(format!(
+            "Esplora returned {}: {}",
+            status, body
+        )))
+    } else {
+        Err(WalletError::NetworkError(format!(
+            "Esplora returned {}: {}",
+            status, body
+        )))
+    }
+}
+
+#[async_trait]
+impl ChainBackend for EsploraBackend {
+    fn name(&self) -> &'static str {
+        "esplora"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let hex = self.get_text(&format!("/tx/{}/hex", txid)).await?;
+
+        deserialize_hex(hex.trim())
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+    }
+
+    // Esplora has no batch endpoint; `buffered` keeps the order of `txids`
+    async fn get_transactions(&self, txids: &[Txid]) -> WalletResult<Vec<Transaction>> {
+        stream::iter(txids.iter().copied())
+            .map(|txid| async move { self.get_transaction(&txid).await })
+            .buffered(CONCURRENT_REQUESTS)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect()
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        let hex = self.get_text(&format!("/tx/{}/hex", txid)).await?;
+        let hex = hex.trim().to_string();
+        if !verbose {
+            return Ok(Value::String(hex));
+        }
+
+        // Esplora's decoded form, plus the fields bitcoind callers rely on
+        let mut info: Value = self.get_json(&format!("/tx/{}", txid)).await?;
+        let status = self.get_status(txid).await?;
+        let tip_height = self.tip_height().await?;
+        info["hex"] = json!(hex);
+        info["confirmations"] = json!(confirmations(&status, tip_height));
+        Ok(info)
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        let status = self.get_status(txid).await?;
+        let tip_height = self.tip_height().await?;
+
+        // Mirror the fields of bitcoind's gettransaction
+        Ok(json!({
+            "txid": txid,
+            "confirmed": status.confirmed,
+            "confirmations": confirmations(&status, tip_height),
+            "blockhash": status.block_hash,
+            "blockheight": status.block_height,
+            "blocktime": status.block_time,
+        }))
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let utxos: Vec<EsploraUtxo> = self.get_json(&format!("/address/{}/utxo", address)).await?;
+        let tip_height = if utxos.iter().any(|utxo| utxo.status.confirmed) {
+            self.tip_height().await?
+        } else {
+            0
+        };
+
+        Ok(utxos
+            .into_iter()
+            .map(|utxo| Utxo {
+                txid: utxo.txid,
+                vout: utxo.vout,
+                value: Amount::from_sat(utxo.value),
+                status: UtxoStatus {
+                    confirmed: utxo.status.confirmed,
+                    block_height: utxo.status.block_height,
+                },
+                script_pubkey: address.script_pubkey(),
+                address: Some(address.to_string()),
+                confirmations: confirmations(&utxo.status, tip_height) as u32,
+                desc: None,
+                assets: Default::default(),
+            })
+            .collect())
+    }
+
+    async fn addresses_used(&self, addresses: &[Address]) -> WalletResult<Option<Vec<bool>>> {
+        let mut used = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            let stats: Value = self.get_json(&format!("/address/{}", address)).await?;
+            let tx_count = |kind: &str| stats[kind]["tx_count"].as_u64().unwrap_or(0);
+            used.push(tx_count("chain_stats") + tx_count("mempool_stats") > 0);
+        }
+        Ok(Some(used))
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        let outspend: Value = self
+            .get_json(&format!("/tx/{}/outspend/{}", outpoint.txid, outpoint.vout))
+            .await?;
+        Ok(outspend["spent"] == json!(false))
+    }
+
+    // One `outspends` request per transaction covers all its outputs
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        let mut txids: Vec<Txid> = outpoints.iter().map(|outpoint| outpoint.txid).collect();
+        txids.sort();
+        txids.dedup();
+
+        let outspends: HashMap<Txid, Vec<EsploraOutspend>> = stream::iter(txids)
+            .map(|txid| async move {
+                let outspends = match self.get_json(&format!("/tx/{}/outspends", txid)).await {
+                    Ok(outspends) => outspends,
+                    // Esplora answers 404 for a transaction it does not know
+                    Err(WalletError::BitcoinError(_)) => Vec::new(),
+                    Err(e) => return Err(e),
+                };
+                Ok((txid, outspends))
+            })
+            .buffer_unordered(CONCURRENT_REQUESTS)
+            .collect::<Vec<_>>()
+            .await
+            .into_iter()
+            .collect::<WalletResult<_>>()?;
+
+        Ok(outpoints
+            .iter()
+            .map(
+                |outpoint| match outspends[&outpoint.txid].get(outpoint.vout as usize) {
+                    Some(outspend) if !outspend.spent => SpendStatus::Unspent,
+                    Some(EsploraOutspend {
+                        txid,
+                        status: Some(status),
+                        ..
+                    }) if !status.confirmed => SpendStatus::SpentInMempool {
+                        spending_txid: *txid,
+                    },
+                    Some(outspend) => SpendStatus::SpentInBlock {
+                        spending_txid: outspend.txid,
+                        block_height: outspend.status.as_ref().and_then(|s| s.block_height),
+                    },
+                    None => SpendStatus::NotFound,
+                },
+            )
+            .collect())
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        let response = self
+            .client
+            .post(format!("{}/tx", self.base_url))
+            .body(tx_hex.to_string())
+            .send()
+            .await
+            .map_err(|e| WalletError::NetworkError(format!("Esplora request failed: {}", e)))?;
+
+        let txid = check_status(response).await?.text().await.map_err(|e| {
+            WalletError::NetworkError(format!("Failed to read Esplora response: {}", e))
+        })?;
+
+        Txid::from_str(txid.trim())
+            .map_err(|e| WalletError::BitcoinError(format!("Invalid txid from Esplora: {}", e)))
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        // Plain Esplora has no package relay, so broadcast parents first
+        let mut txids = Vec::with_capacity(tx_hexes.len());
+        for tx_hex in tx_hexes {
+            txids.push(self.broadcast(tx_hex).await?);
+        }
+
+        Ok(json!({ "txids": txids }))
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>> {
+        // Keys are confirmation targets, values sat/vB
+        let estimates: HashMap<String, f64> = self.get_json("/fee-estimates").await?;
+
+        // Use the closest target that is not slower than requested
+        let rate = estimates
+            .iter()
+            .filter_map(|(target, rate)| target.parse::<u16>().ok().map(|t| (t, *rate)))
+            .filter(|(target, _)| *target <= conf_target)
+            .max_by_key(|(target, _)| *target)
+            .map(|(_, rate)| rate);
+
+        Ok(rate)
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        let mempool: EsploraMempool = self.get_json("/mempool").await?;
+        Ok(Some(mempool.fee_histogram))
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        let height = self.get_text("/blocks/tip/height").await?;
+
+        height
+            .trim()
+            .parse::<u64>()
+            .map_err(|e| WalletError::NetworkError(format!("Invalid tip height: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use crate::services::chain::{FakeEsplora, MemoryBackend};
+    use bitcoin::{consensus::encode::serialize_hex, hashes::Hash, Network, TxOut};
+    use std::sync::Arc;
+
+    fn serve() -> (FakeEsplora, EsploraBackend) {
+        let fake = FakeEsplora::spawn(Arc::new(MemoryBackend::new(Network::Regtest))).unwrap();
+        let backend =
+            EsploraBackend::new(&format!("{}/", fake.url()), &LimitsConfig::default()).unwrap();
+        (fake, backend)
+    }
+
+    #[tokio::test]
+    async fn lists_utxos_with_confirmations() {
+        let (fake, esplora) = serve();
+        let owner = address(1, Network::Regtest);
+        let confirmed = fake
+            .chain()
+            .fund(&owner, Amount::from_sat(10_000), Some(100));
+        let unconfirmed = fake.chain().fund(&owner, Amount::from_sat(20_000), None);
+        fake.chain().set_tip_height(102);
+
+        let mut utxos = esplora.list_unspent(&owner).await.unwrap();
+        utxos.sort_by_key(|utxo| utxo.value);
+
+        assert_eq!(utxos.len(), 2);
+        assert_eq!(utxos[0].outpoint(), confirmed);
+        assert_eq!(utxos[0].status.block_height, Some(100));
+        assert_eq!(utxos[0].confirmations, 3);
+        assert_eq!(utxos[0].script_pubkey, owner.script_pubkey());
+        assert_eq!(utxos[1].outpoint(), unconfirmed);
+        assert!(!utxos[1].status.confirmed);
+        assert_eq!(utxos[1].confirmations, 0);
+    }
+
+    #[tokio::test]
+    async fn fetches_transactions_in_order() {
+        let (fake, esplora) = serve();
+        let txids: Vec<Txid> = (1..=20)
+            .map(|n| {
+                let owner = address(n, Network::Regtest);
+                fake.chain()
+                    .fund(&owner, Amount::from_sat(1_000), None)
+                    .txid
+            })
+            .collect();
+
+        let txs = esplora.get_transactions(&txids).await.unwrap();
+
+        let fetched: Vec<Txid> = txs.iter().map(Transaction::compute_txid).collect();
+        assert_eq!(fetched, txids);
+    }
+
+    #[tokio::test]
+    async fn reports_transaction_status() {
+        let (fake, esplora) = serve();
+        let owner = address(1, Network::Regtest);
+        let outpoint = fake.chain().fund(&owner, Amount::from_sat(1_000), Some(7));
+        fake.chain().set_tip_height(10);
+
+        let status = esplora
+            .get_transaction_status(&outpoint.txid)
+            .await
+            .unwrap();
+
+        assert_eq!(status["confirmed"], json!(true));
+        assert_eq!(status["confirmations"], json!(4));
+        assert_eq!(status["blockheight"], json!(7));
+    }
+
+    #[tokio::test]
+    async fn missing_transaction_is_a_client_error() {
+        let (_fake, esplora) = serve();
+
+        let result = esplora.get_transaction(&Txid::all_zeros()).await;
+
+        assert!(matches!(result, Err(WalletError::BitcoinError(_))));
+    }
+
+    #[tokio::test]
+    async fn broadcast_spends_the_output() {
+        let (fake, esplora) = serve();
+        let owner = address(1, Network::Regtest);
+        let outpoint = fake.chain().fund(&owner, Amount::from_sat(10_000), Some(1));
+        let tx = spending_tx(
+            &[outpoint],
+            vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: address(2, Network::Regtest).script_pubkey(),
+            }],
+        );
+
+        let txid = esplora.broadcast(&serialize_hex(&tx)).await.unwrap();
+
+        assert_eq!(txid, tx.compute_txid());
+        assert!(!esplora.is_unspent(&outpoint).await.unwrap());
+        assert!(esplora.is_unspent(&OutPoint::new(txid, 0)).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn estimates_fee_from_the_closest_faster_target() {
+        let (fake, esplora) = serve();
+        fake.chain().set_fee_rate(Some(12.5));
+
+        assert_eq!(esplora.estimate_fee(3).await.unwrap(), Some(12.5));
+        assert_eq!(esplora.tip_height().await.unwrap(), 0);
+
+        fake.chain().set_fee_rate(None);
+        assert_eq!(esplora.estimate_fee(3).await.unwrap(), None);
+    }
+
+    #[tokio::test]
+    async fn spend_status_tells_spent_from_unknown_outputs() {
+        let (fake, esplora) = serve();
+        let owner = address(1, Network::Regtest);
+        let unspent = fake.chain().fund(&owner, Amount::from_sat(10_000), Some(1));
+        let spent = fake.chain().fund(&owner, Amount::from_sat(20_000), Some(1));
+        let spender = spending_tx(
+            &[spent],
+            vec![TxOut {
+                value: Amount::from_sat(19_000),
+                script_pubkey: owner.script_pubkey(),
+            }],
+        );
+        fake.chain().insert_transaction(spender.clone(), Some(2));
+
+        let outpoints = [
+            unspent,
+            spent,
+            OutPoint::new(unspent.txid, 1),
+            OutPoint::new(Txid::all_zeros(), 0),
+        ];
+        let statuses = esplora.spend_status(&outpoints).await.unwrap();
+
+        assert_eq!(
+            statuses,
+            vec![
+                SpendStatus::Unspent,
+                SpendStatus::SpentInBlock {
+                    spending_txid: Some(spender.compute_txid()),
+                    block_height: Some(2),
+                },
+                SpendStatus::NotFound,
+                SpendStatus::NotFound,
+            ]
+        );
+    }
+}
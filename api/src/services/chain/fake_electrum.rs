@@ -0,0 +1,171 @@
+use super::{electrum::script_hash, ChainBackend, MemoryBackend};
+use crate::error::{WalletError, WalletResult};
+use bitcoin::Txid;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    str::FromStr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+};
+
+/// In-process Electrum server backed by a [`MemoryBackend`].
+///
+/// Answers the methods [`super::ElectrumBackend`] uses from the memory chain.
+/// Individual methods can be scripted with canned results or errors to
+/// simulate misbehaving servers.
+pub struct FakeElectrum {
+    addr: SocketAddr,
+    inner: Arc<FakeInner>,
+}
+
+struct FakeInner {
+    chain: Arc<MemoryBackend>,
+    scripted: Mutex<HashMap<String, Result<Value, String>>>,
+}
+
+impl FakeElectrum {
+    /// Bind an ephemeral localhost port and serve on the current runtime
+    pub fn spawn(chain: Arc<MemoryBackend>) -> WalletResult<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .map_err(|e| {
+                WalletError::NetworkError(format!("Failed to bind fake Electrum: {}", e))
+            })?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+        let listener = tokio::net::TcpListener::from_std(listener)
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        let inner = Arc::new(FakeInner {
+            chain,
+            scripted: Mutex::new(HashMap::new()),
+        });
+
+        let server = inner.clone();
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                tokio::spawn(serve_connection(server.clone(), stream));
+            }
+        });
+
+        Ok(Self { addr, inner })
+    }
+
+    /// `host:port` to hand to [`super::ElectrumBackend::new`]
+    pub fn addr(&self) -> String {
+        self.addr.to_string()
+    }
+
+    /// The chain served, for seeding transactions
+    pub fn chain(&self) -> &Arc<MemoryBackend> {
+        &self.inner.chain
+    }
+
+    /// Answer every future call of `method` with `reply` instead of the chain
+    pub fn script(&self, method: &str, reply: Result<Value, String>) {
+        self.inner
+            .scripted
+            .lock()
+            .unwrap()
+            .insert(method.to_string(), reply);
+    }
+}
+
+async fn serve_connection(inner: Arc<FakeInner>, stream: TcpStream) {
+    let (reader, mut writer) = stream.into_split();
+    let mut lines = BufReader::new(reader).lines();
+
+    while let Ok(Some(line)) = lines.next_line().await {
+        let request: Value = match serde_json::from_str(&line) {
+            Ok(request) => request,
+            Err(_) => continue,
+        };
+        let method = request["method"].as_str().unwrap_or_default();
+
+        let scripted = inner.scripted.lock().unwrap().get(method).cloned();
+        let reply = match scripted {
+            Some(reply) => reply,
+            None => dispatch(&inner.chain, method, &request["params"]).await,
+        };
+
+        let mut response = match reply {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": request["id"], "result": result }),
+            Err(message) => json!({
+                "jsonrpc": "2.0",
+                "id": request["id"],
+                "error": { "code": 1, "message": message },
+            }),
+        }
+        .to_string();
+        response.push('\n');
+
+        if writer.write_all(response.as_bytes()).await.is_err() {
+            break;
+        }
+    }
+}
+
+async fn dispatch(chain: &MemoryBackend, method: &str, params: &Value) -> Result<Value, String> {
+    let param = params[0].as_str().unwrap_or_default();
+
+    match method {
+        "server.version" => Ok(json!(["fake-electrum", "1.4"])),
+        "blockchain.headers.subscribe" => {
+            let height = chain.tip_height().await.map_err(|e| e.to_string())?;
+            Ok(json!({ "height": height, "hex": "" }))
+        }
+        "blockchain.estimatefee" => {
+            let rate = chain.estimate_fee(1).await.map_err(|e| e.to_string())?;
+            Ok(json!(rate.map(|r| r / 100_000.0).unwrap_or(-1.0)))
+        }
+        "mempool.get_fee_histogram" => {
+            let histogram = chain.mempool_histogram().await.map_err(|e| e.to_string())?;
+            Ok(json!(histogram.unwrap_or_default()))
+        }
+        "blockchain.scripthash.listunspent" => {
+            let unspent: Vec<Value> = chain
+                .unspent_matching(|script| script_hash(script) == param)
+                .into_iter()
+                .map(|(outpoint, output, height)| {
+                    json!({
+                        "tx_hash": outpoint.txid,
+                        "tx_pos": outpoint.vout,
+                        "height": height.unwrap_or(0),
+                        "value": output.value.to_sat(),
+                    })
+                })
+                .collect();
+            Ok(json!(unspent))
+        }
+        "blockchain.scripthash.get_history" => {
+            let history: Vec<Value> = chain
+                .history_matching(|script| script_hash(script) == param)
+                .into_iter()
+                .map(|(txid, height)| json!({ "tx_hash": txid, "height": height.unwrap_or(0) }))
+                .collect();
+            Ok(json!(history))
+        }
+        "blockchain.transaction.get" => {
+            let txid = Txid::from_str(param).map_err(|e| e.to_string())?;
+            chain
+                .get_raw_transaction(&txid, false)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        "blockchain.transaction.broadcast" => chain
+            .broadcast(param)
+            .await
+            .map(|txid| json!(txid))
+            .map_err(|e| e.to_string()),
+        other => Err(format!("unknown method {}", other)),
+    }
+}
@@ -0,0 +1,252 @@
+use super::{ChainBackend, MemoryBackend};
+use crate::error::{WalletError, WalletResult};
+use crate::models::SpendStatus;
+use crate::services::address::parse_address;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    routing::{get, post},
+    Json, Router,
+};
+use bitcoin::{OutPoint, Txid};
+use serde_json::json;
+use std::{net::SocketAddr, sync::Arc};
+
+/// Local Esplora-compatible server backed by a [`MemoryBackend`].
+///
+/// Serves the subset of the Esplora REST API that [`super::EsploraBackend`]
+/// uses, so the HTTP client can be exercised end to end without a real
+/// indexer.
+pub struct FakeEsplora {
+    addr: SocketAddr,
+    chain: Arc<MemoryBackend>,
+}
+
+impl FakeEsplora {
+    /// Bind an ephemeral localhost port and serve on the current runtime
+    pub fn spawn(chain: Arc<MemoryBackend>) -> WalletResult<Self> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| {
+                listener.set_nonblocking(true)?;
+                Ok(listener)
+            })
+            .map_err(|e| {
+                WalletError::NetworkError(format!("Failed to bind fake Esplora: {}", e))
+            })?;
+        let addr = listener
+            .local_addr()
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+        let listener = tokio::net::TcpListener::from_std(listener)
+            .map_err(|e| WalletError::NetworkError(e.to_string()))?;
+
+        let app = Router::new()
+            .route("/tx", post(broadcast))
+            .route("/tx/{txid}", get(tx))
+            .route("/tx/{txid}/hex", get(tx_hex))
+            .route("/tx/{txid}/status", get(tx_status))
+            .route("/tx/{txid}/outspend/{vout}", get(tx_outspend))
+            .route("/tx/{txid}/outspends", get(tx_outspends))
+            .route("/address/{address}", get(address_stats))
+            .route("/address/{address}/utxo", get(address_utxos))
+            .route("/fee-estimates", get(fee_estimates))
+            .route("/mempool", get(mempool))
+            .route("/blocks/tip/height", get(tip_height))
+            .with_state(chain.clone());
+
+        tokio::spawn(async move {
+            if let Err(e) = axum::serve(listener, app).await {
+                tracing::error!("Fake Esplora stopped: {}", e);
+            }
+        });
+
+        Ok(Self { addr, chain })
+    }
+
+    /// Base URL to hand to [`super::EsploraBackend::new`]
+    pub fn url(&self) -> String {
+        format!("http://{}", self.addr)
+    }
+
+    /// The chain served, for seeding transactions
+    pub fn chain(&self) -> &Arc<MemoryBackend> {
+        &self.chain
+    }
+}
+
+type FakeState = State<Arc<MemoryBackend>>;
+
+// Esplora answers errors with plain text bodies
+fn error_response(status: StatusCode, err: WalletError) -> Response {
+    (status, err.to_string()).into_response()
+}
+
+async fn tx(State(chain): FakeState, Path(txid): Path<Txid>) -> Response {
+    let (tx, status) = match (
+        chain.get_transaction(&txid).await,
+        chain.get_transaction_status(&txid).await,
+    ) {
+        (Ok(tx), Ok(status)) => (tx, status),
+        (Err(e), _) | (_, Err(e)) => return error_response(StatusCode::NOT_FOUND, e),
+    };
+
+    let vin: Vec<_> = tx
+        .input
+        .iter()
+        .map(|input| {
+            json!({
+                "txid": input.previous_output.txid,
+                "vout": input.previous_output.vout,
+                "sequence": input.sequence.0,
+            })
+        })
+        .collect();
+    let vout: Vec<_> = tx
+        .output
+        .iter()
+        .map(|output| {
+            json!({
+                "scriptpubkey": output.script_pubkey.to_hex_string(),
+                "value": output.value.to_sat(),
+            })
+        })
+        .collect();
+
+    Json(json!({
+        "txid": txid,
+        "version": tx.version.0,
+        "locktime": tx.lock_time.to_consensus_u32(),
+        "vin": vin,
+        "vout": vout,
+        "weight": tx.weight().to_wu(),
+        "status": status,
+    }))
+    .into_response()
+}
+
+async fn tx_hex(State(chain): FakeState, Path(txid): Path<Txid>) -> Response {
+    match chain.get_raw_transaction(&txid, false).await {
+        Ok(hex) => hex.as_str().unwrap_or_default().to_string().into_response(),
+        Err(e) => error_response(StatusCode::NOT_FOUND, e),
+    }
+}
+
+async fn tx_status(State(chain): FakeState, Path(txid): Path<Txid>) -> Response {
+    match chain.get_transaction_status(&txid).await {
+        Ok(status) => Json(status).into_response(),
+        Err(e) => error_response(StatusCode::NOT_FOUND, e),
+    }
+}
+
+async fn tx_outspend(State(chain): FakeState, Path((txid, vout)): Path<(Txid, u32)>) -> Response {
+    match chain.is_unspent(&OutPoint::new(txid, vout)).await {
+        Ok(unspent) => Json(json!({ "spent": !unspent })).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn tx_outspends(State(chain): FakeState, Path(txid): Path<Txid>) -> Response {
+    let tx = match chain.get_transaction(&txid).await {
+        Ok(tx) => tx,
+        Err(e) => return error_response(StatusCode::NOT_FOUND, e),
+    };
+    let outpoints: Vec<OutPoint> = (0..tx.output.len() as u32)
+        .map(|vout| OutPoint::new(txid, vout))
+        .collect();
+
+    match chain.spend_status(&outpoints).await {
+        Ok(statuses) => {
+            let outspends: Vec<_> = statuses
+                .into_iter()
+                .map(|status| match status {
+                    SpendStatus::SpentInMempool { spending_txid } => json!({
+                        "spent": true,
+                        "txid": spending_txid,
+                        "status": { "confirmed": false },
+                    }),
+                    SpendStatus::SpentInBlock {
+                        spending_txid,
+                        block_height,
+                    } => json!({
+                        "spent": true,
+                        "txid": spending_txid,
+                        "status": { "confirmed": true, "block_height": block_height },
+                    }),
+                    SpendStatus::Unspent | SpendStatus::NotFound => json!({ "spent": false }),
+                })
+                .collect();
+            Json(outspends).into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn address_stats(State(chain): FakeState, Path(address): Path<String>) -> Response {
+    let address = match parse_address(&address, chain.network()) {
+        Ok(address) => address,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    let script_pubkey = address.script_pubkey();
+    let history = chain.history_matching(|script| script == script_pubkey.as_script());
+    let confirmed = history
+        .iter()
+        .filter(|(_, height)| height.is_some())
+        .count();
+    Json(json!({
+        "address": address,
+        "chain_stats": { "tx_count": confirmed },
+        "mempool_stats": { "tx_count": history.len() - confirmed },
+    }))
+    .into_response()
+}
+
+async fn address_utxos(State(chain): FakeState, Path(address): Path<String>) -> Response {
+    let address = match parse_address(&address, chain.network()) {
+        Ok(address) => address,
+        Err(e) => return error_response(StatusCode::BAD_REQUEST, e),
+    };
+
+    match chain.list_unspent(&address).await {
+        Ok(utxos) => Json(utxos).into_response(),
+        Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn broadcast(State(chain): FakeState, body: String) -> Response {
+    match chain.broadcast(body.trim()).await {
+        Ok(txid) => txid.to_string().into_response(),
+        Err(e) => error_response(StatusCode::BAD_REQUEST, e),
+    }
+}
+
+async fn fee_estimates(State(chain): FakeState) -> Response {
+    match chain.estimate_fee(1).await {
+        Ok(Some(rate)) => Json(json!({ "1": rate, "6": rate, "144": rate })).into_response(),
+        Ok(None) => Json(json!({})).into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn mempool(State(chain): FakeState) -> Response {
+    match chain.mempool_histogram().await {
+        Ok(histogram) => {
+            let histogram = histogram.unwrap_or_default();
+            let vsize: u64 = histogram.iter().map(|(_, vsize)| vsize).sum();
+            Json(json!({
+                "count": histogram.len(),
+                "vsize": vsize,
+                "fee_histogram": histogram,
+            }))
+            .into_response()
+        }
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
+
+async fn tip_height(State(chain): FakeState) -> Response {
+    match chain.tip_height().await {
+        Ok(height) => height.to_string().into_response(),
+        Err(e) => error_response(StatusCode::INTERNAL_SERVER_ERROR, e),
+    }
+}
@@ -0,0 +1,423 @@
+use super::{confirmations, ChainBackend, MempoolAccept};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo, UtxoStatus};
+use async_trait::async_trait;
+use bitcoin::{
+    absolute::LockTime,
+    consensus::encode::{deserialize_hex, serialize_hex},
+    hashes::Hash,
+    transaction::Version,
+    Address, Amount, Network, OutPoint, Script, ScriptBuf, Transaction, TxIn, TxOut, Txid,
+    WPubkeyHash,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::RwLock,
+};
+
+/// In-memory chain used by handler and backend tests.
+///
+/// Transactions are seeded with [`MemoryBackend::insert_transaction`];
+/// broadcasts land in an unconfirmed "mempool" and spend their inputs.
+pub struct MemoryBackend {
+    network: Network,
+    state: RwLock<MemoryState>,
+}
+
+#[derive(Default)]
+struct MemoryState {
+    txs: HashMap<Txid, Transaction>,
+    // Block height per confirmed transaction
+    heights: HashMap<Txid, u64>,
+    spent: HashSet<OutPoint>,
+    tip_height: u64,
+    fee_rate: Option<f64>,
+    // Scripts of addresses the backend's wallet owns
+    wallet: HashSet<ScriptBuf>,
+    // Reason `testmempoolaccept` refuses everything with, if it does
+    mempool_rejection: Option<String>,
+    // Whether address history is hidden, as from node backends
+    history_hidden: bool,
+}
+
+impl MemoryBackend {
+    pub fn new(network: Network) -> Self {
+        Self {
+            network,
+            state: RwLock::new(MemoryState::default()),
+        }
+    }
+
+    /// Add a transaction, confirmed at `height` or unconfirmed when `None`
+    pub fn insert_transaction(&self, tx: Transaction, height: Option<u64>) {
+        let mut state = self.state.write().unwrap();
+        let txid = tx.compute_txid();
+
+        for input in &tx.input {
+            state.spent.insert(input.previous_output);
+        }
+        if let Some(height) = height {
+            state.heights.insert(txid, height);
+            state.tip_height = state.tip_height.max(height);
+        }
+        state.txs.insert(txid, tx);
+    }
+
+    /// Pay `value` to `address` from a transaction with no real inputs,
+    /// confirmed at `height` or unconfirmed when `None`
+    pub fn fund(&self, address: &Address, value: Amount, height: Option<u64>) -> OutPoint {
+        // A distinct lock time keeps every funding transaction unique
+        let lock_time = self.state.read().unwrap().txs.len() as u32;
+        let tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::from_consensus(lock_time),
+            input: vec![TxIn::default()],
+            output: vec![TxOut {
+                value,
+                script_pubkey: address.script_pubkey(),
+            }],
+        };
+
+        let outpoint = OutPoint::new(tx.compute_txid(), 0);
+        self.insert_transaction(tx, height);
+        outpoint
+    }
+
+    /// Move the tip, ageing every confirmed transaction
+    pub fn set_tip_height(&self, height: u64) {
+        self.state.write().unwrap().tip_height = height;
+    }
+
+    /// Set the sat/vB rate returned for every confirmation target
+    pub fn set_fee_rate(&self, fee_rate: Option<f64>) {
+        self.state.write().unwrap().fee_rate = fee_rate;
+    }
+
+    /// Have `testmempoolaccept` refuse every transaction, or accept them all
+    /// again with `None`
+    pub fn set_mempool_rejection(&self, reason: Option<&str>) {
+        self.state.write().unwrap().mempool_rejection = reason.map(str::to_string);
+    }
+
+    /// Only see unspent outputs, as the node backends do, so whether an
+    /// address was ever paid is unknown
+    pub fn hide_history(&self) {
+        self.state.write().unwrap().history_hidden = true;
+    }
+
+    /// Count `address` as one of the backend's wallet
+    pub fn add_wallet_address(&self, address: &Address) {
+        self.state
+            .write()
+            .unwrap()
+            .wallet
+            .insert(address.script_pubkey());
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    /// Unspent outputs whose script matches, with their confirmation height
+    pub fn unspent_matching(
+        &self,
+        matches: impl Fn(&Script) -> bool,
+    ) -> Vec<(OutPoint, TxOut, Option<u64>)> {
+        let state = self.state.read().unwrap();
+        let mut unspent = Vec::new();
+
+        for (txid, tx) in &state.txs {
+            for (vout, output) in tx.output.iter().enumerate() {
+                let outpoint = OutPoint::new(*txid, vout as u32);
+                if !state.spent.contains(&outpoint) && matches(&output.script_pubkey) {
+                    unspent.push((outpoint, output.clone(), state.heights.get(txid).copied()));
+                }
+            }
+        }
+
+        unspent
+    }
+
+    /// Transactions paying to or spending from a matching script
+    pub fn history_matching(&self, matches: impl Fn(&Script) -> bool) -> Vec<(Txid, Option<u64>)> {
+        let state = self.state.read().unwrap();
+
+        state
+            .txs
+            .iter()
+            .filter(|(_, tx)| {
+                let pays = tx.output.iter().any(|o| matches(&o.script_pubkey));
+                let spends = tx.input.iter().any(|input| {
+                    state
+                        .txs
+                        .get(&input.previous_output.txid)
+                        .and_then(|prev| prev.output.get(input.previous_output.vout as usize))
+                        .is_some_and(|prev_out| matches(&prev_out.script_pubkey))
+                });
+                pays || spends
+            })
+            .map(|(txid, _)| (*txid, state.heights.get(txid).copied()))
+            .collect()
+    }
+
+    fn lookup(&self, txid: &Txid) -> WalletResult<(Transaction, Option<u64>, u64)> {
+        let state = self.state.read().unwrap();
+        let tx =
+            state.txs.get(txid).cloned().ok_or_else(|| {
+                WalletError::BitcoinError(format!("Transaction {} not found", txid))
+            })?;
+
+        Ok((tx, state.heights.get(txid).copied(), state.tip_height))
+    }
+}
+
+/// P2WPKH address whose key hash is `n` repeated
+pub fn address(n: u8, network: Network) -> Address {
+    let script_pubkey = ScriptBuf::new_p2wpkh(&WPubkeyHash::from_byte_array([n; 20]));
+    Address::from_script(&script_pubkey, network).unwrap()
+}
+
+/// Unsigned transaction spending `outpoints` into `outputs`
+pub fn spending_tx(outpoints: &[OutPoint], outputs: Vec<TxOut>) -> Transaction {
+    Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: outpoints
+            .iter()
+            .map(|outpoint| TxIn {
+                previous_output: *outpoint,
+                ..Default::default()
+            })
+            .collect(),
+        output: outputs,
+    }
+}
+
+fn status_json(height: Option<u64>, tip_height: u64) -> Value {
+    match height {
+        Some(height) => json!({
+            "confirmed": true,
+            "block_height": height,
+            "confirmations": tip_height.saturating_sub(height) + 1,
+        }),
+        None => json!({ "confirmed": false, "confirmations": 0 }),
+    }
+}
+
+#[async_trait]
+impl ChainBackend for MemoryBackend {
+    fn name(&self) -> &'static str {
+        "memory"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        Ok(self.lookup(txid)?.0)
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        let (tx, height, tip_height) = self.lookup(txid)?;
+        let hex = serialize_hex(&tx);
+
+        if !verbose {
+            return Ok(Value::String(hex));
+        }
+
+        let mut info = status_json(height, tip_height);
+        info["txid"] = json!(txid);
+        info["hex"] = json!(hex);
+        Ok(info)
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        let (_, height, tip_height) = self.lookup(txid)?;
+        Ok(status_json(height, tip_height))
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let script_pubkey = address.script_pubkey();
+        let tip_height = self.state.read().unwrap().tip_height;
+        let utxos = self
+            .unspent_matching(|script| script == script_pubkey.as_script())
+            .into_iter()
+            .map(|(outpoint, output, height)| Utxo {
+                txid: outpoint.txid,
+                vout: outpoint.vout,
+                value: output.value,
+                status: UtxoStatus {
+                    confirmed: height.is_some(),
+                    block_height: height,
+                },
+                script_pubkey: output.script_pubkey,
+                address: Some(address.to_string()),
+                confirmations: confirmations(height, tip_height),
+                desc: None,
+                assets: Default::default(),
+            })
+            .collect();
+
+        Ok(utxos)
+    }
+
+    async fn addresses_used(&self, addresses: &[Address]) -> WalletResult<Option<Vec<bool>>> {
+        if self.state.read().unwrap().history_hidden {
+            return Ok(None);
+        }
+        let used = addresses
+            .iter()
+            .map(|address| {
+                let script_pubkey = address.script_pubkey();
+                !self
+                    .history_matching(|script| script == script_pubkey.as_script())
+                    .is_empty()
+            })
+            .collect();
+        Ok(Some(used))
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        let state = self.state.read().unwrap();
+        let exists = state
+            .txs
+            .get(&outpoint.txid)
+            .is_some_and(|tx| tx.output.len() > outpoint.vout as usize);
+
+        Ok(exists && !state.spent.contains(outpoint))
+    }
+
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        let state = self.state.read().unwrap();
+
+        Ok(outpoints
+            .iter()
+            .map(|outpoint| {
+                let spender = state.txs.iter().find(|(_, tx)| {
+                    tx.input
+                        .iter()
+                        .any(|input| input.previous_output == *outpoint)
+                });
+                let exists = state
+                    .txs
+                    .get(&outpoint.txid)
+                    .is_some_and(|tx| tx.output.len() > outpoint.vout as usize);
+
+                match spender.map(|(txid, _)| (*txid, state.heights.get(txid).copied())) {
+                    None if exists => SpendStatus::Unspent,
+                    Some((txid, None)) => SpendStatus::SpentInMempool {
+                        spending_txid: Some(txid),
+                    },
+                    Some((txid, height)) => SpendStatus::SpentInBlock {
+                        spending_txid: Some(txid),
+                        block_height: height,
+                    },
+                    None => SpendStatus::NotFound,
+                }
+            })
+            .collect())
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        let tx: Transaction = deserialize_hex(tx_hex)
+            .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))?;
+
+        // Like bitcoind, accept a transaction that is already known
+        let txid = tx.compute_txid();
+        {
+            let state = self.state.read().unwrap();
+            if state.txs.contains_key(&txid) {
+                return Ok(txid);
+            }
+            if let Some(input) = tx
+                .input
+                .iter()
+                .find(|input| state.spent.contains(&input.previous_output))
+            {
+                return Err(WalletError::BitcoinError(format!(
+                    "Input {} is already spent",
+                    input.previous_output
+                )));
+            }
+        }
+
+        self.insert_transaction(tx, None);
+        Ok(txid)
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        let mut txids = Vec::with_capacity(tx_hexes.len());
+        for tx_hex in tx_hexes {
+            txids.push(self.broadcast(tx_hex).await?);
+        }
+
+        Ok(json!({ "txids": txids }))
+    }
+
+    async fn test_mempool_accept(
+        &self,
+        tx_hexes: &[String],
+    ) -> WalletResult<Option<Vec<MempoolAccept>>> {
+        let reject_reason = self.state.read().unwrap().mempool_rejection.clone();
+
+        tx_hexes
+            .iter()
+            .map(|tx_hex| {
+                let tx: Transaction = deserialize_hex(tx_hex).map_err(|e| {
+                    WalletError::BitcoinError(format!("Deserialization failed: {}", e))
+                })?;
+                Ok(MempoolAccept {
+                    txid: tx.compute_txid().to_string(),
+                    allowed: reject_reason.is_none(),
+                    vsize: Some(tx.vsize() as u64),
+                    effective_fee_rate: None,
+                    reject_reason: reject_reason.clone(),
+                })
+            })
+            .collect::<WalletResult<Vec<_>>>()
+            .map(Some)
+    }
+
+    async fn estimate_fee(&self, _conf_target: u16) -> WalletResult<Option<f64>> {
+        Ok(self.state.read().unwrap().fee_rate)
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        let state = self.state.read().unwrap();
+
+        // One bucket per unconfirmed transaction whose inputs are all known
+        let mut histogram: Vec<(f64, u64)> = state
+            .txs
+            .iter()
+            .filter(|(txid, _)| !state.heights.contains_key(*txid))
+            .filter_map(|(_, tx)| {
+                let input_value = tx
+                    .input
+                    .iter()
+                    .map(|input| {
+                        state
+                            .txs
+                            .get(&input.previous_output.txid)?
+                            .output
+                            .get(input.previous_output.vout as usize)
+                            .map(|prev_out| prev_out.value.to_sat())
+                    })
+                    .sum::<Option<u64>>()?;
+                let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+                let vsize = tx.vsize() as u64;
+                let fee = input_value.checked_sub(output_value)?;
+                Some((fee as f64 / vsize as f64, vsize))
+            })
+            .collect();
+        histogram.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+        Ok(Some(histogram))
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        Ok(self.state.read().unwrap().tip_height)
+    }
+
+    async fn is_wallet_address(&self, address: &Address) -> WalletResult<bool> {
+        let state = self.state.read().unwrap();
+        Ok(state.wallet.contains(&address.script_pubkey()))
+    }
+}
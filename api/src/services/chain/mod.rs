@@ -0,0 +1,469 @@
+mod cli;
+mod electrum;
+mod esplora;
+#[cfg(test)]
+mod fake_electrum;
+#[cfg(test)]
+mod fake_esplora;
+#[cfg(test)]
+pub mod memory;
+mod router;
+mod rpc;
+
+pub use cli::CliBackend;
+pub use electrum::ElectrumBackend;
+pub use esplora::EsploraBackend;
+#[cfg(test)]
+pub use fake_electrum::FakeElectrum;
+#[cfg(test)]
+pub use fake_esplora::FakeEsplora;
+#[cfg(test)]
+pub use memory::MemoryBackend;
+pub use router::RouterBackend;
+pub use rpc::RpcBackend;
+
+use crate::config::{network_name, LimitsConfig, NetworkConfig};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo};
+use crate::services::bitcoin_cli::CliExecutor;
+use crate::services::bitcoin_rpc::RpcClient;
+use async_trait::async_trait;
+use bitcoin::{Address, Amount, Network, OutPoint, Transaction, Txid};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::Arc,
+    time::Duration,
+};
+
+/// Unconfirmed transaction together with its unconfirmed ancestors, as
+/// reported by `getmempoolentry`
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AncestorPackage {
+    /// Transactions in the package, the transaction itself included
+    pub count: u64,
+    pub vsize: u64,
+    /// Total fee in sats
+    pub fee: u64,
+}
+
+impl AncestorPackage {
+    /// Read the ancestor fields of a `getmempoolentry` result
+    pub fn from_mempool_entry(entry: &Value) -> WalletResult<Self> {
+        let field = |value: &Value, name: &str| {
+            value
+                .as_u64()
+                .ok_or_else(|| WalletError::BitcoinError(format!("Mempool entry has no {}", name)))
+        };
+        // Fees are reported in BTC
+        let fee = entry["fees"]["ancestor"]
+            .as_f64()
+            .and_then(|btc| Amount::from_btc(btc).ok())
+            .ok_or_else(|| {
+                WalletError::BitcoinError("Mempool entry has no fees.ancestor".into())
+            })?;
+
+        Ok(Self {
+            count: field(&entry["ancestorcount"], "ancestorcount")?,
+            vsize: field(&entry["ancestorsize"], "ancestorsize")?,
+            fee: fee.to_sat(),
+        })
+    }
+}
+
+/// Verdict of the mempool on one transaction, as reported by
+/// `testmempoolaccept` and `submitpackage`
+#[derive(Debug, Clone)]
+pub struct MempoolAccept {
+    pub txid: String,
+    pub allowed: bool,
+    pub vsize: Option<u64>,
+    /// Rate in sat/vB the transaction was judged at, which for a package
+    /// member includes the ancestors or descendants it was bundled with
+    pub effective_fee_rate: Option<f64>,
+    pub reject_reason: Option<String>,
+}
+
+impl MempoolAccept {
+    /// Read one result of `testmempoolaccept` or one entry of the
+    /// `tx-results` of `submitpackage`
+    pub fn from_node(result: &Value) -> Self {
+        let reject_reason = result["reject-reason"]
+            .as_str()
+            .or_else(|| result["package-error"].as_str())
+            .or_else(|| result["error"].as_str())
+            .map(str::to_string);
+
+        Self {
+            txid: result["txid"].as_str().unwrap_or_default().to_string(),
+            // submitpackage only reports the accepted ones without an error
+            allowed: result["allowed"]
+                .as_bool()
+                .unwrap_or(reject_reason.is_none()),
+            vsize: result["vsize"].as_u64(),
+            // Reported in BTC/kvB
+            effective_fee_rate: result["fees"]["effective-feerate"]
+                .as_f64()
+                .map(|rate| rate * 100_000.0),
+            reject_reason,
+        }
+    }
+}
+
+/// Confirmations of something mined at `block_height`, if at all
+pub fn confirmations(block_height: Option<u64>, tip_height: u64) -> u32 {
+    block_height.map_or(0, |height| {
+        (tip_height.saturating_sub(height) + 1)
+            .try_into()
+            .unwrap_or(u32::MAX)
+    })
+}
+
+// Backends report confirmation differently; any of these means mined
+pub fn is_confirmed(status: &Value) -> bool {
+    status["confirmed"].as_bool().unwrap_or(false)
+        || status["confirmations"].as_u64().unwrap_or(0) > 0
+}
+
+/// Whether a transaction is mined, asked through the verbose raw
+/// transaction rather than the status, which nodes only know for
+/// transactions of their wallet
+pub async fn is_mined(chain: &dyn ChainBackend, txid: &Txid) -> WalletResult<bool> {
+    Ok(is_confirmed(&chain.get_raw_transaction(txid, true).await?))
+}
+
+// Smallest bucket of `fee_histogram`, a tenth of a block
+const HISTOGRAM_BUCKET_VSIZE: u64 = 100_000;
+
+/// Fee histogram of a node's mempool from `getrawmempool true`: entries
+/// sorted by fee rate, highest first, and merged into buckets of at least
+/// `HISTOGRAM_BUCKET_VSIZE`, each at the lowest rate it holds
+pub fn fee_histogram(mempool: &Value) -> Vec<(f64, u64)> {
+    let mut entries: Vec<(f64, u64)> = mempool
+        .as_object()
+        .into_iter()
+        .flat_map(|entries| entries.values())
+        .filter_map(|entry| {
+            let vsize = entry["vsize"].as_u64().filter(|vsize| *vsize > 0)?;
+            // Reported in BTC; prioritisetransaction shows up in `modified`
+            let fee = entry["fees"]["modified"]
+                .as_f64()
+                .or_else(|| entry["fees"]["base"].as_f64())
+                .and_then(|btc| Amount::from_btc(btc).ok())?;
+            Some((fee.to_sat() as f64 / vsize as f64, vsize))
+        })
+        .collect();
+    entries.sort_by(|a, b| b.0.total_cmp(&a.0));
+
+    let mut histogram: Vec<(f64, u64)> = Vec::new();
+    for (rate, vsize) in entries {
+        match histogram.last_mut() {
+            Some(bucket) if bucket.1 < HISTOGRAM_BUCKET_VSIZE => {
+                bucket.0 = rate;
+                bucket.1 += vsize;
+            }
+            _ => histogram.push((rate, vsize)),
+        }
+    }
+    histogram
+}
+
+/// Source of chain data and broadcast used by every handler.
+///
+/// Implementations must be cheap to share across requests; handlers receive
+/// them as `Arc<dyn ChainBackend>` through the router state.
+#[async_trait]
+pub trait ChainBackend: Send + Sync {
+    /// Short name of the backend, used in logs and broadcast responses
+    fn name(&self) -> &'static str;
+
+    /// Fetch and decode a transaction
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction>;
+
+    /// Fetch several transactions, preserving the order of `txids`
+    async fn get_transactions(&self, txids: &[Txid]) -> WalletResult<Vec<Transaction>> {
+        let mut txs = Vec::with_capacity(txids.len());
+        for txid in txids {
+            txs.push(self.get_transaction(txid).await?);
+        }
+        Ok(txs)
+    }
+
+    /// Fetch a transaction as returned by the backend: hex when `verbose` is
+    /// false, decoded JSON otherwise
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value>;
+
+    /// Fetch the confirmation status of a transaction
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value>;
+
+    /// List unspent outputs paying to an address
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>>;
+
+    /// List unspent outputs of several addresses, grouped in the order of
+    /// `addresses`
+    async fn list_unspent_many(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        let mut grouped = Vec::with_capacity(addresses.len());
+        for address in addresses {
+            grouped.push(self.list_unspent(address).await?);
+        }
+        Ok(grouped)
+    }
+
+    /// Unspent outputs of the first `count` indexes of a ranged descriptor,
+    /// in one scan. `None` when the backend cannot scan by descriptor.
+    async fn list_unspent_descriptor(
+        &self,
+        _descriptor: &str,
+        _count: u32,
+    ) -> WalletResult<Option<Vec<Utxo>>> {
+        Ok(None)
+    }
+
+    /// Whether each address has ever been paid, or `None` when the backend
+    /// only sees unspent outputs and cannot tell
+    async fn addresses_used(&self, _addresses: &[Address]) -> WalletResult<Option<Vec<bool>>> {
+        Ok(None)
+    }
+
+    /// Whether an output exists and is unspent, counting mempool spends
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool>;
+
+    /// Where each output was spent, if it was, in the order given
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>>;
+
+    /// Broadcast a signed transaction
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid>;
+
+    /// Submit a package of transactions, parents first
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value>;
+
+    /// Check transactions, parents first, against mempool policy without
+    /// broadcasting them. `None` when the backend has no such check.
+    async fn test_mempool_accept(
+        &self,
+        _tx_hexes: &[String],
+    ) -> WalletResult<Option<Vec<MempoolAccept>>> {
+        Ok(None)
+    }
+
+    /// Estimate a fee rate in sat/vB for a confirmation target
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>>;
+
+    /// Mempool fee histogram as `(sat/vB, vsize)` buckets, highest rate
+    /// first, or `None` when the backend cannot report one
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        Ok(None)
+    }
+
+    /// Fee and size of an unconfirmed transaction and all of its
+    /// unconfirmed ancestors.
+    ///
+    /// The default walks the parents through [`ChainBackend::get_transaction`],
+    /// which costs a few round-trips per ancestor; nodes answer directly.
+    async fn mempool_ancestors(&self, txid: &Txid) -> WalletResult<AncestorPackage> {
+        let mut package = AncestorPackage::default();
+        let mut seen = HashSet::new();
+        let mut pending = vec![*txid];
+
+        while let Some(txid) = pending.pop() {
+            if !seen.insert(txid) || is_confirmed(&self.get_transaction_status(&txid).await?) {
+                continue;
+            }
+
+            let tx = self.get_transaction(&txid).await?;
+            let prev_txids: Vec<Txid> = tx.input.iter().map(|i| i.previous_output.txid).collect();
+            let prev_txs = self.get_transactions(&prev_txids).await?;
+
+            let input_value: u64 = tx
+                .input
+                .iter()
+                .zip(&prev_txs)
+                .filter_map(|(input, prev_tx)| {
+                    prev_tx.output.get(input.previous_output.vout as usize)
+                })
+                .map(|output| output.value.to_sat())
+                .sum();
+            let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+
+            package.count += 1;
+            package.vsize += tx.vsize() as u64;
+            package.fee += input_value.saturating_sub(output_value);
+            pending.extend(prev_txids);
+        }
+
+        Ok(package)
+    }
+
+    /// Height of the current chain tip
+    async fn tip_height(&self) -> WalletResult<u64>;
+
+    /// Sign a transaction with the backend's own wallet, if it has one
+    async fn sign_with_wallet(&self, _tx_hex: &str) -> WalletResult<String> {
+        Err(WalletError::BitcoinError(format!(
+            "Wallet signing is not supported by the {} backend",
+            self.name()
+        )))
+    }
+
+    /// Whether an address belongs to the backend's own wallet, if it has one
+    async fn is_wallet_address(&self, _address: &Address) -> WalletResult<bool> {
+        Err(WalletError::BitcoinError(format!(
+            "Wallet lookups are not supported by the {} backend",
+            self.name()
+        )))
+    }
+}
+
+/// Chain backends serving one network
+pub struct Chains {
+    /// Backend used when a request names no wallet
+    pub default: Arc<dyn ChainBackend>,
+    /// Backends bound to the node wallets in `rpc.wallets`
+    pub wallets: HashMap<String, Arc<dyn ChainBackend>>,
+}
+
+/// Build the chain backends of one network.
+///
+/// Several `chain.backends` build a [`RouterBackend`] over those sources, in
+/// order, with `chain.quorum` agreeing sources required for UTXO queries.
+/// Accepted values are `bitcoin-cli`, `bitcoin-rpc`, `esplora` and
+/// `electrum`.
+/// Every RPC-backed backend shares one [`RpcClient`].
+pub fn from_config(
+    network: Network,
+    config: &NetworkConfig,
+    limits: &LimitsConfig,
+) -> WalletResult<Chains> {
+    let mut builder = Builder {
+        network,
+        config,
+        limits,
+        rpc: None,
+    };
+    let kinds = &config.chain.backends;
+
+    let default: Arc<dyn ChainBackend> = if kinds.len() == 1 {
+        builder.backend(&kinds[0])?
+    } else {
+        let backends = kinds
+            .iter()
+            .map(|kind| builder.backend(kind))
+            .collect::<WalletResult<Vec<_>>>()?;
+        let timeout = limits.chain_timeout();
+
+        tracing::info!(
+            "Routing over {} with quorum {}",
+            kinds.join(", "),
+            config.chain.quorum
+        );
+        Arc::new(RouterBackend::new(backends, config.chain.quorum, timeout))
+    };
+    tracing::info!(
+        "Using {} chain backend for {}",
+        default.name(),
+        network_name(network)
+    );
+
+    let mut wallets = HashMap::new();
+    for name in &config.rpc.wallets {
+        let backend: Arc<dyn ChainBackend> =
+            Arc::new(RpcBackend::new(builder.rpc_client()?, Some(name.clone())));
+        wallets.insert(name.clone(), backend);
+    }
+
+    Ok(Chains { default, wallets })
+}
+
+struct Builder<'a> {
+    network: Network,
+    config: &'a NetworkConfig,
+    limits: &'a LimitsConfig,
+    rpc: Option<Arc<RpcClient>>,
+}
+
+impl Builder<'_> {
+    fn rpc_client(&mut self) -> WalletResult<Arc<RpcClient>> {
+        if let Some(client) = &self.rpc {
+            return Ok(client.clone());
+        }
+        let client = Arc::new(RpcClient::from_config(&self.config.rpc, self.limits)?);
+        self.rpc = Some(client.clone());
+        Ok(client)
+    }
+
+    fn backend(&mut self, kind: &str) -> WalletResult<Arc<dyn ChainBackend>> {
+        let config = self.config;
+        let network = self.network;
+
+        let backend: Arc<dyn ChainBackend> = match kind {
+            "bitcoin-cli" => Arc::new(CliBackend::new(CliExecutor::new(
+                network,
+                self.limits.bitcoin_cli_max_concurrency,
+                Duration::from_secs(self.limits.bitcoin_cli_timeout_secs),
+            ))),
+            "bitcoin-rpc" => Arc::new(RpcBackend::new(
+                self.rpc_client()?,
+                config.rpc.wallet.clone(),
+            )),
+            "esplora" => Arc::new(EsploraBackend::new(
+                required(&config.chain.esplora_url, "chain.esplora_url")?,
+                self.limits,
+            )?),
+            "electrum" => Arc::new(ElectrumBackend::new(
+                required(&config.chain.electrum_url, "chain.electrum_url")?,
+                self.limits,
+            )),
+            other => {
+                return Err(WalletError::NetworkError(format!(
+                    "Unknown chain backend: {}",
+                    other
+                )))
+            }
+        };
+
+        Ok(backend)
+    }
+}
+
+fn required<'a>(value: &'a Option<String>, name: &str) -> WalletResult<&'a str> {
+    value
+        .as_deref()
+        .ok_or_else(|| WalletError::NetworkError(format!("{} must be set", name)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn entry(fee_sats: u64, vsize: u64) -> Value {
+        json!({ "vsize": vsize, "fees": { "base": fee_sats as f64 / 100_000_000.0 } })
+    }
+
+    #[test]
+    fn fee_histogram_buckets_by_rate() {
+        let mempool = json!({
+            "a": entry(2_000, 1_000),
+            "b": entry(5_000_000, 100_000),
+            "c": entry(1_000_000, 50_000),
+            "d": entry(300_000, 60_000),
+        });
+
+        let histogram = fee_histogram(&mempool);
+
+        // 50 sat/vB fills a bucket alone; 20 and 5 share the next one
+        assert_eq!(
+            histogram,
+            vec![(50.0, 100_000), (5.0, 110_000), (2.0, 1_000)]
+        );
+    }
+
+    #[test]
+    fn fee_histogram_prefers_modified_fees() {
+        let mempool = json!({
+            "a": { "vsize": 100, "fees": { "base": 0.000001, "modified": 0.00001 } },
+        });
+
+        assert_eq!(fee_histogram(&mempool), vec![(10.0, 100)]);
+    }
+}
@@ -0,0 +1,535 @@
+use super::{AncestorPackage, ChainBackend, MempoolAccept};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo};
+use async_trait::async_trait;
+use bitcoin::{Address, OutPoint, Transaction, Txid};
+use futures::future::join_all;
+use serde_json::Value;
+use std::{
+    collections::HashMap,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::OnceCell;
+
+// Consecutive failures before a source is benched
+const MAX_FAILURES: u32 = 3;
+// How long a benched source is skipped
+const COOLDOWN: Duration = Duration::from_secs(30);
+// How long fetched transactions are served from cache
+const TX_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Routes calls over several chain sources.
+///
+/// Sources are tried in configured order, skipping ones that recently kept
+/// failing, and the next one is used when a source can't be reached or
+/// times out. An error a source answers with, such as a rejected broadcast
+/// or an unknown txid, is returned as is: the next source would say the same.
+/// With a quorum above one, UTXO and spent-status queries go to every
+/// source and only an answer that enough of them agree on is returned.
+pub struct RouterBackend {
+    sources: Vec<Source>,
+    quorum: usize,
+    timeout: Duration,
+    tx_cache: Mutex<HashMap<Txid, (Instant, Transaction)>>,
+    inflight: Mutex<HashMap<Txid, Arc<OnceCell<Transaction>>>>,
+}
+
+struct Source {
+    backend: Arc<dyn ChainBackend>,
+    health: Mutex<Health>,
+}
+
+#[derive(Default)]
+struct Health {
+    consecutive_failures: u32,
+    benched_until: Option<Instant>,
+}
+
+impl Source {
+    fn is_healthy(&self) -> bool {
+        let health = self.health.lock().unwrap();
+        health
+            .benched_until
+            .is_none_or(|until| Instant::now() >= until)
+    }
+
+    // Only failures to reach the source count toward benching it
+    fn record(&self, result: &WalletResult<impl Sized>) {
+        let mut health = self.health.lock().unwrap();
+        match result {
+            Err(e @ WalletError::NetworkError(_)) => {
+                health.consecutive_failures += 1;
+                if health.consecutive_failures >= MAX_FAILURES {
+                    tracing::warn!(
+                        "Benching {} chain source for {:?}: {}",
+                        self.backend.name(),
+                        COOLDOWN,
+                        e
+                    );
+                    health.benched_until = Some(Instant::now() + COOLDOWN);
+                }
+            }
+            _ => *health = Health::default(),
+        }
+    }
+}
+
+impl RouterBackend {
+    pub fn new(backends: Vec<Arc<dyn ChainBackend>>, quorum: usize, timeout: Duration) -> Self {
+        Self {
+            sources: backends
+                .into_iter()
+                .map(|backend| Source {
+                    backend,
+                    health: Mutex::new(Health::default()),
+                })
+                .collect(),
+            quorum: quorum.max(1),
+            timeout,
+            tx_cache: Mutex::new(HashMap::new()),
+            inflight: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Healthy sources in configured order, benched ones last as a final resort
+    fn ordered_sources(&self) -> Vec<&Source> {
+        let (mut healthy, benched): (Vec<&Source>, Vec<&Source>) =
+            self.sources.iter().partition(|s| s.is_healthy());
+        healthy.extend(benched);
+        healthy
+    }
+
+    async fn timed<T>(
+        &self,
+        source: &Source,
+        call: impl Future<Output = WalletResult<T>>,
+    ) -> WalletResult<T> {
+        let result = match tokio::time::timeout(self.timeout, call).await {
+            Ok(result) => result,
+            Err(_) => Err(WalletError::NetworkError(format!(
+                "{} timed out after {:?}",
+                source.backend.name(),
+                self.timeout
+            ))),
+        };
+        source.record(&result);
+        result
+    }
+
+    async fn failover<T, F, Fut>(&self, op: &str, call: F) -> WalletResult<T>
+    where
+        F: Fn(Arc<dyn ChainBackend>) -> Fut,
+        Fut: Future<Output = WalletResult<T>>,
+    {
+        let mut last_error = None;
+
+        for source in self.ordered_sources() {
+            match self.timed(source, call(source.backend.clone())).await {
+                Err(e @ WalletError::NetworkError(_)) => {
+                    tracing::warn!("{} failed on {}: {}", op, source.backend.name(), e);
+                    last_error = Some(e);
+                }
+                result => return result,
+            }
+        }
+
+        Err(last_error
+            .unwrap_or_else(|| WalletError::NetworkError("No chain sources configured".into())))
+    }
+
+    // Ask every healthy source and return an answer at least `quorum` of
+    // them agree on. Benched sources are asked too when there are not
+    // enough healthy ones left to reach it.
+    async fn agree<T, K, F, Fut>(&self, op: &str, key: impl Fn(&T) -> K, call: F) -> WalletResult<T>
+    where
+        K: PartialEq,
+        F: Fn(Arc<dyn ChainBackend>) -> Fut,
+        Fut: Future<Output = WalletResult<T>>,
+    {
+        let (mut sources, benched): (Vec<&Source>, Vec<&Source>) =
+            self.sources.iter().partition(|s| s.is_healthy());
+        if sources.len() < self.quorum {
+            sources.extend(benched);
+        }
+
+        let call = &call;
+        let calls = sources.into_iter().map(|source| async move {
+            (
+                source,
+                self.timed(source, call(source.backend.clone())).await,
+            )
+        });
+        let results = join_all(calls).await;
+
+        let mut groups: Vec<(K, T, usize)> = Vec::new();
+        let mut answered = 0;
+        for (source, result) in results {
+            match result {
+                Ok(value) => {
+                    answered += 1;
+                    let k = key(&value);
+                    match groups.iter_mut().find(|(group, _, _)| *group == k) {
+                        Some(group) => group.2 += 1,
+                        None => groups.push((k, value, 1)),
+                    }
+                }
+                Err(e) => tracing::warn!("{} failed on {}: {}", op, source.backend.name(), e),
+            }
+        }
+
+        groups
+            .into_iter()
+            .find(|(_, _, votes)| *votes >= self.quorum)
+            .map(|(_, value, _)| value)
+            .ok_or_else(|| {
+                WalletError::NetworkError(format!(
+                    "No quorum for {}: needed {} agreeing sources, {} of {} answered",
+                    op,
+                    self.quorum,
+                    answered,
+                    self.sources.len()
+                ))
+            })
+    }
+
+    fn cached_transaction(&self, txid: &Txid) -> Option<Transaction> {
+        let mut cache = self.tx_cache.lock().unwrap();
+        match cache.get(txid) {
+            Some((fetched, tx)) if fetched.elapsed() < TX_CACHE_TTL => Some(tx.clone()),
+            Some(_) => {
+                cache.remove(txid);
+                None
+            }
+            None => None,
+        }
+    }
+
+    // Entries that are never read again would otherwise stay forever, so
+    // expired ones are dropped whenever a new one goes in
+    fn cache_transaction(&self, txid: Txid, tx: Transaction) {
+        let mut cache = self.tx_cache.lock().unwrap();
+        cache.retain(|_, (fetched, _)| fetched.elapsed() < TX_CACHE_TTL);
+        cache.insert(txid, (Instant::now(), tx));
+    }
+}
+
+#[async_trait]
+impl ChainBackend for RouterBackend {
+    fn name(&self) -> &'static str {
+        "router"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        if let Some(tx) = self.cached_transaction(txid) {
+            return Ok(tx);
+        }
+
+        // Concurrent lookups of the same txid share one fetch
+        let cell = self
+            .inflight
+            .lock()
+            .unwrap()
+            .entry(*txid)
+            .or_default()
+            .clone();
+        let result = cell
+            .get_or_try_init(|| {
+                self.failover("get_transaction", |b| async move {
+                    b.get_transaction(txid).await
+                })
+            })
+            .await
+            .cloned();
+        self.inflight.lock().unwrap().remove(txid);
+
+        if let Ok(tx) = &result {
+            self.cache_transaction(*txid, tx.clone());
+        }
+        result
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        self.failover("get_raw_transaction", |b| async move {
+            b.get_raw_transaction(txid, verbose).await
+        })
+        .await
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        self.failover("get_transaction_status", |b| async move {
+            b.get_transaction_status(txid).await
+        })
+        .await
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        if self.quorum <= 1 {
+            return self
+                .failover(
+                    "list_unspent",
+                    |b| async move { b.list_unspent(address).await },
+                )
+                .await;
+        }
+
+        // Sources may list UTXOs in any order
+        let key = |utxos: &Vec<Utxo>| {
+            let mut key: Vec<_> = utxos.iter().map(|u| (u.outpoint(), u.value)).collect();
+            key.sort();
+            key
+        };
+        self.agree("list_unspent", key, |b| async move {
+            b.list_unspent(address).await
+        })
+        .await
+    }
+
+    async fn list_unspent_many(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        if self.quorum <= 1 {
+            return self
+                .failover("list_unspent_many", |b| async move {
+                    b.list_unspent_many(addresses).await
+                })
+                .await;
+        }
+
+        let key = |grouped: &Vec<Vec<Utxo>>| {
+            grouped
+                .iter()
+                .map(|utxos| {
+                    let mut key: Vec<_> = utxos.iter().map(|u| (u.outpoint(), u.value)).collect();
+                    key.sort();
+                    key
+                })
+                .collect::<Vec<_>>()
+        };
+        self.agree("list_unspent_many", key, |b| async move {
+            b.list_unspent_many(addresses).await
+        })
+        .await
+    }
+
+    async fn list_unspent_descriptor(
+        &self,
+        descriptor: &str,
+        count: u32,
+    ) -> WalletResult<Option<Vec<Utxo>>> {
+        self.failover("list_unspent_descriptor", |b| async move {
+            b.list_unspent_descriptor(descriptor, count).await
+        })
+        .await
+    }
+
+    async fn addresses_used(&self, addresses: &[Address]) -> WalletResult<Option<Vec<bool>>> {
+        self.failover("addresses_used", |b| async move {
+            b.addresses_used(addresses).await
+        })
+        .await
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        if self.quorum <= 1 {
+            return self
+                .failover(
+                    "is_unspent",
+                    |b| async move { b.is_unspent(outpoint).await },
+                )
+                .await;
+        }
+
+        self.agree(
+            "is_unspent",
+            |unspent| *unspent,
+            |b| async move { b.is_unspent(outpoint).await },
+        )
+        .await
+    }
+
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        if self.quorum <= 1 {
+            return self
+                .failover("spend_status", |b| async move {
+                    b.spend_status(outpoints).await
+                })
+                .await;
+        }
+
+        // Sources differ in how much they know about a spend, so only
+        // whether and where each output was spent has to agree
+        let key = |statuses: &Vec<SpendStatus>| {
+            statuses
+                .iter()
+                .map(std::mem::discriminant)
+                .collect::<Vec<_>>()
+        };
+        self.agree("spend_status", key, |b| async move {
+            b.spend_status(outpoints).await
+        })
+        .await
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        self.failover("broadcast", |b| async move { b.broadcast(tx_hex).await })
+            .await
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        self.failover("submit_package", |b| async move {
+            b.submit_package(tx_hexes).await
+        })
+        .await
+    }
+
+    async fn test_mempool_accept(
+        &self,
+        tx_hexes: &[String],
+    ) -> WalletResult<Option<Vec<MempoolAccept>>> {
+        self.failover("test_mempool_accept", |b| async move {
+            b.test_mempool_accept(tx_hexes).await
+        })
+        .await
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>> {
+        self.failover("estimate_fee", |b| async move {
+            b.estimate_fee(conf_target).await
+        })
+        .await
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        self.failover("mempool_histogram", |b| async move {
+            b.mempool_histogram().await
+        })
+        .await
+    }
+
+    async fn mempool_ancestors(&self, txid: &Txid) -> WalletResult<AncestorPackage> {
+        self.failover("mempool_ancestors", |b| async move {
+            b.mempool_ancestors(txid).await
+        })
+        .await
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        self.failover("tip_height", |b| async move { b.tip_height().await })
+            .await
+    }
+
+    async fn sign_with_wallet(&self, tx_hex: &str) -> WalletResult<String> {
+        self.failover("sign_with_wallet", |b| async move {
+            b.sign_with_wallet(tx_hex).await
+        })
+        .await
+    }
+
+    async fn is_wallet_address(&self, address: &Address) -> WalletResult<bool> {
+        self.failover("is_wallet_address", |b| async move {
+            b.is_wallet_address(address).await
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::{memory::address, MemoryBackend};
+    use bitcoin::{Amount, Network};
+
+    fn router(chains: &[Arc<MemoryBackend>], quorum: usize) -> RouterBackend {
+        let backends = chains
+            .iter()
+            .map(|chain| chain.clone() as Arc<dyn ChainBackend>)
+            .collect();
+        RouterBackend::new(backends, quorum, Duration::from_secs(5))
+    }
+
+    fn bench(source: &Source) {
+        source.health.lock().unwrap().benched_until = Some(Instant::now() + COOLDOWN);
+    }
+
+    #[tokio::test]
+    async fn quorum_falls_back_to_benched_sources() {
+        let address = address(1, Network::Regtest);
+        let chains = [
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+        ];
+        let outpoint = chains[0].fund(&address, Amount::from_sat(10_000), Some(1));
+        chains[1].fund(&address, Amount::from_sat(10_000), Some(1));
+
+        let router = router(&chains, 2);
+        bench(&router.sources[1]);
+
+        let utxos = router.list_unspent(&address).await.unwrap();
+        assert_eq!(utxos.len(), 1);
+        assert_eq!(utxos[0].outpoint(), outpoint);
+        // Answering again takes the source off the bench
+        assert!(router.sources[1].is_healthy());
+    }
+
+    #[tokio::test]
+    async fn quorum_is_not_met_by_disagreeing_sources() {
+        let address = address(1, Network::Regtest);
+        let chains = [
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+        ];
+        chains[0].fund(&address, Amount::from_sat(10_000), Some(1));
+
+        let router = router(&chains, 2);
+        assert!(router.list_unspent(&address).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn answered_errors_neither_bench_nor_fail_over() {
+        let chains = [
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+            Arc::new(MemoryBackend::new(Network::Regtest)),
+        ];
+        let only_second = chains[1].fund(&address(1, Network::Regtest), Amount::from_sat(1), None);
+
+        let router = router(&chains, 1);
+        for _ in 0..MAX_FAILURES {
+            match router.get_transaction(&only_second.txid).await {
+                Err(WalletError::BitcoinError(message)) => assert!(message.contains("not found")),
+                other => panic!("expected the first source's answer, got {:?}", other),
+            }
+        }
+
+        assert!(router.sources[0].is_healthy());
+        assert_eq!(
+            router.sources[0]
+                .health
+                .lock()
+                .unwrap()
+                .consecutive_failures,
+            0
+        );
+    }
+
+    #[tokio::test]
+    async fn caching_a_transaction_drops_expired_ones() {
+        let chain = Arc::new(MemoryBackend::new(Network::Regtest));
+        let address = address(1, Network::Regtest);
+        let stale = chain.fund(&address, Amount::from_sat(10_000), Some(1));
+        let fresh = chain.fund(&address, Amount::from_sat(20_000), Some(1));
+
+        let router = router(std::slice::from_ref(&chain), 1);
+        let stale_tx = router.get_transaction(&stale.txid).await.unwrap();
+        let expired = Instant::now() - TX_CACHE_TTL;
+        router
+            .tx_cache
+            .lock()
+            .unwrap()
+            .insert(stale.txid, (expired, stale_tx));
+
+        router.get_transaction(&fresh.txid).await.unwrap();
+        let cache = router.tx_cache.lock().unwrap();
+        assert!(cache.contains_key(&fresh.txid));
+        assert!(!cache.contains_key(&stale.txid));
+    }
+}
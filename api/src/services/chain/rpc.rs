@@ -0,0 +1,255 @@
+use super::{fee_histogram, AncestorPackage, ChainBackend, MempoolAccept};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{SpendStatus, Utxo};
+use crate::services::{bitcoin_cli, bitcoin_rpc::RpcClient};
+use async_trait::async_trait;
+use bitcoin::{consensus::encode::deserialize_hex, Address, OutPoint, Transaction, Txid};
+use serde::de::DeserializeOwned;
+use serde_json::{json, Value};
+use std::{collections::HashMap, sync::Arc};
+
+/// Backend that talks JSON-RPC to bitcoind.
+///
+/// Wallet-scoped calls (`listunspent`, `gettransaction`, signing) go to the
+/// configured wallet's endpoint; every backend shares the same client pool.
+pub struct RpcBackend {
+    client: Arc<RpcClient>,
+    wallet: Option<String>,
+}
+
+impl RpcBackend {
+    pub fn new(client: Arc<RpcClient>, wallet: Option<String>) -> Self {
+        Self { client, wallet }
+    }
+
+    async fn call<T: DeserializeOwned>(&self, method: &str, args: Vec<Value>) -> WalletResult<T> {
+        self.client.call(None, method, &args).await
+    }
+
+    async fn wallet_call<T: DeserializeOwned>(
+        &self,
+        method: &str,
+        args: Vec<Value>,
+    ) -> WalletResult<T> {
+        self.client
+            .call(self.wallet.as_deref(), method, &args)
+            .await
+    }
+}
+
+fn decode_tx(hex: &str) -> WalletResult<Transaction> {
+    deserialize_hex(hex)
+        .map_err(|e| WalletError::BitcoinError(format!("Deserialization failed: {}", e)))
+}
+
+#[async_trait]
+impl ChainBackend for RpcBackend {
+    fn name(&self) -> &'static str {
+        "bitcoin-rpc"
+    }
+
+    async fn get_transaction(&self, txid: &Txid) -> WalletResult<Transaction> {
+        let hex: String = self
+            .call("getrawtransaction", vec![json!(txid), json!(false)])
+            .await?;
+
+        decode_tx(&hex)
+    }
+
+    async fn get_transactions(&self, txids: &[Txid]) -> WalletResult<Vec<Transaction>> {
+        // One round-trip for all of them
+        let calls: Vec<(&str, Vec<Value>)> = txids
+            .iter()
+            .map(|txid| ("getrawtransaction", vec![json!(txid), json!(false)]))
+            .collect();
+
+        self.client
+            .batch(None, &calls)
+            .await?
+            .into_iter()
+            .map(|result| {
+                let hex = result?;
+                decode_tx(hex.as_str().unwrap_or_default())
+            })
+            .collect()
+    }
+
+    async fn get_raw_transaction(&self, txid: &Txid, verbose: bool) -> WalletResult<Value> {
+        self.call("getrawtransaction", vec![json!(txid), json!(verbose)])
+            .await
+    }
+
+    async fn get_transaction_status(&self, txid: &Txid) -> WalletResult<Value> {
+        self.wallet_call("gettransaction", vec![json!(txid)]).await
+    }
+
+    async fn list_unspent(&self, address: &Address) -> WalletResult<Vec<Utxo>> {
+        let items: Vec<bitcoin_cli::BListUnspentItem> = self
+            .wallet_call(
+                "listunspent",
+                vec![json!(0), json!(9999999), json!([address.to_string()])],
+            )
+            .await?;
+
+        Ok(bitcoin_cli::to_utxos(items, self.tip_height().await?))
+    }
+
+    // One scan of the UTXO set, so the addresses need not be in the wallet,
+    // but outputs still in the mempool are not seen
+    async fn list_unspent_many(&self, addresses: &[Address]) -> WalletResult<Vec<Vec<Utxo>>> {
+        let scan: bitcoin_cli::BScanTxOutSet = self
+            .call(
+                "scantxoutset",
+                vec![
+                    json!("start"),
+                    json!(bitcoin_cli::address_descriptors(addresses)),
+                ],
+            )
+            .await?;
+
+        Ok(bitcoin_cli::group_by_address(scan, addresses))
+    }
+
+    async fn list_unspent_descriptor(
+        &self,
+        descriptor: &str,
+        count: u32,
+    ) -> WalletResult<Option<Vec<Utxo>>> {
+        let range = bitcoin_cli::ranged_descriptor(descriptor, count);
+        let scan: bitcoin_cli::BScanTxOutSet = self
+            .call("scantxoutset", vec![json!("start"), json!([range])])
+            .await?;
+        Ok(Some(bitcoin_cli::scan_utxos(scan)))
+    }
+
+    async fn is_unspent(&self, outpoint: &OutPoint) -> WalletResult<bool> {
+        let tx_out: Option<Value> = self
+            .call(
+                "gettxout",
+                vec![json!(outpoint.txid), json!(outpoint.vout), json!(true)],
+            )
+            .await?;
+        Ok(tx_out.is_some())
+    }
+
+    // One batch of `gettxout` for all of them, then one
+    // `gettxspendingprevout` for those spent or missing
+    async fn spend_status(&self, outpoints: &[OutPoint]) -> WalletResult<Vec<SpendStatus>> {
+        let calls: Vec<(&str, Vec<Value>)> = outpoints
+            .iter()
+            .map(|outpoint| {
+                (
+                    "gettxout",
+                    vec![json!(outpoint.txid), json!(outpoint.vout), json!(true)],
+                )
+            })
+            .collect();
+        let unspent = self
+            .client
+            .batch(None, &calls)
+            .await?
+            .into_iter()
+            .map(|tx_out| Ok(!tx_out?.is_null()))
+            .collect::<WalletResult<Vec<bool>>>()?;
+
+        let spent: Vec<OutPoint> = outpoints
+            .iter()
+            .zip(&unspent)
+            .filter(|(_, unspent)| !**unspent)
+            .map(|(outpoint, _)| *outpoint)
+            .collect();
+        let spending: Vec<_> = if spent.is_empty() {
+            Vec::new()
+        } else {
+            self.call(
+                "gettxspendingprevout",
+                vec![json!(bitcoin_cli::prevouts(&spent))],
+            )
+            .await?
+        };
+
+        // An error for one transaction means the node does not know it
+        let txids = bitcoin_cli::unresolved_txids(outpoints, &unspent, &spending);
+        let calls: Vec<(&str, Vec<Value>)> = txids
+            .iter()
+            .map(|txid| ("getrawtransaction", vec![json!(txid), json!(true)]))
+            .collect();
+        let mut output_counts = HashMap::new();
+        for (txid, tx) in txids.iter().zip(self.client.batch(None, &calls).await?) {
+            match tx {
+                Ok(tx) => {
+                    let count = tx["vout"].as_array().map_or(0, Vec::len);
+                    output_counts.insert(*txid, count);
+                }
+                Err(WalletError::BitcoinError(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
+
+        Ok(bitcoin_cli::spend_status(
+            outpoints,
+            &unspent,
+            spending,
+            &output_counts,
+        ))
+    }
+
+    async fn broadcast(&self, tx_hex: &str) -> WalletResult<Txid> {
+        self.call("sendrawtransaction", vec![json!(tx_hex)]).await
+    }
+
+    async fn submit_package(&self, tx_hexes: &[String]) -> WalletResult<Value> {
+        self.call("submitpackage", vec![json!(tx_hexes)]).await
+    }
+
+    async fn test_mempool_accept(
+        &self,
+        tx_hexes: &[String],
+    ) -> WalletResult<Option<Vec<MempoolAccept>>> {
+        let results: Vec<Value> = self
+            .call("testmempoolaccept", vec![json!(tx_hexes)])
+            .await?;
+        Ok(Some(results.iter().map(MempoolAccept::from_node).collect()))
+    }
+
+    async fn estimate_fee(&self, conf_target: u16) -> WalletResult<Option<f64>> {
+        let estimate: Value = self
+            .call("estimatesmartfee", vec![json!(conf_target)])
+            .await?;
+
+        // bitcoind reports BTC/kvB
+        Ok(estimate["feerate"].as_f64().map(|rate| rate * 100_000.0))
+    }
+
+    async fn mempool_histogram(&self) -> WalletResult<Option<Vec<(f64, u64)>>> {
+        let mempool: Value = self.call("getrawmempool", vec![json!(true)]).await?;
+        Ok(Some(fee_histogram(&mempool)))
+    }
+
+    async fn mempool_ancestors(&self, txid: &Txid) -> WalletResult<AncestorPackage> {
+        let entry: Value = self.call("getmempoolentry", vec![json!(txid)]).await?;
+        AncestorPackage::from_mempool_entry(&entry)
+    }
+
+    async fn tip_height(&self) -> WalletResult<u64> {
+        self.call("getblockcount", vec![]).await
+    }
+
+    async fn sign_with_wallet(&self, tx_hex: &str) -> WalletResult<String> {
+        let result: Value = self
+            .wallet_call("signrawtransactionwithwallet", vec![json!(tx_hex)])
+            .await?;
+
+        result["hex"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| WalletError::BitcoinError("Failed to get signed transaction hex".into()))
+    }
+
+    async fn is_wallet_address(&self, address: &Address) -> WalletResult<bool> {
+        let info: Value = self
+            .wallet_call("getaddressinfo", vec![json!(address.to_string())])
+            .await?;
+        Ok(info["ismine"].as_bool().unwrap_or(false))
+    }
+}
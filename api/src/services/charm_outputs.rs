@@ -0,0 +1,106 @@
+use crate::models::CharmAsset;
+use bitcoin::{Transaction, Wtxid};
+use std::{
+    collections::{BTreeSet, HashMap},
+    sync::{Arc, LazyLock, Mutex},
+};
+
+// Transactions whose charms are kept; verifying a spell takes a proof check
+const CACHE_SIZE: usize = 4096;
+
+type Cache = Mutex<HashMap<Wtxid, Arc<Vec<Vec<CharmAsset>>>>>;
+
+static CHARMS: LazyLock<Cache> = LazyLock::new(Default::default);
+
+/// Charms held by each output of `tx`, or none at all unless it has a
+/// spell that verifies.
+///
+/// Verification is CPU-bound, so it runs on the blocking pool, and its
+/// outcome is cached. The cache is keyed by wtxid because the spell sits in
+/// the witness, which the txid does not commit to.
+pub async fn output_charms(tx: &Transaction) -> Arc<Vec<Vec<CharmAsset>>> {
+    let wtxid = tx.compute_wtxid();
+    if let Some(charms) = CHARMS.lock().unwrap().get(&wtxid) {
+        return charms.clone();
+    }
+
+    let tx = tx.clone();
+    let charms = match tokio::task::spawn_blocking(move || verify(&tx)).await {
+        Ok(charms) => Arc::new(charms),
+        Err(e) => {
+            tracing::error!("Spell verification of {} failed: {}", wtxid, e);
+            return Arc::default();
+        }
+    };
+
+    let mut cache = CHARMS.lock().unwrap();
+    if cache.len() >= CACHE_SIZE {
+        if let Some(evicted) = cache.keys().next().copied() {
+            cache.remove(&evicted);
+        }
+    }
+    cache.insert(wtxid, charms.clone());
+    charms
+}
+
+fn verify(tx: &Transaction) -> Vec<Vec<CharmAsset>> {
+    let Some(spell) = charms::tx::norm_spell(tx) else {
+        return Vec::new();
+    };
+    // Charms name their app by its index here
+    let apps: Vec<_> = spell.app_public_inputs.keys().collect();
+
+    spell
+        .tx
+        .outs
+        .iter()
+        .map(|held| {
+            held.iter()
+                .filter_map(|(app_index, data)| {
+                    let app = apps.get(*app_index)?;
+                    Some(CharmAsset {
+                        app: app.to_string(),
+                        amount: (app.tag == charms_data::TOKEN)
+                            .then(|| data.value::<u64>().ok())
+                            .flatten(),
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// Indices of the outputs of `tx` that carry charms.
+///
+/// Only a spell that verifies assigns charms, so a transaction without one,
+/// or with an invalid one, has none.
+pub async fn charm_outputs(tx: &Transaction) -> BTreeSet<usize> {
+    output_charms(tx)
+        .await
+        .iter()
+        .enumerate()
+        .filter(|(_, charms)| !charms.is_empty())
+        .map(|(vout, _)| vout)
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use bitcoin::{Amount, Network, OutPoint, TxOut};
+
+    #[tokio::test]
+    async fn caches_transactions_without_a_spell() {
+        let tx = spending_tx(
+            &[OutPoint::null()],
+            vec![TxOut {
+                value: Amount::from_sat(1_000),
+                script_pubkey: address(1, Network::Regtest).script_pubkey(),
+            }],
+        );
+
+        assert!(charm_outputs(&tx).await.is_empty());
+        assert!(CHARMS.lock().unwrap().contains_key(&tx.compute_wtxid()));
+    }
+}
@@ -0,0 +1,412 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{CoinSelectRequest, CoinSelectResponse, ScriptType, SelectionAlgorithm, Utxo};
+use crate::services::address::parse_address;
+use crate::services::assets::classify;
+use crate::services::chain::ChainBackend;
+use crate::services::preflight::MAX_FEE_RATE;
+use crate::services::vsize::{estimate_tx_size, InputType, OutputType, TxSize};
+use bitcoin::{Address, Amount, Network, OutPoint};
+use rand::Rng;
+
+const MAX_ADDRESSES: usize = 1000;
+const MAX_CANDIDATES: usize = 5000;
+// Branches branch-and-bound may visit before giving up, as in bitcoind
+const BNB_MAX_TRIES: usize = 100_000;
+// Random subsets the knapsack tries
+const KNAPSACK_ITERATIONS: usize = 1000;
+// Returned with every selection, as callers must not take `excluded` for
+// the whole story
+const ASSET_DETECTION: &str = "Assets are read from the transaction that created each UTXO: \
+    charms of a verified spell, inscriptions it revealed and runes its runestone assigned. \
+    Inscriptions and runes that only passed through it are not detected.";
+
+// A UTXO that pays for itself at the fee rate
+struct Candidate {
+    utxo: Utxo,
+    input_type: InputType,
+    /// Value less the fee of spending it
+    effective_value: u64,
+}
+
+// Inputs chosen, by index into the candidates, and the transaction
+// they make
+struct Selection {
+    selected: Vec<usize>,
+    change: Option<u64>,
+    fee: u64,
+    size: TxSize,
+    algorithm: SelectionAlgorithm,
+}
+
+// What every selection for one request shares
+struct Selector {
+    target: u64,
+    fee_rate: f64,
+    outputs: Vec<OutputType>,
+    change: OutputType,
+    change_dust: u64,
+    /// Fee of creating the change output and of spending it later
+    cost_of_change: u64,
+}
+
+fn fee_for_weight(weight: u64, fee_rate: f64) -> u64 {
+    (weight as f64 * fee_rate / 4.0).ceil() as u64
+}
+
+// Output and input types of change, and the dust limit bitcoind relays
+// it above
+fn change_types(script_type: ScriptType) -> (OutputType, InputType, u64) {
+    match script_type {
+        ScriptType::Pkh => (OutputType::P2pkh, InputType::P2pkh, 546),
+        ScriptType::ShWpkh => (OutputType::P2sh, InputType::P2shP2wpkh, 540),
+        ScriptType::Wpkh => (OutputType::P2wpkh, InputType::P2wpkh, 294),
+        ScriptType::Tr => (OutputType::P2tr, InputType::P2trKeyPath, 330),
+    }
+}
+
+impl Selector {
+    // Value the inputs must cover beyond their own fees: the target and
+    // the fee of the rest of the transaction. The probe input brings the
+    // segwit header and input count along, then is taken off again.
+    fn selection_target(&self) -> u64 {
+        let probe = [InputType::P2wpkh];
+        let overhead = estimate_tx_size(&probe, &self.outputs).weight - probe[0].weight();
+        self.target + fee_for_weight(overhead, self.fee_rate)
+    }
+
+    // Price `selected` exactly, with change when the excess pays for it
+    // and clears the dust limit, else with the excess left to the fee
+    fn finish(
+        &self,
+        candidates: &[Candidate],
+        selected: &[usize],
+        algorithm: SelectionAlgorithm,
+    ) -> Option<Selection> {
+        let inputs: Vec<InputType> = selected
+            .iter()
+            .map(|i| candidates[*i].input_type.clone())
+            .collect();
+        let total: u64 = selected
+            .iter()
+            .map(|i| candidates[*i].utxo.value.to_sat())
+            .sum();
+
+        let size = estimate_tx_size(&inputs, &self.outputs);
+        let fee = (size.vsize as f64 * self.fee_rate).ceil() as u64;
+        let excess = total.checked_sub(self.target + fee)?;
+
+        let mut with_change = self.outputs.clone();
+        with_change.push(self.change.clone());
+        let size_with_change = estimate_tx_size(&inputs, &with_change);
+        let fee_with_change = (size_with_change.vsize as f64 * self.fee_rate).ceil() as u64;
+        let change = total.saturating_sub(self.target + fee_with_change);
+
+        Some(
+            if excess > self.cost_of_change && change >= self.change_dust {
+                Selection {
+                    selected: selected.to_vec(),
+                    change: Some(change),
+                    fee: fee_with_change,
+                    size: size_with_change,
+                    algorithm,
+                }
+            } else {
+                Selection {
+                    selected: selected.to_vec(),
+                    change: None,
+                    fee: fee + excess,
+                    size,
+                    algorithm,
+                }
+            },
+        )
+    }
+}
+
+// Depth-first search, largest first, for inputs whose effective value
+// lands between `target` and `target + cost_of_change`, so no change is
+// needed; the one overshooting least wins
+fn branch_and_bound(values: &[u64], target: u64, cost_of_change: u64) -> Option<Vec<usize>> {
+    // Value still available from each index on
+    let mut remaining = vec![0; values.len() + 1];
+    for i in (0..values.len()).rev() {
+        remaining[i] = remaining[i + 1] + values[i];
+    }
+
+    let mut best: Option<(u64, Vec<usize>)> = None;
+    let mut selected: Vec<usize> = Vec::new();
+    let mut value = 0;
+    let mut i = 0;
+    for _ in 0..BNB_MAX_TRIES {
+        let backtrack = if value + remaining[i] < target || value > target + cost_of_change {
+            true
+        } else if value >= target {
+            let waste = value - target;
+            if best
+                .as_ref()
+                .is_none_or(|(best_waste, _)| waste < *best_waste)
+            {
+                best = Some((waste, selected.clone()));
+            }
+            if waste == 0 {
+                break;
+            }
+            true
+        } else {
+            false
+        };
+
+        if backtrack {
+            // Leave out the last input taken, and the equal ones after it,
+            // which would only repeat the same sums
+            let Some(last) = selected.pop() else {
+                break;
+            };
+            value -= values[last];
+            i = last + 1;
+            while i < values.len() && values[i] == values[last] {
+                i += 1;
+            }
+        } else {
+            selected.push(i);
+            value += values[i];
+            i += 1;
+        }
+    }
+
+    best.map(|(_, selected)| selected)
+}
+
+// bitcoind's knapsack: the smallest single input covering `target`, or
+// the best of many random subsets of the smaller ones, whichever is closer
+fn knapsack(values: &[u64], target: u64) -> Option<Vec<usize>> {
+    let mut smaller: Vec<usize> = (0..values.len()).filter(|i| values[*i] < target).collect();
+    let lowest_larger = (0..values.len())
+        .filter(|i| values[*i] >= target)
+        .min_by_key(|i| values[*i]);
+    let total_smaller: u64 = smaller.iter().map(|i| values[*i]).sum();
+
+    if total_smaller == target {
+        return Some(smaller);
+    }
+    if total_smaller < target {
+        return lowest_larger.map(|i| vec![i]);
+    }
+
+    smaller.sort_by_key(|i| std::cmp::Reverse(values[*i]));
+    let mut rng = rand::thread_rng();
+    let mut best = vec![true; smaller.len()];
+    let mut best_value = total_smaller;
+    for _ in 0..KNAPSACK_ITERATIONS {
+        if best_value == target {
+            break;
+        }
+        let mut included = vec![false; smaller.len()];
+        let mut value = 0;
+        // A random pass, then a pass taking whatever is left, each
+        // stepping back from every subset that reaches the target
+        'passes: for pass in 0..2 {
+            for (k, i) in smaller.iter().enumerate() {
+                let take = if pass == 0 {
+                    rng.gen_bool(0.5)
+                } else {
+                    !included[k]
+                };
+                if !take {
+                    continue;
+                }
+                value += values[*i];
+                included[k] = true;
+                if value >= target {
+                    if value < best_value {
+                        best_value = value;
+                        best = included.clone();
+                    }
+                    value -= values[*i];
+                    included[k] = false;
+                    if pass == 1 {
+                        break 'passes;
+                    }
+                }
+            }
+        }
+    }
+
+    match lowest_larger {
+        Some(i) if best_value != target && values[i] <= best_value => Some(vec![i]),
+        _ => Some(
+            smaller
+                .iter()
+                .zip(best)
+                .filter(|(_, included)| *included)
+                .map(|(i, _)| *i)
+                .collect(),
+        ),
+    }
+}
+
+// Largest inputs until the target is covered
+fn largest_first(values: &[u64], target: u64) -> Option<Vec<usize>> {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by_key(|i| std::cmp::Reverse(values[*i]));
+
+    let mut value = 0;
+    let mut selected = Vec::new();
+    for i in order {
+        if value >= target {
+            break;
+        }
+        value += values[i];
+        selected.push(i);
+    }
+    (value >= target).then_some(selected)
+}
+
+/// Choose inputs for a plain BTC payment of `target` at `fee_rate`.
+///
+/// Branch-and-bound looks for inputs that need no change first, then the
+/// knapsack and largest-first fallbacks pick inputs leaving change of
+/// `change_type`. Fees come from the exact size of the transaction the
+/// inputs make. UTXOs that cost more to spend than they hold are left out.
+///
+/// So are UTXOs found to carry charms, inscriptions or runes, going by the
+/// transaction that created each rather than the request, and those whose
+/// transaction could not be checked. That check only sees what the
+/// creating transaction did: a verified spell, inscriptions it revealed and
+/// runes its runestone assigned. Inscriptions and runes that passed through
+/// it from earlier transactions take an ord or rune index to see, and may
+/// still be selected.
+pub async fn select(
+    chain: &dyn ChainBackend,
+    request: CoinSelectRequest,
+    network: Network,
+) -> WalletResult<CoinSelectResponse> {
+    if !(request.fee_rate > 0.0 && request.fee_rate <= MAX_FEE_RATE) {
+        return Err(WalletError::InvalidRequest(format!(
+            "fee_rate must be above 0 and at most {} sat/vB",
+            MAX_FEE_RATE
+        )));
+    }
+    let mut utxos = match (request.utxos.is_empty(), request.addresses.is_empty()) {
+        (false, true) => request.utxos,
+        (true, false) => {
+            if request.addresses.len() > MAX_ADDRESSES {
+                return Err(WalletError::InvalidRequest(format!(
+                    "At most {} addresses per request",
+                    MAX_ADDRESSES
+                )));
+            }
+            let addresses = request
+                .addresses
+                .iter()
+                .map(|address| parse_address(address, network))
+                .collect::<WalletResult<Vec<Address>>>()?;
+            chain
+                .list_unspent_many(&addresses)
+                .await?
+                .into_iter()
+                .flatten()
+                .collect()
+        }
+        _ => {
+            return Err(WalletError::InvalidRequest(
+                "Give exactly one of utxos or addresses".to_string(),
+            ))
+        }
+    };
+    if utxos.len() > MAX_CANDIDATES {
+        return Err(WalletError::InvalidRequest(format!(
+            "At most {} candidate UTXOs per request",
+            MAX_CANDIDATES
+        )));
+    }
+
+    // A UTXO whose transaction could not be checked may carry anything, so
+    // it is left out too
+    let unchecked = classify(chain, &mut utxos).await?;
+    let (utxos, left_out): (Vec<Utxo>, Vec<Utxo>) = utxos
+        .into_iter()
+        .partition(|utxo| utxo.assets.is_empty() && !unchecked.contains(&utxo.outpoint()));
+    let (unchecked, excluded): (Vec<OutPoint>, Vec<OutPoint>) = left_out
+        .iter()
+        .map(Utxo::outpoint)
+        .partition(|outpoint| unchecked.contains(outpoint));
+
+    let mut candidates = Vec::with_capacity(utxos.len());
+    for utxo in utxos {
+        let input_type = InputType::from_script_pubkey(&utxo.script_pubkey).ok_or_else(|| {
+            WalletError::InvalidRequest(format!(
+                "Cannot size the input spending {}; give its script_pubkey",
+                utxo.outpoint()
+            ))
+        })?;
+        let fee = fee_for_weight(input_type.weight(), request.fee_rate);
+        if let Some(effective_value) = utxo.value.to_sat().checked_sub(fee).filter(|v| *v > 0) {
+            candidates.push(Candidate {
+                utxo,
+                input_type,
+                effective_value,
+            });
+        }
+    }
+    candidates.sort_by_key(|candidate| std::cmp::Reverse(candidate.effective_value));
+
+    let (change, change_input, change_dust) = change_types(request.change_type);
+    let selector = Selector {
+        target: request.target.to_sat(),
+        fee_rate: request.fee_rate,
+        outputs: request.outputs.unwrap_or_else(|| vec![change.clone()]),
+        cost_of_change: fee_for_weight(change.weight() + change_input.weight(), request.fee_rate),
+        change,
+        change_dust,
+    };
+
+    let values: Vec<u64> = candidates.iter().map(|c| c.effective_value).collect();
+    let target = selector.selection_target();
+    // Enough over the target that change clears the dust limit
+    let target_with_change = target + selector.cost_of_change + selector.change_dust;
+    let selection = branch_and_bound(&values, target, selector.cost_of_change)
+        .and_then(|selected| {
+            selector.finish(&candidates, &selected, SelectionAlgorithm::BranchAndBound)
+        })
+        .or_else(|| {
+            knapsack(&values, target_with_change)
+                .or_else(|| knapsack(&values, target))
+                .and_then(|selected| {
+                    selector.finish(&candidates, &selected, SelectionAlgorithm::Knapsack)
+                })
+        })
+        .or_else(|| {
+            largest_first(&values, target).and_then(|selected| {
+                selector.finish(&candidates, &selected, SelectionAlgorithm::LargestFirst)
+            })
+        });
+
+    let Some(selection) = selection else {
+        let available: u64 = candidates.iter().map(|c| c.utxo.value.to_sat()).sum();
+        return Err(WalletError::InsufficientFunds(format!(
+            "{} sats at {} sat/vB cannot be paid from {} sats in {} spendable UTXOs",
+            selector.target,
+            request.fee_rate,
+            available,
+            candidates.len()
+        )));
+    };
+
+    let mut candidates: Vec<Option<Candidate>> = candidates.into_iter().map(Some).collect();
+    Ok(CoinSelectResponse {
+        inputs: selection
+            .selected
+            .iter()
+            .filter_map(|i| candidates[*i].take())
+            .map(|candidate| candidate.utxo)
+            .collect(),
+        change: selection.change.map(Amount::from_sat),
+        fee: Amount::from_sat(selection.fee),
+        size: selection.size,
+        algorithm: selection.algorithm,
+        excluded,
+        unchecked,
+        asset_detection: ASSET_DETECTION,
+    })
+}
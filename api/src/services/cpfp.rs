@@ -0,0 +1,275 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{BroadcastTxResponse, CpfpRequest, CpfpResponse};
+use crate::services::broadcast;
+use crate::services::chain::{is_mined, ChainBackend};
+use crate::services::charm_outputs::charm_outputs;
+use crate::services::vsize::{estimate_tx_size, InputType, OutputType};
+use bitcoin::{
+    absolute::LockTime, consensus::encode::deserialize_hex, psbt::Psbt, transaction::Version,
+    Address, Amount, Network, OutPoint, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+
+/// Lowest rate in sat/vB the child may pay on its own (bitcoind's default
+/// `-minrelaytxfee`)
+pub const MIN_RELAY_FEE: f64 = 1.0;
+
+/// Build a child spending `request.vout` of a stuck transaction that lifts
+/// its whole unconfirmed package to `request.fee_rate`. The output must pay
+/// to an address of the backend's wallet.
+///
+/// The child has a single input and sends everything but its fee to
+/// `address`. It is returned as an unsigned PSBT; once signed, it goes out
+/// together with its parents through [`submit`].
+pub async fn plan(
+    chain: &dyn ChainBackend,
+    network: Network,
+    address: &Address,
+    request: &CpfpRequest,
+) -> WalletResult<CpfpResponse> {
+    let txid = &request.txid;
+    let parent = chain.get_transaction(txid).await?;
+    if is_mined(chain, txid).await? {
+        return Err(WalletError::InvalidRequest(format!(
+            "Transaction {} is already confirmed",
+            txid
+        )));
+    }
+
+    let vout = request.vout as usize;
+    let prevout = parent.output.get(vout).cloned().ok_or_else(|| {
+        WalletError::InvalidRequest(format!("Transaction has no output {}", vout))
+    })?;
+    if charm_outputs(&parent).await.contains(&vout) {
+        return Err(WalletError::InvalidRequest(format!(
+            "Output {} carries charms and cannot pay for the child",
+            vout
+        )));
+    }
+    let is_ours = match Address::from_script(&prevout.script_pubkey, network) {
+        Ok(owner) => chain.is_wallet_address(&owner).await?,
+        Err(_) => false,
+    };
+    if !is_ours {
+        return Err(WalletError::InvalidRequest(format!(
+            "Output {} does not pay to this wallet and cannot pay for the child",
+            vout
+        )));
+    }
+    let outpoint = OutPoint::new(*txid, request.vout);
+    if !chain.is_unspent(&outpoint).await? {
+        return Err(WalletError::InvalidRequest(format!(
+            "Output {} is already spent",
+            outpoint
+        )));
+    }
+
+    let input_type = InputType::from_script_pubkey(&prevout.script_pubkey).ok_or_else(|| {
+        WalletError::InvalidRequest(format!("Cannot spend output {} of this script type", vout))
+    })?;
+    let output_type =
+        OutputType::from_script_pubkey(&address.script_pubkey()).ok_or_else(|| {
+            WalletError::InvalidAddress(format!("Unsupported address type: {}", address))
+        })?;
+    let child_vsize = estimate_tx_size(&[input_type], &[output_type]).vsize;
+
+    let package = chain.mempool_ancestors(txid).await?;
+    let package_fee_rate = package.fee as f64 / package.vsize as f64;
+    if request.fee_rate <= package_fee_rate {
+        return Err(WalletError::InvalidRequest(format!(
+            "Package already pays {:.2} sat/vB, above the target {} sat/vB",
+            package_fee_rate, request.fee_rate
+        )));
+    }
+
+    // The child pays for the whole package at the target rate, but never
+    // less than its own relay fee
+    let total_vsize = package.vsize + child_vsize;
+    let child_fee = ((request.fee_rate * total_vsize as f64).ceil() as u64)
+        .saturating_sub(package.fee)
+        .max((MIN_RELAY_FEE * child_vsize as f64).ceil() as u64);
+
+    let dust_limit = address.script_pubkey().minimal_non_dust().to_sat();
+    let child_value = prevout
+        .value
+        .to_sat()
+        .checked_sub(child_fee)
+        .filter(|value| *value >= dust_limit)
+        .ok_or_else(|| {
+            WalletError::InsufficientFunds(format!(
+                "Output of {} sats cannot cover a child fee of {} sats",
+                prevout.value.to_sat(),
+                child_fee
+            ))
+        })?;
+
+    let child = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::ZERO,
+        input: vec![TxIn {
+            previous_output: outpoint,
+            script_sig: ScriptBuf::new(),
+            sequence: Sequence::ENABLE_RBF_NO_LOCKTIME,
+            witness: Witness::new(),
+        }],
+        output: vec![TxOut {
+            value: Amount::from_sat(child_value),
+            script_pubkey: address.script_pubkey(),
+        }],
+    };
+
+    let mut psbt = Psbt::from_unsigned_tx(child)
+        .map_err(|e| WalletError::BitcoinError(format!("Failed to build PSBT: {}", e)))?;
+    if !prevout.script_pubkey.is_witness_program() {
+        psbt.inputs[0].non_witness_utxo = Some(parent);
+    }
+    psbt.inputs[0].witness_utxo = Some(prevout);
+
+    Ok(CpfpResponse {
+        psbt: psbt.to_string(),
+        parent: txid.to_string(),
+        package_count: package.count,
+        package_vsize: package.vsize,
+        package_fee: package.fee,
+        package_fee_rate,
+        child_vsize,
+        child_fee,
+        child_value,
+        fee_rate: (package.fee + child_fee) as f64 / total_vsize as f64,
+    })
+}
+
+/// Submit a signed child together with its unconfirmed parents, so nodes
+/// evaluate the parents at the package rate
+pub async fn submit(
+    chain: &dyn ChainBackend,
+    child_hex: &str,
+) -> WalletResult<BroadcastTxResponse> {
+    let child: Transaction = deserialize_hex(child_hex)
+        .map_err(|e| WalletError::InvalidRequest(format!("Deserialization failed: {}", e)))?;
+
+    let mut parent_txids: Vec<Txid> = Vec::new();
+    for input in &child.input {
+        let txid = input.previous_output.txid;
+        if !parent_txids.contains(&txid) && !is_mined(chain, &txid).await? {
+            parent_txids.push(txid);
+        }
+    }
+    if parent_txids.is_empty() {
+        return Err(WalletError::InvalidRequest(
+            "Child spends no unconfirmed transaction".to_string(),
+        ));
+    }
+
+    let mut tx_package = chain.get_transactions(&parent_txids).await?;
+    tx_package.push(child);
+
+    broadcast::broadcast_transactions(chain, tx_package, false).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use bitcoin::{consensus::encode::serialize_hex, Network};
+
+    // Unconfirmed payment of 50_000 sats paying 2_000 sats of fee
+    fn stuck_payment(chain: &MemoryBackend) -> Transaction {
+        let funding = chain.fund(
+            &address(1, Network::Regtest),
+            Amount::from_sat(100_000),
+            Some(1),
+        );
+        let tx = spending_tx(
+            &[funding],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: address(2, Network::Regtest).script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(48_000),
+                    script_pubkey: address(1, Network::Regtest).script_pubkey(),
+                },
+            ],
+        );
+        chain.insert_transaction(tx.clone(), None);
+        chain.add_wallet_address(&address(1, Network::Regtest));
+        tx
+    }
+
+    fn request(txid: Txid, vout: u32, fee_rate: f64) -> CpfpRequest {
+        CpfpRequest {
+            txid,
+            vout,
+            fee_rate,
+            address: address(1, Network::Regtest).to_string(),
+        }
+    }
+
+    #[tokio::test]
+    async fn child_pays_for_the_package() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let parent = stuck_payment(&chain);
+        let txid = parent.compute_txid();
+        let to = address(1, Network::Regtest);
+
+        let plan = plan(&chain, Network::Regtest, &to, &request(txid, 1, 20.0))
+            .await
+            .unwrap();
+        assert_eq!(plan.package_count, 1);
+        assert_eq!(plan.package_fee, 2_000);
+        assert!(plan.fee_rate >= 20.0);
+        assert_eq!(plan.child_value, 48_000 - plan.child_fee);
+    }
+
+    #[tokio::test]
+    async fn rejects_caller_mistakes_as_invalid_requests() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let txid = stuck_payment(&chain).compute_txid();
+        let to = address(1, Network::Regtest);
+
+        let no_output = plan(&chain, Network::Regtest, &to, &request(txid, 2, 20.0)).await;
+        assert!(matches!(no_output, Err(WalletError::InvalidRequest(_))));
+
+        let too_low = plan(&chain, Network::Regtest, &to, &request(txid, 1, 1.0)).await;
+        assert!(matches!(too_low, Err(WalletError::InvalidRequest(_))));
+
+        // Output 0 pays someone else
+        let not_ours = plan(&chain, Network::Regtest, &to, &request(txid, 0, 20.0)).await;
+        assert!(matches!(not_ours, Err(WalletError::InvalidRequest(_))));
+
+        let funding = chain.fund(&to, Amount::from_sat(10_000), Some(2));
+        let confirmed = plan(
+            &chain,
+            Network::Regtest,
+            &to,
+            &request(funding.txid, 0, 20.0),
+        )
+        .await;
+        assert!(matches!(confirmed, Err(WalletError::InvalidRequest(_))));
+    }
+
+    #[tokio::test]
+    async fn submit_needs_an_unconfirmed_parent() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let funding = chain.fund(
+            &address(1, Network::Regtest),
+            Amount::from_sat(10_000),
+            Some(1),
+        );
+        let child = spending_tx(
+            &[funding],
+            vec![TxOut {
+                value: Amount::from_sat(9_000),
+                script_pubkey: address(2, Network::Regtest).script_pubkey(),
+            }],
+        );
+
+        let result = submit(&chain, &serialize_hex(&child)).await;
+        assert!(matches!(result, Err(WalletError::InvalidRequest(_))));
+    }
+}
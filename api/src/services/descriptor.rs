@@ -0,0 +1,257 @@
+use crate::config::network_name;
+use crate::error::{WalletError, WalletResult};
+use crate::models::ScriptType;
+use bitcoin::bip32::{ChildNumber, DerivationPath, Xpub};
+use bitcoin::secp256k1::{PublicKey, Secp256k1, VerifyOnly};
+use bitcoin::{base58, Address, CompressedPublicKey, Network, NetworkKind};
+use std::ops::Range;
+
+const XPUB: [u8; 4] = [0x04, 0x88, 0xb2, 0x1e];
+const TPUB: [u8; 4] = [0x04, 0x35, 0x87, 0xcf];
+
+// SLIP-132 version bytes, with the standard ones they stand for and the
+// script they imply
+const SLIP132_VERSIONS: [([u8; 4], [u8; 4], Option<ScriptType>); 6] = [
+    (XPUB, XPUB, None),
+    (TPUB, TPUB, None),
+    ([0x04, 0x9d, 0x7c, 0xb2], XPUB, Some(ScriptType::ShWpkh)), // ypub
+    ([0x04, 0xb2, 0x47, 0x46], XPUB, Some(ScriptType::Wpkh)),   // zpub
+    ([0x04, 0x4a, 0x52, 0x62], TPUB, Some(ScriptType::ShWpkh)), // upub
+    ([0x04, 0x5f, 0x1c, 0xf6], TPUB, Some(ScriptType::Wpkh)),   // vpub
+];
+
+fn invalid(message: impl std::fmt::Display) -> WalletError {
+    WalletError::InvalidRequest(format!("Invalid descriptor: {}", message))
+}
+
+// Decode an extended public key in any SLIP-132 form, with the script
+// type its prefix implies
+fn parse_xpub(key: &str, network: Network) -> WalletResult<(Xpub, Option<ScriptType>)> {
+    let mut data = base58::decode_check(key).map_err(|e| invalid(format!("{}: {}", key, e)))?;
+    let (standard, script_type) = SLIP132_VERSIONS
+        .iter()
+        .find(|(version, _, _)| data.starts_with(version))
+        .map(|(_, standard, script_type)| (standard, *script_type))
+        .ok_or_else(|| invalid(format!("{} is not an extended public key", key)))?;
+    data[..4].copy_from_slice(standard);
+
+    let xpub = Xpub::decode(&data).map_err(|e| invalid(format!("{}: {}", key, e)))?;
+    if xpub.network != NetworkKind::from(network) {
+        return Err(WalletError::WrongNetwork(format!(
+            "{} is not a {} key",
+            key,
+            network_name(network)
+        )));
+    }
+    Ok((xpub, script_type))
+}
+
+/// Ranged descriptor over one extended public key, in the forms wallets
+/// use for single-key accounts: `pkh`, `sh(wpkh)`, `wpkh` and key-path
+/// `tr`, with unhardened steps after the key ending in `/*`. One `<a;b>`
+/// step (BIP389) gives one keychain per alternative.
+pub struct KeyDescriptor {
+    script_type: ScriptType,
+    xpub: Xpub,
+    /// Steps from the key to the ranged index, one path per keychain
+    keychains: Vec<DerivationPath>,
+    network: Network,
+    secp: Secp256k1<VerifyOnly>,
+}
+
+impl KeyDescriptor {
+    pub fn parse(descriptor: &str, network: Network) -> WalletResult<Self> {
+        // The checksum only guards against typos, which parsing catches too
+        let body = descriptor.split('#').next().unwrap_or_default().trim();
+
+        let (script_type, key) = [
+            ("sh(wpkh(", "))", ScriptType::ShWpkh),
+            ("wpkh(", ")", ScriptType::Wpkh),
+            ("pkh(", ")", ScriptType::Pkh),
+            ("tr(", ")", ScriptType::Tr),
+        ]
+        .iter()
+        .find_map(|(open, close, script_type)| {
+            let key = body.strip_prefix(open)?.strip_suffix(close)?;
+            Some((*script_type, key))
+        })
+        .ok_or_else(|| invalid(format!("{} is not pkh, sh(wpkh), wpkh or tr", body)))?;
+        if key.contains(',') || key.contains('(') {
+            return Err(invalid("only single-key descriptors are supported"));
+        }
+
+        // Key origin, e.g. `[d34db33f/84h/0h/0h]`, says nothing about the scan
+        let key = match key.strip_prefix('[') {
+            Some(rest) => rest
+                .split_once(']')
+                .map(|(_, key)| key)
+                .ok_or_else(|| invalid("unterminated key origin"))?,
+            None => key,
+        };
+
+        let mut steps = key.split('/');
+        let (xpub, _) = parse_xpub(steps.next().unwrap_or_default(), network)?;
+        let steps: Vec<&str> = steps.collect();
+        if steps.last() != Some(&"*") {
+            return Err(invalid("the key must end in /*"));
+        }
+
+        let mut keychains = vec![Vec::new()];
+        for step in &steps[..steps.len() - 1] {
+            let alternatives: Vec<&str> = match step.strip_prefix('<') {
+                Some(rest) if keychains.len() == 1 => rest
+                    .strip_suffix('>')
+                    .ok_or_else(|| invalid(format!("unterminated step {}", step)))?
+                    .split(';')
+                    .collect(),
+                Some(_) => return Err(invalid("only one <a;b> step is allowed")),
+                None => vec![step],
+            };
+            let children = alternatives
+                .iter()
+                .map(|index| {
+                    index
+                        .parse::<u32>()
+                        .ok()
+                        .and_then(|index| ChildNumber::from_normal_idx(index).ok())
+                        .ok_or_else(|| {
+                            invalid(format!(
+                                "{} is not an unhardened step; hardened steps need the private key",
+                                index
+                            ))
+                        })
+                })
+                .collect::<WalletResult<Vec<_>>>()?;
+
+            keychains = if children.len() == 1 {
+                keychains
+                    .into_iter()
+                    .map(|mut path| {
+                        path.push(children[0]);
+                        path
+                    })
+                    .collect()
+            } else {
+                children
+                    .iter()
+                    .map(|child| {
+                        let mut path = keychains[0].clone();
+                        path.push(*child);
+                        path
+                    })
+                    .collect()
+            };
+        }
+
+        Ok(Self {
+            script_type,
+            xpub,
+            keychains: keychains.into_iter().map(DerivationPath::from).collect(),
+            network,
+            secp: Secp256k1::verification_only(),
+        })
+    }
+
+    /// Receive and change keychains of an account key, at `/0/*` and `/1/*`
+    pub fn from_xpub(
+        key: &str,
+        script_type: Option<ScriptType>,
+        network: Network,
+    ) -> WalletResult<Self> {
+        let (xpub, implied) = parse_xpub(key.trim(), network)?;
+        Ok(Self {
+            script_type: script_type.or(implied).unwrap_or(ScriptType::Wpkh),
+            xpub,
+            keychains: [0, 1]
+                .into_iter()
+                .map(|i| DerivationPath::from(vec![ChildNumber::Normal { index: i }]))
+                .collect(),
+            network,
+            secp: Secp256k1::verification_only(),
+        })
+    }
+
+    pub fn keychain_count(&self) -> usize {
+        self.keychains.len()
+    }
+
+    /// The `keychain`-th path as a descriptor of its own, ranged over its
+    /// last step and without a checksum
+    pub fn descriptor(&self, keychain: usize) -> String {
+        let path = &self.keychains[keychain];
+        let key = if path.is_empty() {
+            format!("{}/*", self.xpub)
+        } else {
+            format!("{}/{}/*", self.xpub, path)
+        };
+        match self.script_type {
+            ScriptType::Pkh => format!("pkh({})", key),
+            ScriptType::ShWpkh => format!("sh(wpkh({}))", key),
+            ScriptType::Wpkh => format!("wpkh({})", key),
+            ScriptType::Tr => format!("tr({})", key),
+        }
+    }
+
+    /// Addresses at `indexes` of the `keychain`-th path
+    pub fn addresses(&self, keychain: usize, indexes: Range<u32>) -> WalletResult<Vec<Address>> {
+        // Derived once rather than for every index
+        let parent = self
+            .xpub
+            .derive_pub(&self.secp, &self.keychains[keychain])
+            .map_err(invalid)?;
+        indexes
+            .map(|index| {
+                let child = ChildNumber::from_normal_idx(index).map_err(invalid)?;
+                let key = parent
+                    .ckd_pub(&self.secp, child)
+                    .map_err(invalid)?
+                    .public_key;
+                Ok(self.script_address(key))
+            })
+            .collect()
+    }
+
+    fn script_address(&self, key: PublicKey) -> Address {
+        let compressed = CompressedPublicKey(key);
+        match self.script_type {
+            ScriptType::Pkh => Address::p2pkh(compressed, self.network),
+            ScriptType::ShWpkh => Address::p2shwpkh(&compressed, self.network),
+            ScriptType::Wpkh => Address::p2wpkh(&compressed, self.network),
+            ScriptType::Tr => {
+                Address::p2tr(&self.secp, key.x_only_public_key().0, None, self.network)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::bip32::Xpriv;
+    use bitcoin::secp256k1::Secp256k1;
+
+    fn tpub() -> Xpub {
+        let xpriv = Xpriv::new_master(Network::Regtest, &[7; 32]).unwrap();
+        Xpub::from_priv(&Secp256k1::new(), &xpriv)
+    }
+
+    #[test]
+    fn renders_each_keychain_as_its_own_descriptor() {
+        let tpub = tpub();
+        let descriptor = format!("sh(wpkh([d34db33f/49h/1h/0h]{}/<0;1>/*))", tpub);
+        let descriptor = KeyDescriptor::parse(&descriptor, Network::Regtest).unwrap();
+
+        assert_eq!(descriptor.descriptor(0), format!("sh(wpkh({}/0/*))", tpub));
+        assert_eq!(descriptor.descriptor(1), format!("sh(wpkh({}/1/*))", tpub));
+    }
+
+    #[test]
+    fn derives_the_same_addresses_in_any_range() {
+        let descriptor =
+            KeyDescriptor::from_xpub(&tpub().to_string(), None, Network::Regtest).unwrap();
+
+        let all = descriptor.addresses(1, 0..4).unwrap();
+        assert_eq!(descriptor.addresses(1, 2..4).unwrap(), all[2..]);
+        assert_ne!(all[0], descriptor.addresses(0, 0..1).unwrap()[0]);
+    }
+}
@@ -0,0 +1,109 @@
+use crate::models::{FeeEstimateResponse, FeeRateEstimate, FeeSource};
+use crate::services::chain::ChainBackend;
+use crate::services::vsize::TxSize;
+
+// Same policy as the wallet's fee-rate.js: never go below the floor, and
+// stay a margin above the market so a small surge doesn't strand the tx
+pub const MIN_FEE_RATE: f64 = 2.5;
+pub const MARGIN: f64 = 1.1;
+// Raw rate assumed when neither the estimator nor the mempool answers
+const FALLBACK_FEE_RATE: f64 = 5.0;
+// Virtual size of a full block, used to walk the mempool histogram
+const BLOCK_VSIZE: u64 = 1_000_000;
+
+/// Confirmation targets reported when the caller asks for none
+pub const DEFAULT_TARGETS: [u16; 4] = [1, 3, 6, 144];
+
+/// Apply the floor and margin to a market rate, rounded to 0.1 sat/vB
+pub fn apply_policy(raw_rate: f64) -> f64 {
+    let rate = (raw_rate * MARGIN * 10.0).round() / 10.0;
+    rate.max(MIN_FEE_RATE)
+}
+
+/// Rate needed to land within `target` blocks, given a mempool histogram of
+/// `(sat/vB, vsize)` buckets sorted by rate, highest first.
+///
+/// Returns `None` when the mempool would clear within the target, in which
+/// case any relayable rate confirms.
+pub fn rate_from_histogram(histogram: &[(f64, u64)], target: u16) -> Option<f64> {
+    let capacity = BLOCK_VSIZE * target as u64;
+    let mut ahead = 0;
+
+    for (rate, vsize) in histogram {
+        ahead += vsize;
+        if ahead >= capacity {
+            return Some(*rate);
+        }
+    }
+    None
+}
+
+/// Estimate fee rates for each confirmation target.
+///
+/// The backend's estimator (`estimatesmartfee` on a node) is tried first,
+/// then the mempool histogram, then a fixed fallback; the policy is applied
+/// to whichever answered and the source is reported per target. With a
+/// transaction `size`, each estimate also carries the total fee.
+pub async fn estimate_fees(
+    chain: &dyn ChainBackend,
+    targets: &[u16],
+    size: Option<TxSize>,
+) -> FeeEstimateResponse {
+    let mut targets = targets.to_vec();
+    targets.sort_unstable();
+    targets.dedup();
+
+    // Fetched once and shared by every target that needs it
+    let mut histogram = None;
+
+    let mut estimates = Vec::with_capacity(targets.len());
+    for target in targets {
+        let estimate = match chain.estimate_fee(target).await {
+            Ok(estimate) => estimate,
+            Err(e) => {
+                tracing::warn!("Fee estimator unavailable for {} blocks: {}", target, e);
+                None
+            }
+        };
+
+        let (raw_fee_rate, source) = match estimate {
+            Some(rate) => (rate, FeeSource::Estimator),
+            None => {
+                if histogram.is_none() {
+                    histogram = Some(match chain.mempool_histogram().await {
+                        Ok(histogram) => histogram,
+                        Err(e) => {
+                            tracing::warn!("Mempool histogram unavailable: {}", e);
+                            None
+                        }
+                    });
+                }
+
+                match histogram.as_ref().unwrap() {
+                    Some(histogram) => (
+                        rate_from_histogram(histogram, target).unwrap_or(1.0),
+                        FeeSource::Mempool,
+                    ),
+                    None => (FALLBACK_FEE_RATE, FeeSource::Default),
+                }
+            }
+        };
+
+        let fee_rate = apply_policy(raw_fee_rate);
+        estimates.push(FeeRateEstimate {
+            target_blocks: target,
+            fee_rate,
+            raw_fee_rate,
+            source,
+            fee: size.map(|size| (fee_rate * size.vsize as f64).ceil() as u64),
+        });
+    }
+
+    FeeEstimateResponse {
+        backend: chain.name().to_string(),
+        min_fee_rate: MIN_FEE_RATE,
+        margin: MARGIN,
+        size,
+        estimates,
+    }
+}
@@ -4,5 +4,20 @@ pub mod bitcoin_cli;
 #[path = "bitcoin-rpc/mod.rs"]
 pub mod bitcoin_rpc;
 
+pub mod address;
+pub mod assets;
+pub mod broadcast;
+pub mod chain;
+pub mod charm_outputs;
+pub mod coin_selection;
+pub mod cpfp;
+pub mod descriptor;
+pub mod fees;
 pub mod health;
+pub mod preflight;
+pub mod psbt;
+pub mod rbf;
+pub mod signing;
 pub mod spell;
+pub mod utxos;
+pub mod vsize;
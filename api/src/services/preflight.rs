@@ -0,0 +1,520 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::UtxoAssets;
+use crate::models::{PolicyReason, PolicyViolation};
+use crate::services::assets::{has_valid_runestone, input_values, output_assets};
+use crate::services::chain::{ChainBackend, MempoolAccept};
+use crate::services::charm_outputs::charm_outputs;
+use bitcoin::{Script, Transaction, Txid};
+use std::collections::{hash_map::Entry, HashMap};
+
+/// Highest fee rate let through, in sat/vB (bitcoind's default
+/// `-maxfeerate` of 0.1 BTC/kvB)
+pub const MAX_FEE_RATE: f64 = 10_000.0;
+// MAX_STANDARD_TX_WEIGHT
+const MAX_STANDARD_TX_WEIGHT: u64 = 400_000;
+// OP_RETURN, push opcode and 80 data bytes: the historical
+// `-datacarriersize`, which most nodes still enforce
+const MAX_OP_RETURN_SIZE: usize = 83;
+// Rejections that a child can make up for when relayed with its parents
+const FEE_REJECTIONS: [&str; 2] = ["min relay fee not met", "mempool min fee not met"];
+
+/// What the checks learned about transactions that passed them
+pub struct Preflight {
+    /// Fee of each transaction in sats, in package order
+    pub fees: Vec<u64>,
+    /// Results of `testmempoolaccept`, when the backend has it
+    pub accepted: Option<Vec<MempoolAccept>>,
+}
+
+fn is_standard(script: &Script) -> bool {
+    script.is_p2pkh()
+        || script.is_p2sh()
+        || script.is_witness_program()
+        || script.is_p2pk()
+        || script.is_multisig()
+        || (script.is_op_return() && script.len() <= MAX_OP_RETURN_SIZE)
+}
+
+fn violation(
+    txid: &Txid,
+    reason: PolicyReason,
+    input: Option<usize>,
+    output: Option<usize>,
+    message: String,
+) -> PolicyViolation {
+    PolicyViolation {
+        txid: txid.to_string(),
+        reason,
+        input: input.map(|i| i as u32),
+        output: output.map(|o| o as u32),
+        message: format!("{}: {}", txid, message),
+    }
+}
+
+/// Check transactions, sorted parents first, before they are broadcast.
+///
+/// Every transaction must pay a fee no higher than [`MAX_FEE_RATE`], create
+/// no dust and only standard scripts, and spend existing unspent outputs.
+/// Inputs holding charms need a spell in the spending transaction that
+/// verifies and assigns charms to its outputs, unless `allow_charm_burn`
+/// is set. Inputs holding runes need a runestone that is not a cenotaph,
+/// and inscribed ones are
+/// refused outright. What passes goes through `testmempoolaccept`, where
+/// the backend has it. Every failure is collected into one
+/// [`WalletError::PolicyRejected`].
+pub async fn check(
+    chain: &dyn ChainBackend,
+    txs: &[Transaction],
+    tx_hexes: &[String],
+    allow_charm_burn: bool,
+) -> WalletResult<Preflight> {
+    let mut violations = Vec::new();
+
+    // Inputs come from other package members or from the chain
+    let mut prev_txs: HashMap<Txid, Transaction> = txs
+        .iter()
+        .map(|tx| (tx.compute_txid(), tx.clone()))
+        .collect();
+    let mut external: Vec<Txid> = txs
+        .iter()
+        .flat_map(|tx| tx.input.iter().map(|i| i.previous_output.txid))
+        .filter(|txid| !prev_txs.contains_key(txid))
+        .collect();
+    external.sort_unstable();
+    external.dedup();
+    match chain.get_transactions(&external).await {
+        Ok(found) => prev_txs.extend(external.iter().copied().zip(found)),
+        Err(WalletError::BitcoinError(e)) => {
+            let txid = txs[0].compute_txid();
+            return Err(WalletError::PolicyRejected(vec![violation(
+                &txid,
+                PolicyReason::MissingInputs,
+                None,
+                None,
+                format!("inputs cannot be looked up: {}", e),
+            )]));
+        }
+        Err(e) => return Err(e),
+    }
+
+    // Placing the inscriptions of a previous transaction takes the values
+    // of its own inputs
+    let spent_txs = txs
+        .iter()
+        .flat_map(|tx| tx.input.iter())
+        .filter_map(|input| prev_txs.get(&input.previous_output.txid));
+    let input_values = input_values(chain, spent_txs, &prev_txs).await?;
+
+    // Verifying a spell is costly, so each previous transaction is checked once
+    let mut assets: HashMap<Txid, Vec<UtxoAssets>> = HashMap::new();
+    let mut fees = Vec::with_capacity(txs.len());
+
+    for tx in txs {
+        let txid = tx.compute_txid();
+
+        if tx.weight().to_wu() > MAX_STANDARD_TX_WEIGHT {
+            violations.push(violation(
+                &txid,
+                PolicyReason::TxTooLarge,
+                None,
+                None,
+                format!("weight {} exceeds {}", tx.weight(), MAX_STANDARD_TX_WEIGHT),
+            ));
+        }
+
+        for (vout, output) in tx.output.iter().enumerate() {
+            let script = &output.script_pubkey;
+            if !is_standard(script) {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::NonStandardScript,
+                    None,
+                    Some(vout),
+                    format!("output {} has a non-standard script", vout),
+                ));
+            } else if !script.is_op_return() && output.value < script.minimal_non_dust() {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::Dust,
+                    None,
+                    Some(vout),
+                    format!(
+                        "output {} of {} sats is below the dust limit of {} sats",
+                        vout,
+                        output.value.to_sat(),
+                        script.minimal_non_dust().to_sat()
+                    ),
+                ));
+            }
+        }
+
+        // Charms only move on when a spell that verifies assigns them anew
+        let carries_charms = !charm_outputs(tx).await.is_empty();
+        // A cenotaph burns the runes, so it does not count
+        let carries_runestone = has_valid_runestone(tx);
+        let mut input_value = Some(0u64);
+
+        for (index, input) in tx.input.iter().enumerate() {
+            let outpoint = input.previous_output;
+            let prev_tx = &prev_txs[&outpoint.txid];
+            let vout = outpoint.vout as usize;
+            let Some(prevout) = prev_tx.output.get(vout) else {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::MissingInputs,
+                    Some(index),
+                    None,
+                    format!("input {} spends missing output {}", index, outpoint),
+                ));
+                input_value = None;
+                continue;
+            };
+            input_value = input_value.map(|value| value + prevout.value.to_sat());
+
+            // A transaction already in the mempool, as the parents resent
+            // with a CPFP child are, spends its own inputs
+            if external.contains(&outpoint.txid)
+                && !chain.is_unspent(&outpoint).await?
+                && chain.get_transaction(&txid).await.is_err()
+            {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::InputSpent,
+                    Some(index),
+                    None,
+                    format!(
+                        "input {} spends {}, which is already spent",
+                        index, outpoint
+                    ),
+                ));
+            }
+
+            let prev_assets = match assets.entry(outpoint.txid) {
+                Entry::Occupied(entry) => entry.into_mut(),
+                Entry::Vacant(entry) => entry.insert(output_assets(prev_tx, &input_values).await),
+            };
+            let prev_assets = &prev_assets[vout];
+            if !prev_assets.charms.is_empty() && !carries_charms {
+                if allow_charm_burn {
+                    tracing::warn!("{} burns the charms in {} as requested", txid, outpoint);
+                } else {
+                    violations.push(violation(
+                        &txid,
+                        PolicyReason::CarriesCharms,
+                        Some(index),
+                        None,
+                        format!(
+                            "input {} spends charms in {} without a spell to carry them",
+                            index, outpoint
+                        ),
+                    ));
+                }
+            }
+            if !prev_assets.inscriptions.is_empty() {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::CarriesInscription,
+                    Some(index),
+                    None,
+                    format!("input {} spends the inscription in {}", index, outpoint),
+                ));
+            }
+            if prev_assets.carries_runes() && !carries_runestone {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::CarriesRunes,
+                    Some(index),
+                    None,
+                    format!(
+                        "input {} may spend runes in {} without a runestone to carry them",
+                        index, outpoint
+                    ),
+                ));
+            }
+        }
+
+        let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+        let Some(input_value) = input_value else {
+            fees.push(0);
+            continue;
+        };
+        match input_value.checked_sub(output_value) {
+            Some(fee) => {
+                let fee_rate = fee as f64 / tx.vsize() as f64;
+                if fee_rate > MAX_FEE_RATE {
+                    violations.push(violation(
+                        &txid,
+                        PolicyReason::AbsurdFee,
+                        None,
+                        None,
+                        format!(
+                            "fee rate of {:.1} sat/vB exceeds {} sat/vB",
+                            fee_rate, MAX_FEE_RATE
+                        ),
+                    ));
+                }
+                fees.push(fee);
+            }
+            None => {
+                violations.push(violation(
+                    &txid,
+                    PolicyReason::OutputsExceedInputs,
+                    None,
+                    None,
+                    format!(
+                        "outputs of {} sats exceed inputs of {} sats",
+                        output_value, input_value
+                    ),
+                ));
+                fees.push(0);
+            }
+        }
+    }
+
+    if !violations.is_empty() {
+        return Err(WalletError::PolicyRejected(violations));
+    }
+
+    let accepted = match chain.test_mempool_accept(tx_hexes).await {
+        Ok(accepted) => accepted,
+        Err(e) => {
+            tracing::warn!("Mempool acceptance check unavailable: {}", e);
+            None
+        }
+    };
+    let is_package = txs.len() > 1;
+    for result in accepted.iter().flatten().filter(|r| !r.allowed) {
+        let reason = result.reject_reason.as_deref().unwrap_or("rejected");
+        if is_package && FEE_REJECTIONS.iter().any(|f| reason.contains(f)) {
+            continue;
+        }
+        violations.push(PolicyViolation {
+            txid: result.txid.clone(),
+            reason: PolicyReason::MempoolRejected,
+            input: None,
+            output: None,
+            message: format!("{}: {}", result.txid, reason),
+        });
+    }
+    if !violations.is_empty() {
+        return Err(WalletError::PolicyRejected(violations));
+    }
+
+    Ok(Preflight { fees, accepted })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use bitcoin::{
+        consensus::encode::serialize_hex,
+        opcodes::all::{OP_ENDIF, OP_IF, OP_PUSHNUM_13, OP_RETURN},
+        opcodes::OP_FALSE,
+        script::{Builder, PushBytesBuf},
+        Amount, Network, OutPoint, ScriptBuf, TxOut, Witness,
+    };
+
+    fn pay(value: u64) -> TxOut {
+        TxOut {
+            value: Amount::from_sat(value),
+            script_pubkey: address(2, Network::Regtest).script_pubkey(),
+        }
+    }
+
+    fn funded(chain: &MemoryBackend, value: u64) -> OutPoint {
+        chain.fund(
+            &address(1, Network::Regtest),
+            Amount::from_sat(value),
+            Some(1),
+        )
+    }
+
+    // Runestone with the given tag and value pairs and no edicts
+    fn runestone(fields: &[u8]) -> TxOut {
+        TxOut {
+            value: Amount::ZERO,
+            script_pubkey: Builder::new()
+                .push_opcode(OP_RETURN)
+                .push_opcode(OP_PUSHNUM_13)
+                .push_slice(PushBytesBuf::try_from(fields.to_vec()).unwrap())
+                .into_script(),
+        }
+    }
+
+    async fn reasons(chain: &MemoryBackend, txs: &[Transaction]) -> Vec<PolicyReason> {
+        let tx_hexes: Vec<String> = txs.iter().map(serialize_hex).collect();
+        match check(chain, txs, &tx_hexes, false).await {
+            Ok(_) => vec![],
+            Err(WalletError::PolicyRejected(violations)) => {
+                violations.iter().map(|v| v.reason).collect()
+            }
+            Err(e) => panic!("expected a policy rejection, got {}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn passes_a_plain_payment() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let tx = spending_tx(&[funded(&chain, 100_000)], vec![pay(99_000)]);
+        let tx_hex = serialize_hex(&tx);
+
+        let preflight = check(&chain, &[tx], &[tx_hex], false).await.unwrap();
+        assert_eq!(preflight.fees, vec![1_000]);
+    }
+
+    #[tokio::test]
+    async fn rejects_fees_and_values() {
+        let chain = MemoryBackend::new(Network::Regtest);
+
+        let absurd = spending_tx(&[funded(&chain, 10_000_000)], vec![pay(1_000)]);
+        assert_eq!(
+            reasons(&chain, &[absurd]).await,
+            vec![PolicyReason::AbsurdFee]
+        );
+
+        let overspend = spending_tx(&[funded(&chain, 10_000)], vec![pay(20_000)]);
+        assert_eq!(
+            reasons(&chain, &[overspend]).await,
+            vec![PolicyReason::OutputsExceedInputs]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_non_standard_outputs() {
+        let chain = MemoryBackend::new(Network::Regtest);
+
+        let dust = spending_tx(&[funded(&chain, 10_000)], vec![pay(8_000), pay(100)]);
+        assert_eq!(reasons(&chain, &[dust]).await, vec![PolicyReason::Dust]);
+
+        let bare = TxOut {
+            value: Amount::from_sat(8_000),
+            script_pubkey: ScriptBuf::from_bytes(vec![0x51]),
+        };
+        let non_standard = spending_tx(&[funded(&chain, 10_000)], vec![bare]);
+        assert_eq!(
+            reasons(&chain, &[non_standard]).await,
+            vec![PolicyReason::NonStandardScript]
+        );
+
+        let too_large = spending_tx(&[funded(&chain, 10_000_000)], vec![pay(1_000); 3_300]);
+        assert_eq!(
+            reasons(&chain, &[too_large]).await,
+            vec![PolicyReason::TxTooLarge]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_missing_and_spent_inputs() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let funding = funded(&chain, 10_000);
+
+        let missing_output = OutPoint::new(funding.txid, 5);
+        let missing = spending_tx(&[missing_output], vec![pay(9_000)]);
+        assert_eq!(
+            reasons(&chain, &[missing]).await,
+            vec![PolicyReason::MissingInputs]
+        );
+
+        let unknown = spending_tx(&[OutPoint::null()], vec![pay(9_000)]);
+        assert_eq!(
+            reasons(&chain, &[unknown]).await,
+            vec![PolicyReason::MissingInputs]
+        );
+
+        chain.insert_transaction(spending_tx(&[funding], vec![pay(9_000)]), None);
+        let double_spend = spending_tx(&[funding], vec![pay(8_000)]);
+        assert_eq!(
+            reasons(&chain, &[double_spend]).await,
+            vec![PolicyReason::InputSpent]
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_spending_inscriptions() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let envelope = Builder::new()
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice(b"ord")
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let mut reveal = spending_tx(
+            &[funded(&chain, 20_000)],
+            vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: address(1, Network::Regtest).script_pubkey(),
+            }],
+        );
+        reveal.input[0].witness =
+            Witness::from_slice(&[vec![0; 64], envelope.to_bytes(), vec![0xc0; 33]]);
+        chain.insert_transaction(reveal.clone(), Some(2));
+
+        let spend = spending_tx(&[OutPoint::new(reveal.compute_txid(), 0)], vec![pay(9_000)]);
+        assert_eq!(
+            reasons(&chain, &[spend]).await,
+            vec![PolicyReason::CarriesInscription]
+        );
+    }
+
+    #[tokio::test]
+    async fn runes_need_a_runestone_that_deciphers() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let etching = spending_tx(
+            &[funded(&chain, 20_000)],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(10_000),
+                    script_pubkey: address(1, Network::Regtest).script_pubkey(),
+                },
+                runestone(&[]),
+            ],
+        );
+        chain.insert_transaction(etching.clone(), Some(2));
+        let runes = OutPoint::new(etching.compute_txid(), 0);
+
+        let plain = spending_tx(&[runes], vec![pay(9_000)]);
+        assert_eq!(
+            reasons(&chain, &[plain]).await,
+            vec![PolicyReason::CarriesRunes]
+        );
+
+        // An unknown flag makes a cenotaph, which would burn the runes
+        let cenotaph = spending_tx(&[runes], vec![pay(9_000), runestone(&[2, 0b1000])]);
+        assert_eq!(
+            reasons(&chain, &[cenotaph]).await,
+            vec![PolicyReason::CarriesRunes]
+        );
+
+        let carried = spending_tx(&[runes], vec![pay(9_000), runestone(&[])]);
+        assert_eq!(reasons(&chain, &[carried]).await, vec![]);
+    }
+
+    #[tokio::test]
+    async fn reports_mempool_rejections() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        chain.set_mempool_rejection(Some("non-final"));
+        let tx = spending_tx(&[funded(&chain, 100_000)], vec![pay(99_000)]);
+
+        assert_eq!(
+            reasons(&chain, &[tx]).await,
+            vec![PolicyReason::MempoolRejected]
+        );
+    }
+
+    #[tokio::test]
+    async fn packages_may_leave_the_fee_to_the_child() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        chain.set_mempool_rejection(Some("min relay fee not met"));
+        let parent = spending_tx(&[funded(&chain, 100_000)], vec![pay(99_990)]);
+        let child = spending_tx(
+            &[OutPoint::new(parent.compute_txid(), 0)],
+            vec![pay(98_000)],
+        );
+
+        assert_eq!(reasons(&chain, &[parent, child]).await, vec![]);
+    }
+}
@@ -0,0 +1,843 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{
+    AnalyzePsbtResponse, BroadcastTxResponse, CreatePsbtRequest, PsbtInputAnalysis, PsbtOutputSpec,
+    PsbtRole, TaprootInputSpec,
+};
+use crate::services::address::parse_address;
+use crate::services::broadcast;
+use crate::services::chain::ChainBackend;
+use crate::services::vsize::{
+    estimate_tx_size, witness_size, InputType, OutputType, SCHNORR_SIG_SIZE,
+};
+use bitcoin::{
+    absolute::LockTime,
+    bip32::{DerivationPath, Fingerprint},
+    hex::FromHex,
+    key::TapTweak,
+    opcodes::all::{OP_CHECKSIG, OP_CHECKSIGADD, OP_CHECKSIGVERIFY},
+    psbt::{self, Psbt},
+    script::{Builder, Instruction, PushBytesBuf},
+    secp256k1::{Secp256k1, XOnlyPublicKey},
+    taproot::{ControlBlock, TapLeafHash, TapNodeHash},
+    transaction::Version,
+    Amount, Network, OutPoint, Script, ScriptBuf, Sequence, Transaction, TxIn, TxOut, Txid,
+    Witness,
+};
+use std::str::FromStr;
+
+/// Decode a base64 PSBT
+pub fn parse_psbt(psbt: &str) -> WalletResult<Psbt> {
+    Psbt::from_str(psbt).map_err(|e| WalletError::BitcoinError(format!("Invalid PSBT: {}", e)))
+}
+
+fn decode_hex(field: &str, value: &str) -> WalletResult<Vec<u8>> {
+    Vec::<u8>::from_hex(value)
+        .map_err(|e| WalletError::BitcoinError(format!("Invalid {}: {}", field, e)))
+}
+
+/// Create an unsigned PSBT, like bitcoind's `createpsbt`
+pub fn create(request: &CreatePsbtRequest, network: Network) -> WalletResult<Psbt> {
+    if request.inputs.is_empty() || request.outputs.is_empty() {
+        return Err(WalletError::BitcoinError(
+            "A PSBT needs at least one input and one output".to_string(),
+        ));
+    }
+
+    let input = request
+        .inputs
+        .iter()
+        .map(|spec| TxIn {
+            previous_output: OutPoint::new(spec.txid, spec.vout),
+            script_sig: ScriptBuf::new(),
+            sequence: spec
+                .sequence
+                .map(Sequence::from_consensus)
+                .unwrap_or(Sequence::ENABLE_RBF_NO_LOCKTIME),
+            witness: Witness::new(),
+        })
+        .collect();
+
+    let output = request
+        .outputs
+        .iter()
+        .map(|spec| match spec {
+            PsbtOutputSpec::Payment { address, value } => Ok(TxOut {
+                value: Amount::from_sat(*value),
+                script_pubkey: parse_address(address, network)?.script_pubkey(),
+            }),
+            PsbtOutputSpec::Data { data } => {
+                let data = PushBytesBuf::try_from(decode_hex("data", data)?).map_err(|_| {
+                    WalletError::BitcoinError("OP_RETURN data is too long".to_string())
+                })?;
+                Ok(TxOut {
+                    value: Amount::ZERO,
+                    script_pubkey: ScriptBuf::new_op_return(data),
+                })
+            }
+        })
+        .collect::<WalletResult<Vec<_>>>()?;
+
+    let tx = Transaction {
+        version: Version::TWO,
+        lock_time: LockTime::from_consensus(request.locktime),
+        input,
+        output,
+    };
+    Psbt::from_unsigned_tx(tx)
+        .map_err(|e| WalletError::BitcoinError(format!("Failed to build PSBT: {}", e)))
+}
+
+fn is_final(input: &psbt::Input) -> bool {
+    input.final_script_sig.is_some() || input.final_script_witness.is_some()
+}
+
+// Assumes single-signature inputs: any signature makes one ready to finalize
+fn next_role(input: &psbt::Input) -> PsbtRole {
+    if is_final(input) {
+        PsbtRole::Extractor
+    } else if input.witness_utxo.is_none() && input.non_witness_utxo.is_none() {
+        PsbtRole::Updater
+    } else if input.tap_key_sig.is_some()
+        || !input.tap_script_sigs.is_empty()
+        || !input.partial_sigs.is_empty()
+    {
+        PsbtRole::Finalizer
+    } else {
+        PsbtRole::Signer
+    }
+}
+
+// X-only keys checked by a tapscript leaf, in script order. Only pushes
+// followed by a signature check count, so data such as a spell envelope is
+// not mistaken for keys.
+pub fn leaf_keys(script: &Script) -> Vec<XOnlyPublicKey> {
+    let instructions: Vec<Instruction> = script.instructions().filter_map(Result::ok).collect();
+    instructions
+        .windows(2)
+        .filter_map(|pair| match pair {
+            [Instruction::PushBytes(bytes), Instruction::Op(op)]
+                if bytes.len() == 32
+                    && [OP_CHECKSIG, OP_CHECKSIGVERIFY, OP_CHECKSIGADD].contains(op) =>
+            {
+                XOnlyPublicKey::from_slice(bytes.as_bytes()).ok()
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+// Keys the PSBT knows about that have not signed yet
+fn missing_signatures(input: &psbt::Input) -> Vec<String> {
+    if is_final(input) || input.tap_key_sig.is_some() {
+        return Vec::new();
+    }
+
+    if !input.tap_scripts.is_empty() {
+        return input
+            .tap_key_origins
+            .iter()
+            .filter(|(key, (leaves, _))| {
+                !leaves.is_empty()
+                    && !leaves
+                        .iter()
+                        .any(|leaf| input.tap_script_sigs.contains_key(&(**key, *leaf)))
+            })
+            .map(|(key, _)| key.to_string())
+            .collect();
+    }
+    if let Some(key) = input.tap_internal_key {
+        return vec![key.to_string()];
+    }
+
+    input
+        .bip32_derivation
+        .keys()
+        .filter(|key| {
+            !input
+                .partial_sigs
+                .contains_key(&bitcoin::PublicKey::new(**key))
+        })
+        .map(|key| key.to_string())
+        .collect()
+}
+
+// How an input will be spent, for size estimates. Taproot inputs with a
+// leaf are taken to spend through their first one.
+fn input_type(input: &psbt::Input, prevout: &TxOut) -> Option<InputType> {
+    if !prevout.script_pubkey.is_p2tr() {
+        return InputType::from_script_pubkey(&prevout.script_pubkey);
+    }
+
+    match input.tap_scripts.iter().next() {
+        Some((control_block, (script, _))) if input.tap_key_sig.is_none() => {
+            let mut items = vec![SCHNORR_SIG_SIZE; leaf_keys(script).len().max(1)];
+            items.push(script.len() as u64);
+            items.push(control_block.size() as u64);
+            Some(InputType::P2trScriptPath {
+                witness_size: witness_size(&items),
+            })
+        }
+        _ => Some(InputType::P2trKeyPath),
+    }
+}
+
+/// Report what a PSBT still needs, like bitcoind's `analyzepsbt`
+pub fn analyze(psbt: &Psbt) -> AnalyzePsbtResponse {
+    let inputs: Vec<PsbtInputAnalysis> = psbt
+        .inputs
+        .iter()
+        .map(|input| PsbtInputAnalysis {
+            has_utxo: input.witness_utxo.is_some() || input.non_witness_utxo.is_some(),
+            is_final: is_final(input),
+            missing_signatures: missing_signatures(input),
+            next: next_role(input),
+        })
+        .collect();
+    let next = inputs
+        .iter()
+        .map(|input| input.next)
+        .min()
+        .unwrap_or(PsbtRole::Extractor);
+
+    let fee = psbt.fee().ok().map(Amount::to_sat);
+    let estimated_vsize = if next == PsbtRole::Extractor {
+        Some(psbt.clone().extract_tx_unchecked_fee_rate().vsize() as u64)
+    } else {
+        let input_types = psbt
+            .inputs
+            .iter()
+            .enumerate()
+            .map(|(index, input)| input_type(input, psbt.spend_utxo(index).ok()?))
+            .collect::<Option<Vec<_>>>();
+        let output_types = psbt
+            .unsigned_tx
+            .output
+            .iter()
+            .map(|output| OutputType::from_script_pubkey(&output.script_pubkey))
+            .collect::<Option<Vec<_>>>();
+        input_types
+            .zip(output_types)
+            .map(|(inputs, outputs)| estimate_tx_size(&inputs, &outputs).vsize)
+    };
+
+    AnalyzePsbtResponse {
+        inputs,
+        next,
+        fee,
+        estimated_vsize,
+        estimated_fee_rate: fee
+            .zip(estimated_vsize)
+            .map(|(fee, vsize)| fee as f64 / vsize as f64),
+    }
+}
+
+fn tap_output_key(prevout: &TxOut) -> WalletResult<XOnlyPublicKey> {
+    let script = &prevout.script_pubkey;
+    if !script.is_p2tr() {
+        return Err(WalletError::BitcoinError(
+            "Input does not spend a taproot output".to_string(),
+        ));
+    }
+    XOnlyPublicKey::from_slice(&script.as_bytes()[2..34])
+        .map_err(|e| WalletError::BitcoinError(format!("Invalid taproot output key: {}", e)))
+}
+
+/// Record the leaf a taproot input is spent through, after checking that
+/// the output commits to it.
+///
+/// Besides `tap_scripts`, this sets the internal key and merkle root, and
+/// lists every key in the leaf under `tap_key_origins` with the leaf hash so
+/// signers find what to sign. Those keys get an empty origin, since where
+/// they were derived from is not known here.
+pub fn add_tap_leaf(
+    input: &mut psbt::Input,
+    prevout: &TxOut,
+    script: ScriptBuf,
+    control_block: ControlBlock,
+) -> WalletResult<()> {
+    let output_key = tap_output_key(prevout)?;
+    let secp = Secp256k1::verification_only();
+    if !control_block.verify_taproot_commitment(&secp, output_key, &script) {
+        return Err(WalletError::BitcoinError(
+            "Leaf script and control block do not match the output".to_string(),
+        ));
+    }
+
+    let leaf_version = control_block.leaf_version;
+    let leaf_hash = TapLeafHash::from_script(&script, leaf_version);
+    let merkle_root = control_block
+        .merkle_branch
+        .iter()
+        .fold(TapNodeHash::from(leaf_hash), |node, sibling| {
+            TapNodeHash::from_node_hashes(node, *sibling)
+        });
+
+    for key in leaf_keys(&script) {
+        let (leaves, _) = input.tap_key_origins.entry(key).or_insert_with(|| {
+            (
+                Vec::new(),
+                (Fingerprint::default(), DerivationPath::default()),
+            )
+        });
+        if !leaves.contains(&leaf_hash) {
+            leaves.push(leaf_hash);
+        }
+    }
+    input.tap_internal_key = Some(control_block.internal_key);
+    input.tap_merkle_root = Some(merkle_root);
+    input
+        .tap_scripts
+        .insert(control_block, (script, leaf_version));
+    Ok(())
+}
+
+// Key-path data for an output without a script tree
+fn set_tap_internal_key(
+    input: &mut psbt::Input,
+    prevout: &TxOut,
+    internal_key: XOnlyPublicKey,
+) -> WalletResult<()> {
+    let secp = Secp256k1::verification_only();
+    let (output_key, _) = internal_key.tap_tweak(&secp, None);
+    if output_key.to_x_only_public_key() != tap_output_key(prevout)? {
+        return Err(WalletError::BitcoinError(format!(
+            "Internal key {} does not match the output key",
+            internal_key
+        )));
+    }
+    input.tap_internal_key = Some(internal_key);
+    // No leaf hashes marks the key as signing for the key path (BIP371)
+    input
+        .tap_key_origins
+        .entry(internal_key)
+        .or_insert_with(|| {
+            (
+                Vec::new(),
+                (Fingerprint::default(), DerivationPath::default()),
+            )
+        });
+    Ok(())
+}
+
+fn add_taproot_spec(psbt: &mut Psbt, spec: &TaprootInputSpec) -> WalletResult<()> {
+    let prevout = psbt.spend_utxo(spec.index).cloned().map_err(|_| {
+        WalletError::BitcoinError(format!("Input {} has no UTXO to check against", spec.index))
+    })?;
+    let input = &mut psbt.inputs[spec.index];
+
+    match (&spec.leaf_script, &spec.control_block) {
+        (Some(script), Some(control_block)) => {
+            let script = ScriptBuf::from_bytes(decode_hex("leaf_script", script)?);
+            let control_block = ControlBlock::decode(&decode_hex("control_block", control_block)?)
+                .map_err(|e| WalletError::BitcoinError(format!("Invalid control block: {}", e)))?;
+            add_tap_leaf(input, &prevout, script, control_block)
+        }
+        (None, None) => {
+            let key = spec.internal_key.as_deref().ok_or_else(|| {
+                WalletError::BitcoinError(format!("No taproot data for input {}", spec.index))
+            })?;
+            let key = XOnlyPublicKey::from_str(key)
+                .map_err(|e| WalletError::BitcoinError(format!("Invalid internal key: {}", e)))?;
+            set_tap_internal_key(input, &prevout, key)
+        }
+        _ => Err(WalletError::BitcoinError(
+            "leaf_script and control_block must be given together".to_string(),
+        )),
+    }
+    .map_err(|e| match e {
+        WalletError::BitcoinError(msg) => {
+            WalletError::BitcoinError(format!("Input {}: {}", spec.index, msg))
+        }
+        other => other,
+    })
+}
+
+/// Fill in what signers need from the chain, like bitcoind's
+/// `utxoupdatepsbt`, then any taproot spending data the caller supplies.
+///
+/// Inputs without a UTXO get `witness_utxo` for segwit outputs and the full
+/// `non_witness_utxo` for all but taproot ones, whose signatures commit to
+/// every amount anyway.
+pub async fn update(
+    chain: &dyn ChainBackend,
+    psbt: &mut Psbt,
+    taproot: &[TaprootInputSpec],
+) -> WalletResult<()> {
+    let missing: Vec<usize> = psbt
+        .inputs
+        .iter()
+        .enumerate()
+        .filter(|(_, input)| input.witness_utxo.is_none() && input.non_witness_utxo.is_none())
+        .map(|(index, _)| index)
+        .collect();
+    let txids: Vec<Txid> = missing
+        .iter()
+        .map(|index| psbt.unsigned_tx.input[*index].previous_output.txid)
+        .collect();
+    let prev_txs = chain.get_transactions(&txids).await?;
+
+    for (index, prev_tx) in missing.into_iter().zip(prev_txs) {
+        let outpoint = psbt.unsigned_tx.input[index].previous_output;
+        let prevout = prev_tx
+            .output
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or_else(|| {
+                WalletError::BitcoinError(format!("Previous output {} not found", outpoint))
+            })?;
+
+        let input = &mut psbt.inputs[index];
+        if !prevout.script_pubkey.is_p2tr() {
+            input.non_witness_utxo = Some(prev_tx);
+        }
+        if prevout.script_pubkey.is_witness_program() {
+            input.witness_utxo = Some(prevout);
+        }
+    }
+
+    for spec in taproot {
+        add_taproot_spec(psbt, spec)?;
+    }
+    Ok(())
+}
+
+/// Merge the signatures and data of several copies of the same PSBT
+pub fn combine(psbts: &[String]) -> WalletResult<Psbt> {
+    let (first, rest) = psbts
+        .split_first()
+        .ok_or_else(|| WalletError::BitcoinError("No PSBT to combine".to_string()))?;
+
+    let mut combined = parse_psbt(first)?;
+    for psbt in rest {
+        combined
+            .combine(parse_psbt(psbt)?)
+            .map_err(|e| WalletError::BitcoinError(format!("Cannot combine PSBTs: {}", e)))?;
+    }
+    Ok(combined)
+}
+
+// Final scriptSig and witness of an input, once it has every signature
+fn finalize_input(
+    input: &psbt::Input,
+    prevout: &TxOut,
+) -> Option<(Option<ScriptBuf>, Option<Witness>)> {
+    let script = &prevout.script_pubkey;
+
+    if script.is_p2tr() {
+        if let Some(sig) = input.tap_key_sig {
+            return Some((None, Some(Witness::from_slice(&[sig.to_vec()]))));
+        }
+        for (control_block, (leaf_script, leaf_version)) in &input.tap_scripts {
+            let leaf_hash = TapLeafHash::from_script(leaf_script, *leaf_version);
+            let keys = leaf_keys(leaf_script);
+            if keys.is_empty() {
+                continue;
+            }
+            // The first key in the script checks the top of the stack
+            let sigs = keys
+                .iter()
+                .rev()
+                .map(|key| {
+                    input
+                        .tap_script_sigs
+                        .get(&(*key, leaf_hash))
+                        .map(|sig| sig.to_vec())
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(mut stack) = sigs {
+                stack.push(leaf_script.to_bytes());
+                stack.push(control_block.serialize());
+                return Some((None, Some(Witness::from_slice(&stack))));
+            }
+        }
+        return None;
+    }
+
+    if input.partial_sigs.len() != 1 {
+        return None;
+    }
+    let (pubkey, sig) = input.partial_sigs.iter().next()?;
+
+    if script.is_p2wpkh() {
+        Some((None, Some(Witness::p2wpkh(sig, &pubkey.inner))))
+    } else if script.is_p2sh() {
+        let redeem_script = input.redeem_script.as_ref().filter(|s| s.is_p2wpkh())?;
+        let script_sig = Builder::new()
+            .push_slice(PushBytesBuf::try_from(redeem_script.to_bytes()).ok()?)
+            .into_script();
+        Some((Some(script_sig), Some(Witness::p2wpkh(sig, &pubkey.inner))))
+    } else if script.is_p2pkh() {
+        let script_sig = Builder::new()
+            .push_slice(PushBytesBuf::try_from(sig.to_vec()).ok()?)
+            .push_key(pubkey)
+            .into_script();
+        Some((Some(script_sig), None))
+    } else {
+        None
+    }
+}
+
+/// Finalize every input that has its signatures and return whether all
+/// are final.
+///
+/// Handles P2PKH, P2WPKH, P2SH-P2WPKH and taproot inputs; script-path ones
+/// finalize once every key in their leaf has signed. Finalized inputs keep
+/// only their UTXO and final scripts, as BIP174 asks.
+pub fn finalize(psbt: &mut Psbt) -> bool {
+    for index in 0..psbt.inputs.len() {
+        if is_final(&psbt.inputs[index]) {
+            continue;
+        }
+        let Ok(prevout) = psbt.spend_utxo(index).cloned() else {
+            continue;
+        };
+        let input = &mut psbt.inputs[index];
+        let Some((final_script_sig, final_script_witness)) = finalize_input(input, &prevout) else {
+            continue;
+        };
+
+        *input = psbt::Input {
+            non_witness_utxo: input.non_witness_utxo.take(),
+            witness_utxo: input.witness_utxo.take(),
+            final_script_sig,
+            final_script_witness,
+            proprietary: std::mem::take(&mut input.proprietary),
+            unknown: std::mem::take(&mut input.unknown),
+            ..Default::default()
+        };
+    }
+
+    psbt.inputs.iter().all(is_final)
+}
+
+/// Signed transaction of a finalized PSBT
+pub fn extract(psbt: &Psbt) -> WalletResult<Transaction> {
+    if !psbt.inputs.iter().all(is_final) {
+        return Err(WalletError::BitcoinError(
+            "PSBT is not finalized".to_string(),
+        ));
+    }
+    // Fees are checked before broadcast
+    Ok(psbt.clone().extract_tx_unchecked_fee_rate())
+}
+
+/// Extract a finalized PSBT and broadcast it through the usual checks
+pub async fn broadcast(
+    chain: &dyn ChainBackend,
+    psbt: &Psbt,
+    allow_charm_burn: bool,
+) -> WalletResult<BroadcastTxResponse> {
+    broadcast::broadcast_transactions(chain, vec![extract(psbt)?], allow_charm_burn).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use bitcoin::{
+        ecdsa,
+        key::Keypair,
+        secp256k1::{All, Message, SecretKey},
+        sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+        taproot::{self, LeafVersion, TaprootBuilder},
+        CompressedPublicKey, PublicKey,
+    };
+
+    struct Signer {
+        secp: Secp256k1<All>,
+        keypair: Keypair,
+    }
+
+    impl Signer {
+        fn new() -> Self {
+            let secp = Secp256k1::new();
+            let keypair =
+                Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+            Self { secp, keypair }
+        }
+
+        fn pubkey(&self) -> PublicKey {
+            PublicKey::new(self.keypair.public_key())
+        }
+
+        fn wpkh(&self) -> ScriptBuf {
+            ScriptBuf::new_p2wpkh(&CompressedPublicKey(self.keypair.public_key()).wpubkey_hash())
+        }
+
+        fn x_only(&self) -> XOnlyPublicKey {
+            self.keypair.x_only_public_key().0
+        }
+
+        fn ecdsa(&self, message: impl Into<Message>) -> ecdsa::Signature {
+            let message = message.into();
+            ecdsa::Signature::sighash_all(
+                self.secp.sign_ecdsa(&message, &self.keypair.secret_key()),
+            )
+        }
+
+        fn schnorr(&self, message: impl Into<Message>, keypair: &Keypair) -> taproot::Signature {
+            taproot::Signature {
+                signature: self.secp.sign_schnorr_no_aux_rand(&message.into(), keypair),
+                sighash_type: TapSighashType::Default,
+            }
+        }
+    }
+
+    // Unsigned PSBT paying 90_000 of a 100_000-sat output locked by
+    // `script_pubkey`, with the UTXO data a signer needs
+    fn spending(script_pubkey: ScriptBuf) -> Psbt {
+        let prev_tx = spending_tx(
+            &[OutPoint::null()],
+            vec![TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey,
+            }],
+        );
+        let tx = spending_tx(
+            &[OutPoint::new(prev_tx.compute_txid(), 0)],
+            vec![TxOut {
+                value: Amount::from_sat(90_000),
+                script_pubkey: address(2, Network::Regtest).script_pubkey(),
+            }],
+        );
+
+        let mut psbt = Psbt::from_unsigned_tx(tx).unwrap();
+        let prevout = prev_tx.output[0].clone();
+        if !prevout.script_pubkey.is_p2tr() {
+            psbt.inputs[0].non_witness_utxo = Some(prev_tx);
+        }
+        if prevout.script_pubkey.is_witness_program() {
+            psbt.inputs[0].witness_utxo = Some(prevout);
+        }
+        psbt
+    }
+
+    fn prevouts(psbt: &Psbt) -> Vec<TxOut> {
+        vec![psbt.spend_utxo(0).unwrap().clone()]
+    }
+
+    // Finalize and extract a signed PSBT, whose size the estimate before
+    // finalizing must cover
+    fn round_trip(mut psbt: Psbt) -> Transaction {
+        let analysis = analyze(&psbt);
+        assert_eq!(analysis.next, PsbtRole::Finalizer);
+        let estimated_vsize = analysis.estimated_vsize.unwrap();
+
+        assert!(finalize(&mut psbt));
+        assert_eq!(analyze(&psbt).next, PsbtRole::Extractor);
+        // Survives encoding, as it travels between signers and the API
+        let psbt = parse_psbt(&psbt.to_string()).unwrap();
+
+        let tx = extract(&psbt).unwrap();
+        assert!(tx.vsize() as u64 <= estimated_vsize);
+        tx
+    }
+
+    #[test]
+    fn finalizes_p2wpkh() {
+        let signer = Signer::new();
+        let mut psbt = spending(signer.wpkh());
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wpkh_signature_hash(
+                0,
+                &signer.wpkh(),
+                Amount::from_sat(100_000),
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = signer.ecdsa(sighash);
+        psbt.inputs[0].partial_sigs.insert(signer.pubkey(), sig);
+
+        let tx = round_trip(psbt);
+        assert!(tx.input[0].script_sig.is_empty());
+        assert_eq!(
+            tx.input[0].witness,
+            Witness::p2wpkh(&sig, &signer.keypair.public_key())
+        );
+    }
+
+    #[test]
+    fn finalizes_p2sh_p2wpkh() {
+        let signer = Signer::new();
+        let redeem_script = signer.wpkh();
+        let mut psbt = spending(ScriptBuf::new_p2sh(&redeem_script.script_hash()));
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .p2wpkh_signature_hash(
+                0,
+                &redeem_script,
+                Amount::from_sat(100_000),
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        let sig = signer.ecdsa(sighash);
+        psbt.inputs[0].partial_sigs.insert(signer.pubkey(), sig);
+        psbt.inputs[0].redeem_script = Some(redeem_script.clone());
+
+        let tx = round_trip(psbt);
+        let pushed = PushBytesBuf::try_from(redeem_script.to_bytes()).unwrap();
+        assert_eq!(
+            tx.input[0].script_sig,
+            Builder::new().push_slice(pushed).into_script()
+        );
+        assert_eq!(
+            tx.input[0].witness,
+            Witness::p2wpkh(&sig, &signer.keypair.public_key())
+        );
+    }
+
+    #[test]
+    fn finalizes_p2pkh() {
+        let signer = Signer::new();
+        let script_pubkey = ScriptBuf::new_p2pkh(&signer.pubkey().pubkey_hash());
+        let mut psbt = spending(script_pubkey.clone());
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .legacy_signature_hash(0, &script_pubkey, EcdsaSighashType::All.to_u32())
+            .unwrap();
+        let sig = signer.ecdsa(sighash);
+        psbt.inputs[0].partial_sigs.insert(signer.pubkey(), sig);
+
+        let tx = round_trip(psbt);
+        let expected = Builder::new()
+            .push_slice(sig.serialize())
+            .push_key(&signer.pubkey())
+            .into_script();
+        assert_eq!(tx.input[0].script_sig, expected);
+        assert!(tx.input[0].witness.is_empty());
+    }
+
+    #[test]
+    fn finalizes_a_taproot_key_path() {
+        let signer = Signer::new();
+        let mut psbt = spending(ScriptBuf::new_p2tr(&signer.secp, signer.x_only(), None));
+        let prevout = prevouts(&psbt)[0].clone();
+        set_tap_internal_key(&mut psbt.inputs[0], &prevout, signer.x_only()).unwrap();
+        assert_eq!(
+            missing_signatures(&psbt.inputs[0]),
+            vec![signer.x_only().to_string()]
+        );
+
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_key_spend_signature_hash(
+                0,
+                &Prevouts::All(&prevouts(&psbt)),
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let tweaked = signer.keypair.tap_tweak(&signer.secp, None).to_keypair();
+        let sig = signer.schnorr(sighash, &tweaked);
+        psbt.inputs[0].tap_key_sig = Some(sig);
+
+        let tx = round_trip(psbt);
+        assert_eq!(tx.input[0].witness, Witness::p2tr_key_spend(&sig));
+    }
+
+    #[test]
+    fn finalizes_a_taproot_script_path() {
+        let signer = Signer::new();
+        let leaf = Builder::new()
+            .push_x_only_key(&signer.x_only())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        // Keyed by another key, so only the leaf can spend
+        let internal_key =
+            Keypair::from_secret_key(&signer.secp, &SecretKey::from_slice(&[2; 32]).unwrap())
+                .x_only_public_key()
+                .0;
+        let tree = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&signer.secp, internal_key)
+            .unwrap();
+        let control_block = tree
+            .control_block(&(leaf.clone(), LeafVersion::TapScript))
+            .unwrap();
+
+        let mut psbt = spending(ScriptBuf::new_p2tr_tweaked(tree.output_key()));
+        let prevout = prevouts(&psbt)[0].clone();
+        add_tap_leaf(
+            &mut psbt.inputs[0],
+            &prevout,
+            leaf.clone(),
+            control_block.clone(),
+        )
+        .unwrap();
+        assert_eq!(analyze(&psbt).next, PsbtRole::Signer);
+        assert_eq!(
+            missing_signatures(&psbt.inputs[0]),
+            vec![signer.x_only().to_string()]
+        );
+
+        let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+        let sighash = SighashCache::new(&psbt.unsigned_tx)
+            .taproot_script_spend_signature_hash(
+                0,
+                &Prevouts::All(&[prevout]),
+                leaf_hash,
+                TapSighashType::Default,
+            )
+            .unwrap();
+        let sig = signer.schnorr(sighash, &signer.keypair);
+        psbt.inputs[0]
+            .tap_script_sigs
+            .insert((signer.x_only(), leaf_hash), sig);
+
+        let tx = round_trip(psbt);
+        let expected =
+            Witness::from_slice(&[sig.to_vec(), leaf.to_bytes(), control_block.serialize()]);
+        assert_eq!(tx.input[0].witness, expected);
+    }
+
+    #[test]
+    fn rejects_a_leaf_the_output_does_not_commit_to() {
+        let signer = Signer::new();
+        let leaf = Builder::new()
+            .push_x_only_key(&signer.x_only())
+            .push_opcode(OP_CHECKSIG)
+            .into_script();
+        let tree = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&signer.secp, signer.x_only())
+            .unwrap();
+        let control_block = tree.control_block(&(leaf, LeafVersion::TapScript)).unwrap();
+
+        let mut psbt = spending(ScriptBuf::new_p2tr(&signer.secp, signer.x_only(), None));
+        let prevout = prevouts(&psbt)[0].clone();
+        let other_leaf = ScriptBuf::from_bytes(vec![0x51]);
+        assert!(add_tap_leaf(&mut psbt.inputs[0], &prevout, other_leaf, control_block).is_err());
+    }
+
+    #[test]
+    fn combines_signatures_from_copies() {
+        let signer = Signer::new();
+        let unsigned = spending(signer.wpkh());
+        let mut signed = unsigned.clone();
+        let sighash = SighashCache::new(&signed.unsigned_tx)
+            .p2wpkh_signature_hash(
+                0,
+                &signer.wpkh(),
+                Amount::from_sat(100_000),
+                EcdsaSighashType::All,
+            )
+            .unwrap();
+        signed.inputs[0]
+            .partial_sigs
+            .insert(signer.pubkey(), signer.ecdsa(sighash));
+
+        let combined = combine(&[unsigned.to_string(), signed.to_string()]).unwrap();
+        assert_eq!(analyze(&unsigned).next, PsbtRole::Signer);
+        assert_eq!(analyze(&combined).next, PsbtRole::Finalizer);
+        assert_eq!(combined.inputs[0].partial_sigs.len(), 1);
+    }
+
+    #[test]
+    fn analysis_asks_for_missing_utxos() {
+        let mut psbt = spending(address(1, Network::Regtest).script_pubkey());
+        psbt.inputs[0] = psbt::Input::default();
+
+        let analysis = analyze(&psbt);
+        assert_eq!(analysis.next, PsbtRole::Updater);
+        assert!(!analysis.inputs[0].has_utxo);
+        assert_eq!(analysis.fee, None);
+    }
+}
@@ -0,0 +1,223 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{BumpFeeRequest, BumpFeeResponse};
+use crate::services::chain::{is_mined, ChainBackend};
+use crate::services::charm_outputs::charm_outputs;
+use bitcoin::{psbt::Psbt, Address, Amount, Network, ScriptBuf, TxOut, Txid, Witness};
+
+/// Minimum fee rate increase, in sat/vB, that a replacement must pay for its
+/// own relay (bitcoind's default `-incrementalrelayfee`)
+pub const INCREMENTAL_RELAY_FEE: f64 = 1.0;
+
+/// Build a BIP125 replacement of an unconfirmed transaction that pays
+/// `fee_rate`, taking the extra fee from its change output, which must pay
+/// to an address of the backend's wallet.
+///
+/// The replacement keeps every input and output and is returned as an
+/// unsigned PSBT. Its fee covers both the target rate and the original fee
+/// plus the incremental relay fee. Fees of descendants that the replacement
+/// would evict are not known here; if there are any, the node rejects it.
+pub async fn bump_fee(
+    chain: &dyn ChainBackend,
+    network: Network,
+    request: &BumpFeeRequest,
+) -> WalletResult<BumpFeeResponse> {
+    let txid = &request.txid;
+    let tx = chain.get_transaction(txid).await?;
+    if is_mined(chain, txid).await? {
+        return Err(WalletError::InvalidRequest(format!(
+            "Transaction {} is already confirmed",
+            txid
+        )));
+    }
+    if !tx.is_explicitly_rbf() {
+        return Err(WalletError::InvalidRequest(format!(
+            "Transaction {} does not signal replaceability (BIP125)",
+            txid
+        )));
+    }
+
+    let change_vout = request.change_vout as usize;
+    let change = tx.output.get(change_vout).ok_or_else(|| {
+        WalletError::InvalidRequest(format!("Transaction has no output {}", change_vout))
+    })?;
+    if charm_outputs(&tx).await.contains(&change_vout) {
+        return Err(WalletError::InvalidRequest(format!(
+            "Output {} carries charms and cannot pay for the fee bump",
+            change_vout
+        )));
+    }
+    let is_change = match Address::from_script(&change.script_pubkey, network) {
+        Ok(address) => chain.is_wallet_address(&address).await?,
+        Err(_) => false,
+    };
+    if !is_change {
+        return Err(WalletError::InvalidRequest(format!(
+            "Output {} does not pay to this wallet and cannot pay for the fee bump",
+            change_vout
+        )));
+    }
+
+    // Input values come from the transactions being spent
+    let prev_txids: Vec<Txid> = tx.input.iter().map(|i| i.previous_output.txid).collect();
+    let prev_txs = chain.get_transactions(&prev_txids).await?;
+    let prevouts = tx
+        .input
+        .iter()
+        .zip(&prev_txs)
+        .map(|(input, prev_tx)| {
+            prev_tx
+                .output
+                .get(input.previous_output.vout as usize)
+                .cloned()
+                .ok_or_else(|| {
+                    WalletError::BitcoinError(format!(
+                        "Previous output {} not found",
+                        input.previous_output
+                    ))
+                })
+        })
+        .collect::<WalletResult<Vec<TxOut>>>()?;
+
+    let input_value: u64 = prevouts.iter().map(|o| o.value.to_sat()).sum();
+    let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+    let original_fee = input_value.checked_sub(output_value).ok_or_else(|| {
+        WalletError::BitcoinError("Transaction spends more than its inputs".to_string())
+    })?;
+
+    // Same inputs and outputs, so the signed replacement has the same size
+    let vsize = tx.vsize() as u64;
+    let original_fee_rate = original_fee as f64 / vsize as f64;
+    if request.fee_rate <= original_fee_rate {
+        return Err(WalletError::InvalidRequest(format!(
+            "Target rate {} sat/vB must exceed the current {:.2} sat/vB",
+            request.fee_rate, original_fee_rate
+        )));
+    }
+
+    // BIP125 rules 3 and 4: pay at least the original fee plus relay of the
+    // replacement at the incremental rate
+    let fee = ((request.fee_rate * vsize as f64).ceil() as u64)
+        .max(original_fee + (INCREMENTAL_RELAY_FEE * vsize as f64).ceil() as u64);
+    let extra_fee = fee - original_fee;
+
+    let dust_limit = change.script_pubkey.minimal_non_dust().to_sat();
+    let change_value = change
+        .value
+        .to_sat()
+        .checked_sub(extra_fee)
+        .filter(|value| *value >= dust_limit)
+        .ok_or_else(|| {
+            WalletError::InsufficientFunds(format!(
+                "Change output of {} sats cannot cover {} sats of extra fee",
+                change.value.to_sat(),
+                extra_fee
+            ))
+        })?;
+
+    let mut replacement = tx.clone();
+    replacement.output[change_vout].value = Amount::from_sat(change_value);
+    for input in &mut replacement.input {
+        input.script_sig = ScriptBuf::new();
+        input.witness = Witness::new();
+    }
+
+    let mut psbt = Psbt::from_unsigned_tx(replacement)
+        .map_err(|e| WalletError::BitcoinError(format!("Failed to build PSBT: {}", e)))?;
+    for ((psbt_input, prevout), prev_tx) in psbt.inputs.iter_mut().zip(prevouts).zip(prev_txs) {
+        if !prevout.script_pubkey.is_witness_program() {
+            psbt_input.non_witness_utxo = Some(prev_tx);
+        }
+        psbt_input.witness_utxo = Some(prevout);
+    }
+
+    Ok(BumpFeeResponse {
+        psbt: psbt.to_string(),
+        replaces: txid.to_string(),
+        original_fee,
+        fee,
+        fee_rate: fee as f64 / vsize as f64,
+        vsize,
+        change_value,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::{
+        memory::{address, spending_tx},
+        MemoryBackend,
+    };
+    use bitcoin::{Network, Sequence, Transaction};
+
+    // Unconfirmed replaceable payment of 50_000 sats with 48_000 of change
+    fn stuck_payment(chain: &MemoryBackend) -> Transaction {
+        let funding = chain.fund(
+            &address(1, Network::Regtest),
+            Amount::from_sat(100_000),
+            Some(1),
+        );
+        let mut tx = spending_tx(
+            &[funding],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(50_000),
+                    script_pubkey: address(2, Network::Regtest).script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(48_000),
+                    script_pubkey: address(1, Network::Regtest).script_pubkey(),
+                },
+            ],
+        );
+        tx.input[0].sequence = Sequence::ENABLE_RBF_NO_LOCKTIME;
+        chain.insert_transaction(tx.clone(), None);
+        chain.add_wallet_address(&address(1, Network::Regtest));
+        tx
+    }
+
+    fn request(txid: Txid, fee_rate: f64, change_vout: u32) -> BumpFeeRequest {
+        BumpFeeRequest {
+            txid,
+            fee_rate,
+            change_vout,
+        }
+    }
+
+    #[tokio::test]
+    async fn takes_the_extra_fee_from_change() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let txid = stuck_payment(&chain).compute_txid();
+
+        let bumped = bump_fee(&chain, Network::Regtest, &request(txid, 50.0, 1))
+            .await
+            .unwrap();
+        assert_eq!(bumped.original_fee, 2_000);
+        assert!(bumped.fee_rate >= 50.0);
+        assert_eq!(bumped.change_value, 48_000 - (bumped.fee - 2_000));
+    }
+
+    #[tokio::test]
+    async fn rejects_caller_mistakes_as_invalid_requests() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let txid = stuck_payment(&chain).compute_txid();
+
+        let no_output = bump_fee(&chain, Network::Regtest, &request(txid, 50.0, 2)).await;
+        assert!(matches!(no_output, Err(WalletError::InvalidRequest(_))));
+
+        let too_low = bump_fee(&chain, Network::Regtest, &request(txid, 1.0, 1)).await;
+        assert!(matches!(too_low, Err(WalletError::InvalidRequest(_))));
+
+        // Output 0 is the payment, not the wallet's change
+        let not_change = bump_fee(&chain, Network::Regtest, &request(txid, 50.0, 0)).await;
+        assert!(matches!(not_change, Err(WalletError::InvalidRequest(_))));
+
+        let funding = chain.fund(
+            &address(3, Network::Regtest),
+            Amount::from_sat(1_000),
+            Some(2),
+        );
+        let confirmed = bump_fee(&chain, Network::Regtest, &request(funding.txid, 50.0, 0)).await;
+        assert!(matches!(confirmed, Err(WalletError::InvalidRequest(_))));
+    }
+}
@@ -0,0 +1,501 @@
+use crate::config::{network_name, SigningConfig};
+use crate::error::{WalletError, WalletResult};
+use crate::models::{PolicyReason, PolicyViolation};
+use crate::services::address::parse_address;
+use crate::services::chain::ChainBackend;
+use bitcoin::hashes::{sha256, Hash};
+use bitcoin::{Address, Network, Transaction, Txid};
+use serde::Serialize;
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Write};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SECS_PER_DAY: u64 = 86_400;
+
+// Sats approved since the start of a UTC day
+#[derive(Default)]
+struct DailySpend {
+    day: u64,
+    total: u64,
+    by_key: HashMap<String, u64>,
+}
+
+/// Spend an approved transaction reserved against the daily limits
+pub struct Approval {
+    txid: Txid,
+    key: Option<String>,
+    spend: u64,
+    day: u64,
+}
+
+#[derive(Serialize)]
+struct AuditRecord<'a> {
+    time: u64,
+    network: &'static str,
+    /// Hash prefix of the caller's API key, which is never logged itself
+    key: Option<&'a str>,
+    txid: String,
+    decision: &'a str,
+    /// Sats leaving the wallet, fee included
+    spend: u64,
+    fee: Option<u64>,
+    destinations: &'a [String],
+    reasons: &'a [PolicyViolation],
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+// Short, stable name for an API key that does not give it away
+fn key_id(api_key: &str) -> String {
+    sha256::Hash::hash(api_key.as_bytes()).to_string()[..16].to_string()
+}
+
+/// Signing policy of one network, checked before the node wallet signs.
+///
+/// The spend of a transaction is what leaves the wallet: every output that
+/// does not pay back to it, plus the fee. Daily totals are kept in memory
+/// and start over when the process restarts; the audit log keeps the
+/// history.
+pub struct SigningPolicy {
+    config: SigningConfig,
+    network: Network,
+    allowed_destinations: Vec<Address>,
+    spent: Mutex<DailySpend>,
+    audit_log: Option<Mutex<File>>,
+}
+
+impl SigningPolicy {
+    pub fn new(config: &SigningConfig, network: Network) -> io::Result<Self> {
+        let allowed_destinations = config
+            .allowed_destinations
+            .iter()
+            .map(|address| {
+                parse_address(address, network)
+                    .expect("destinations are checked by Config::validate")
+            })
+            .collect();
+        let audit_log = match &config.audit_log {
+            Some(path) => Some(Mutex::new(
+                OpenOptions::new().create(true).append(true).open(path)?,
+            )),
+            None => None,
+        };
+
+        Ok(Self {
+            config: config.clone(),
+            network,
+            allowed_destinations,
+            spent: Mutex::new(DailySpend::default()),
+            audit_log,
+        })
+    }
+
+    /// Decide whether the node wallet may sign `tx` for the holder of
+    /// `api_key`, and reserve its spend if so.
+    ///
+    /// Every decision is audited. A refusal lists each rule broken as a
+    /// [`WalletError::SigningRefused`].
+    pub async fn approve(
+        &self,
+        chain: &dyn ChainBackend,
+        tx: &Transaction,
+        api_key: Option<&str>,
+    ) -> WalletResult<Approval> {
+        let txid = tx.compute_txid();
+        let key = api_key.map(key_id);
+        let violation = |reason, output: Option<usize>, message: String| PolicyViolation {
+            txid: txid.to_string(),
+            reason,
+            input: None,
+            output: output.map(|o| o as u32),
+            message: format!("{}: {}", txid, message),
+        };
+
+        // Anonymous callers learn nothing about the wallet, so they are
+        // refused before the chain is asked anything
+        if self.config.require_auth && key.is_none() {
+            let violations = vec![violation(
+                PolicyReason::Unauthenticated,
+                None,
+                "signing requires an API key".to_string(),
+            )];
+            self.audit(AuditRecord {
+                time: now(),
+                network: network_name(self.network),
+                key: None,
+                txid: txid.to_string(),
+                decision: "refused",
+                spend: 0,
+                fee: None,
+                destinations: &[],
+                reasons: &violations,
+            });
+            return Err(WalletError::SigningRefused(violations));
+        }
+
+        let mut violations = Vec::new();
+
+        let prev_txids: Vec<Txid> = tx.input.iter().map(|i| i.previous_output.txid).collect();
+        let input_value = match chain.get_transactions(&prev_txids).await {
+            Ok(prev_txs) => tx
+                .input
+                .iter()
+                .zip(&prev_txs)
+                .map(|(input, prev_tx)| {
+                    prev_tx
+                        .output
+                        .get(input.previous_output.vout as usize)
+                        .map(|output| output.value.to_sat())
+                })
+                .sum::<Option<u64>>(),
+            Err(WalletError::BitcoinError(_)) => None,
+            Err(e) => return Err(e),
+        };
+        if input_value.is_none() {
+            violations.push(violation(
+                PolicyReason::MissingInputs,
+                None,
+                "inputs cannot be looked up".to_string(),
+            ));
+        }
+
+        let mut destinations = Vec::new();
+        let mut spend = 0;
+        for (vout, output) in tx.output.iter().enumerate() {
+            let script = &output.script_pubkey;
+            let address = Address::from_script(script, self.network).ok();
+            if let Some(address) = &address {
+                if chain.is_wallet_address(address).await? {
+                    continue;
+                }
+            }
+
+            spend += output.value.to_sat();
+            if script.is_op_return() {
+                continue;
+            }
+            let destination = address
+                .as_ref()
+                .map(Address::to_string)
+                .unwrap_or_else(|| script.to_hex_string());
+            let allowed = self.allowed_destinations.is_empty()
+                || address
+                    .as_ref()
+                    .is_some_and(|a| self.allowed_destinations.contains(a));
+            if !allowed {
+                violations.push(violation(
+                    PolicyReason::DestinationNotAllowed,
+                    Some(vout),
+                    format!("output {} pays {}, which is not allowed", vout, destination),
+                ));
+            }
+            destinations.push(destination);
+        }
+
+        let output_value: u64 = tx.output.iter().map(|o| o.value.to_sat()).sum();
+        let fee = input_value.and_then(|value| value.checked_sub(output_value));
+        match (input_value, fee) {
+            (Some(input_value), None) => violations.push(violation(
+                PolicyReason::OutputsExceedInputs,
+                None,
+                format!(
+                    "outputs of {} sats exceed inputs of {} sats",
+                    output_value, input_value
+                ),
+            )),
+            (_, Some(fee)) => {
+                if let Some(max_fee) = self.config.max_fee.filter(|max_fee| fee > *max_fee) {
+                    violations.push(violation(
+                        PolicyReason::FeeTooHigh,
+                        None,
+                        format!("fee of {} sats exceeds {} sats", fee, max_fee),
+                    ));
+                }
+                spend += fee;
+            }
+            (None, None) => {}
+        }
+
+        let day = now() / SECS_PER_DAY;
+        let decision = {
+            let mut spent = self.spent.lock().unwrap();
+            if spent.day != day {
+                *spent = DailySpend {
+                    day,
+                    ..Default::default()
+                };
+            }
+
+            if let Some(limit) = self.config.daily_limit {
+                if spent.total + spend > limit {
+                    violations.push(violation(
+                        PolicyReason::DailyLimit,
+                        None,
+                        format!(
+                            "spending {} sats would exceed the daily limit of {} sats, {} already spent",
+                            spend, limit, spent.total
+                        ),
+                    ));
+                }
+            }
+            if let (Some(limit), Some(key)) = (self.config.key_daily_limit, &key) {
+                let key_spent = spent.by_key.get(key).copied().unwrap_or(0);
+                if key_spent + spend > limit {
+                    violations.push(violation(
+                        PolicyReason::KeyDailyLimit,
+                        None,
+                        format!(
+                            "spending {} sats would exceed the daily limit of {} sats per key, {} already spent",
+                            spend, limit, key_spent
+                        ),
+                    ));
+                }
+            }
+
+            if violations.is_empty() {
+                spent.total += spend;
+                if let Some(key) = &key {
+                    *spent.by_key.entry(key.clone()).or_default() += spend;
+                }
+                "approved"
+            } else {
+                "refused"
+            }
+        };
+
+        self.audit(AuditRecord {
+            time: now(),
+            network: network_name(self.network),
+            key: key.as_deref(),
+            txid: txid.to_string(),
+            decision,
+            spend,
+            fee,
+            destinations: &destinations,
+            reasons: &violations,
+        });
+
+        if !violations.is_empty() {
+            return Err(WalletError::SigningRefused(violations));
+        }
+        Ok(Approval {
+            txid,
+            key,
+            spend,
+            day,
+        })
+    }
+
+    /// Give back the spend of an approved transaction that was never
+    /// broadcast, because signing or broadcasting it failed
+    pub fn release(&self, approval: Approval) {
+        {
+            let mut spent = self.spent.lock().unwrap();
+            if spent.day == approval.day {
+                spent.total = spent.total.saturating_sub(approval.spend);
+                if let Some(key_spent) = approval
+                    .key
+                    .as_ref()
+                    .and_then(|key| spent.by_key.get_mut(key))
+                {
+                    *key_spent = key_spent.saturating_sub(approval.spend);
+                }
+            }
+        }
+
+        self.audit(AuditRecord {
+            time: now(),
+            network: network_name(self.network),
+            key: approval.key.as_deref(),
+            txid: approval.txid.to_string(),
+            decision: "released",
+            spend: approval.spend,
+            fee: None,
+            destinations: &[],
+            reasons: &[],
+        });
+    }
+
+    fn audit(&self, record: AuditRecord) {
+        let line = serde_json::to_string(&record).unwrap_or_default();
+        tracing::info!(target: "audit", "{}", line);
+
+        if let Some(file) = &self.audit_log {
+            let mut file = file.lock().unwrap();
+            if let Err(e) = writeln!(file, "{}", line) {
+                tracing::error!("Failed to write signing audit record: {}", e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use crate::services::chain::MemoryBackend;
+    use bitcoin::{Amount, OutPoint, TxOut};
+
+    const NETWORK: Network = Network::Regtest;
+
+    fn policy(config: SigningConfig) -> SigningPolicy {
+        SigningPolicy::new(&config, NETWORK).unwrap()
+    }
+
+    // A wallet whose change goes to address 1, holding `utxos` of 100_000
+    // sats each
+    fn wallet(utxos: usize) -> (MemoryBackend, Vec<OutPoint>) {
+        let chain = MemoryBackend::new(NETWORK);
+        chain.add_wallet_address(&address(1, NETWORK));
+        let outpoints = (0..utxos)
+            .map(|_| chain.fund(&address(1, NETWORK), Amount::from_sat(100_000), Some(1)))
+            .collect();
+        (chain, outpoints)
+    }
+
+    // Pays `value` to address 2 with a 1_000-sat fee, the rest back to the
+    // wallet
+    fn payment(outpoint: OutPoint, value: u64) -> Transaction {
+        spending_tx(
+            &[outpoint],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(value),
+                    script_pubkey: address(2, NETWORK).script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(100_000 - value - 1_000),
+                    script_pubkey: address(1, NETWORK).script_pubkey(),
+                },
+            ],
+        )
+    }
+
+    fn reasons(result: WalletResult<Approval>) -> Vec<PolicyReason> {
+        match result {
+            Err(WalletError::SigningRefused(violations)) => {
+                violations.iter().map(|v| v.reason).collect()
+            }
+            Err(e) => panic!("expected a refusal, got {}", e),
+            Ok(_) => panic!("expected a refusal, got an approval"),
+        }
+    }
+
+    #[tokio::test]
+    async fn refuses_anonymous_callers_before_looking_anything_up() {
+        let audit_log = std::env::temp_dir().join(format!("signing-audit-{}", std::process::id()));
+        let policy = policy(SigningConfig {
+            audit_log: Some(audit_log.to_string_lossy().into_owned()),
+            ..Default::default()
+        });
+        // Unknown inputs would be a violation of their own
+        let tx = payment(OutPoint::new(Txid::all_zeros(), 0), 10_000);
+
+        let result = policy
+            .approve(&MemoryBackend::new(NETWORK), &tx, None)
+            .await;
+        assert_eq!(reasons(result), vec![PolicyReason::Unauthenticated]);
+
+        let audit = std::fs::read_to_string(&audit_log).unwrap();
+        std::fs::remove_file(&audit_log).unwrap();
+        let record: serde_json::Value = serde_json::from_str(audit.trim()).unwrap();
+        assert_eq!(record["decision"], "refused");
+        assert_eq!(record["txid"], tx.compute_txid().to_string());
+        assert_eq!(record["reasons"][0]["reason"], "unauthenticated");
+    }
+
+    #[tokio::test]
+    async fn counts_what_leaves_the_wallet() {
+        let (chain, outpoints) = wallet(1);
+        let policy = policy(SigningConfig::default());
+
+        let approval = policy
+            .approve(&chain, &payment(outpoints[0], 10_000), Some("key"))
+            .await
+            .unwrap();
+        // The payment and the fee, but not the change
+        assert_eq!(approval.spend, 11_000);
+        assert_eq!(policy.spent.lock().unwrap().total, 11_000);
+    }
+
+    #[tokio::test]
+    async fn refuses_destinations_and_fees_outside_the_policy() {
+        let (chain, outpoints) = wallet(1);
+        let policy = policy(SigningConfig {
+            allowed_destinations: vec![address(3, NETWORK).to_string()],
+            max_fee: Some(500),
+            ..Default::default()
+        });
+
+        let result = policy
+            .approve(&chain, &payment(outpoints[0], 10_000), Some("key"))
+            .await;
+        assert_eq!(
+            reasons(result),
+            vec![
+                PolicyReason::DestinationNotAllowed,
+                PolicyReason::FeeTooHigh
+            ]
+        );
+        assert_eq!(policy.spent.lock().unwrap().total, 0);
+    }
+
+    #[tokio::test]
+    async fn daily_limit_covers_every_key_until_released() {
+        let (chain, outpoints) = wallet(2);
+        let policy = policy(SigningConfig {
+            daily_limit: Some(15_000),
+            ..Default::default()
+        });
+
+        let approval = policy
+            .approve(&chain, &payment(outpoints[0], 10_000), Some("alice"))
+            .await
+            .unwrap();
+        let result = policy
+            .approve(&chain, &payment(outpoints[1], 10_000), Some("bob"))
+            .await;
+        assert_eq!(reasons(result), vec![PolicyReason::DailyLimit]);
+
+        // A transaction that was never broadcast no longer counts
+        policy.release(approval);
+        policy
+            .approve(&chain, &payment(outpoints[1], 10_000), Some("bob"))
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn key_daily_limit_is_kept_per_key() {
+        let (chain, outpoints) = wallet(2);
+        let policy = policy(SigningConfig {
+            key_daily_limit: Some(15_000),
+            ..Default::default()
+        });
+
+        policy
+            .approve(&chain, &payment(outpoints[0], 10_000), Some("alice"))
+            .await
+            .unwrap();
+        let result = policy
+            .approve(&chain, &payment(outpoints[1], 10_000), Some("alice"))
+            .await;
+        assert_eq!(reasons(result), vec![PolicyReason::KeyDailyLimit]);
+
+        policy
+            .approve(&chain, &payment(outpoints[1], 10_000), Some("bob"))
+            .await
+            .unwrap();
+        let spent = policy.spent.lock().unwrap();
+        assert_eq!(spent.total, 22_000);
+        assert_eq!(spent.by_key[&key_id("alice")], 11_000);
+        assert_eq!(spent.by_key[&key_id("bob")], 11_000);
+    }
+}
@@ -1,14 +1,24 @@
 // RJJ-TMP
+use crate::config::{network_name, ProverConfig};
 use crate::error::{WalletError, WalletResult};
+use crate::models::ProveSpellRequest;
+use crate::services::chain::ChainBackend;
+use crate::services::psbt;
+use bitcoin::{
+    address::NetworkUnchecked,
+    consensus::encode::{deserialize_hex, serialize_hex},
+    hex::DisplayHex,
+    psbt::Psbt,
+    sighash::{EcdsaSighashType, TapSighashType},
+    taproot::{self, ControlBlock, TapLeafHash},
+    Address, Network, ScriptBuf, Transaction, TxOut, Witness,
+};
 use reqwest;
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 use std::collections::HashMap;
 use tracing::{debug, error, info};
 
-// API endpoint for the external prover service
-const PROVER_API_URL: &str = "https://prove-t4.charms.dev/spells/prove";
-
 // Define structs to represent the JSON payload
 #[derive(Serialize, Deserialize, Debug)]
 struct Charm {
@@ -48,21 +58,76 @@ struct ProverPayload {
     fee_rate: u8,
 }
 
-pub struct SpellProver;
+/// Check a prove payload before paying for a proof: its bitcoin fields
+/// must parse as a [`ProveSpellRequest`], and `change_address` and, when
+/// the spell is sent as JSON, each `spell.outs[].address` must belong to
+/// `network`. Payloads for other chains are left alone.
+pub fn check_payload(payload: &Value, network: Network) -> WalletResult<()> {
+    if payload["chain"]
+        .as_str()
+        .is_some_and(|chain| chain != "bitcoin")
+    {
+        return Ok(());
+    }
+
+    let request = ProveSpellRequest::deserialize(payload)
+        .map_err(|e| WalletError::InvalidRequest(format!("Invalid prove payload: {}", e)))?;
+    check_address("change_address", &request.change_address, network)?;
+
+    let outs = request.spell["outs"].as_array();
+    for (i, out) in outs.into_iter().flatten().enumerate() {
+        if let Some(address) = out.get("address") {
+            let field = format!("spell.outs[{}].address", i);
+            let address = address.as_str().ok_or_else(|| {
+                WalletError::InvalidAddress(format!("{} must be a string", field))
+            })?;
+            let address = address.parse().map_err(|e| {
+                WalletError::InvalidAddress(format!("{}: {}: {}", field, address, e))
+            })?;
+            check_address(&field, &address, network)?;
+        }
+    }
+
+    Ok(())
+}
+
+fn check_address(
+    field: &str,
+    address: &Address<NetworkUnchecked>,
+    network: Network,
+) -> WalletResult<()> {
+    if address.is_valid_for_network(network) {
+        return Ok(());
+    }
+    Err(WalletError::WrongNetwork(format!(
+        "{}: {} is not a {} address",
+        field,
+        address.clone().assume_checked(),
+        network_name(network)
+    )))
+}
+
+pub struct SpellProver {
+    // Endpoint of the external prover service
+    url: String,
+}
 
 impl SpellProver {
-    pub fn new() -> Self {
-        Self
+    pub fn new(config: &ProverConfig) -> Self {
+        Self {
+            url: config.url.clone(),
+        }
     }
 
-    // Sends a spell proving request to the external prover service
-    pub async fn prove_spell(&self, payload_str: String) -> WalletResult<Value> {
+    // Sends a spell proving request to the external prover service and
+    // parses the transactions it returns
+    pub async fn prove_spell(&self, payload_str: String) -> WalletResult<SpellProofResult> {
         let request_body = payload_str;
 
         // Make the POST request to the charms prover service
         let client = reqwest::Client::new();
         let response = client
-            .post(PROVER_API_URL)
+            .post(&self.url)
             .header("Content-Type", "application/json")
             .body(request_body)
             .send()
@@ -116,14 +181,397 @@ impl SpellProver {
             }
         };
 
+        let result = SpellProofResult::from_response(&response_json)?;
         info!("Prove spell request processed successfully");
-        Ok(response_json)
+        Ok(result)
     }
 }
 
+/// Transactions returned by the prover, with the tapscript leaf through
+/// which the spell transaction spends the commit output
+#[derive(Debug, Clone, Serialize)]
 pub struct SpellProofResult {
     pub commit_tx: String,
     pub spell_tx: String,
     pub taproot_script: String,
     pub control_block: String,
 }
+
+fn decode_tx(name: &str, tx_hex: &str) -> WalletResult<Transaction> {
+    deserialize_hex(tx_hex).map_err(|e| WalletError::SpellError(format!("Invalid {}: {}", name, e)))
+}
+
+// Index of the spell transaction's input that spends the commit output
+fn spell_input(commit_tx: &Transaction, spell_tx: &Transaction) -> WalletResult<usize> {
+    let commit_txid = commit_tx.compute_txid();
+    spell_tx
+        .input
+        .iter()
+        .position(|input| input.previous_output.txid == commit_txid)
+        .ok_or_else(|| {
+            WalletError::SpellError(
+                "Spell transaction does not spend the commit output".to_string(),
+            )
+        })
+}
+
+fn unsigned_psbt(mut tx: Transaction) -> WalletResult<Psbt> {
+    for input in &mut tx.input {
+        input.script_sig = ScriptBuf::new();
+        input.witness = Witness::new();
+    }
+    Psbt::from_unsigned_tx(tx)
+        .map_err(|e| WalletError::BitcoinError(format!("Failed to build PSBT: {}", e)))
+}
+
+// Leaf data of the spell input, with the signatures the prover already made
+// over it. Those come before the script and control block, the first key
+// in the script checking the last of them.
+fn add_spell_leaf(
+    input: &mut bitcoin::psbt::Input,
+    prevout: TxOut,
+    witness: &Witness,
+) -> WalletResult<()> {
+    let (Some(script), Some(control_block)) = (
+        witness.taproot_leaf_script(),
+        witness.taproot_control_block(),
+    ) else {
+        return Err(WalletError::SpellError(
+            "Spell input has no tapscript witness".to_string(),
+        ));
+    };
+    let script = script.script.to_owned();
+    let control_block = ControlBlock::decode(control_block)
+        .map_err(|e| WalletError::SpellError(format!("Invalid control block: {}", e)))?;
+    let leaf_hash = TapLeafHash::from_script(&script, control_block.leaf_version);
+    psbt::add_tap_leaf(input, &prevout, script.clone(), control_block)?;
+
+    let sigs: Vec<&[u8]> = witness.iter().take(witness.len() - 2).collect();
+    for (key, sig) in psbt::leaf_keys(&script)
+        .into_iter()
+        .zip(sigs.into_iter().rev())
+    {
+        let sig = taproot::Signature::from_slice(sig)
+            .map_err(|e| WalletError::SpellError(format!("Invalid spell signature: {}", e)))?;
+        input.sighash_type = Some(sig.sighash_type.into());
+        input.tap_script_sigs.insert((key, leaf_hash), sig);
+    }
+    input.witness_utxo = Some(prevout);
+    Ok(())
+}
+
+// What signers should commit to when nothing else is asked for
+fn set_sighash_types(psbt: &mut Psbt) -> WalletResult<()> {
+    for index in 0..psbt.inputs.len() {
+        let is_taproot = psbt
+            .spend_utxo(index)
+            .map_err(|e| WalletError::BitcoinError(format!("Input {}: {}", index, e)))?
+            .script_pubkey
+            .is_p2tr();
+        psbt.inputs[index]
+            .sighash_type
+            .get_or_insert(if is_taproot {
+                TapSighashType::Default.into()
+            } else {
+                EcdsaSighashType::All.into()
+            });
+    }
+    Ok(())
+}
+
+impl SpellProofResult {
+    /// Parse the prover's response: the commit and spell transactions, in
+    /// that order. The leaf script and control block are read from the
+    /// witness the prover put on the spell input.
+    pub fn from_response(response: &Value) -> WalletResult<Self> {
+        let tx_hex = |index: usize, name: &str| {
+            response[index]
+                .as_str()
+                .ok_or_else(|| WalletError::SpellError(format!("Prover returned no {}", name)))
+        };
+        let commit_tx = decode_tx("commit_tx", tx_hex(0, "commit_tx")?)?;
+        let spell_tx = decode_tx("spell_tx", tx_hex(1, "spell_tx")?)?;
+
+        let witness = &spell_tx.input[spell_input(&commit_tx, &spell_tx)?].witness;
+        let (Some(script), Some(control_block)) = (
+            witness.taproot_leaf_script(),
+            witness.taproot_control_block(),
+        ) else {
+            return Err(WalletError::SpellError(
+                "Spell input has no tapscript witness".to_string(),
+            ));
+        };
+
+        Ok(Self {
+            commit_tx: serialize_hex(&commit_tx),
+            spell_tx: serialize_hex(&spell_tx),
+            taproot_script: script.script.to_hex_string(),
+            control_block: control_block.to_lower_hex_string(),
+        })
+    }
+
+    /// Unsigned PSBTs of the commit and spell transactions, ready for any
+    /// BIP371 signer.
+    ///
+    /// Every input gets its previous output and a sighash type. The spell
+    /// input also gets its leaf and the prover's signature, so finalizing
+    /// restores the witness the prover made. The commit output comes from
+    /// the commit transaction; all other inputs are looked up on the chain.
+    pub async fn psbts(&self, chain: &dyn ChainBackend) -> WalletResult<(Psbt, Psbt)> {
+        let commit_tx = decode_tx("commit_tx", &self.commit_tx)?;
+        let spell_tx = decode_tx("spell_tx", &self.spell_tx)?;
+        let index = spell_input(&commit_tx, &spell_tx)?;
+        let witness = spell_tx.input[index].witness.clone();
+        let outpoint = spell_tx.input[index].previous_output;
+        let prevout = commit_tx
+            .output
+            .get(outpoint.vout as usize)
+            .cloned()
+            .ok_or_else(|| {
+                WalletError::SpellError(format!("Commit output {} not found", outpoint))
+            })?;
+
+        let mut commit_psbt = unsigned_psbt(commit_tx)?;
+        psbt::update(chain, &mut commit_psbt, &[]).await?;
+        set_sighash_types(&mut commit_psbt)?;
+
+        let mut spell_psbt = unsigned_psbt(spell_tx)?;
+        add_spell_leaf(&mut spell_psbt.inputs[index], prevout, &witness)?;
+        psbt::update(chain, &mut spell_psbt, &[]).await?;
+        set_sighash_types(&mut spell_psbt)?;
+
+        Ok((commit_psbt, spell_psbt))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::chain::memory::{address, spending_tx};
+    use crate::services::chain::MemoryBackend;
+    use bitcoin::{
+        key::{Keypair, Secp256k1},
+        opcodes::{all::*, OP_FALSE},
+        script::Builder,
+        secp256k1::{Message, SecretKey},
+        sighash::{Prevouts, SighashCache},
+        taproot::{LeafVersion, TaprootBuilder},
+        Amount, OutPoint, XOnlyPublicKey,
+    };
+    use serde_json::json;
+
+    // What the prover returns for a spell, and the pieces of it the spell
+    // PSBT must carry
+    struct Fixture {
+        response: Value,
+        commit_tx: Transaction,
+        spell_tx: Transaction,
+        key: XOnlyPublicKey,
+        script: ScriptBuf,
+        control_block: ControlBlock,
+        sig: taproot::Signature,
+    }
+
+    // The commit transaction pays a taproot output whose only leaf checks
+    // the prover's key and carries the spell. The spell transaction spends
+    // it after a wallet input, signing its own input only, as the prover
+    // does.
+    fn prover_response(chain: &MemoryBackend) -> Fixture {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[2; 32]).unwrap());
+        let key = keypair.x_only_public_key().0;
+        let internal_key =
+            Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[3; 32]).unwrap())
+                .x_only_public_key()
+                .0;
+
+        let script = Builder::new()
+            .push_x_only_key(&key)
+            .push_opcode(OP_CHECKSIG)
+            .push_opcode(OP_FALSE)
+            .push_opcode(OP_IF)
+            .push_slice(b"spell")
+            .push_slice([0xab; 40])
+            .push_opcode(OP_ENDIF)
+            .into_script();
+        let spend_info = TaprootBuilder::new()
+            .add_leaf(0, script.clone())
+            .unwrap()
+            .finalize(&secp, internal_key)
+            .unwrap();
+        let control_block = spend_info
+            .control_block(&(script.clone(), LeafVersion::TapScript))
+            .unwrap();
+
+        let commit_funding = chain.fund(
+            &address(1, Network::Regtest),
+            Amount::from_sat(20_000),
+            Some(1),
+        );
+        let commit_tx = spending_tx(
+            &[commit_funding],
+            vec![TxOut {
+                value: Amount::from_sat(10_000),
+                script_pubkey: ScriptBuf::new_p2tr(&secp, internal_key, spend_info.merkle_root()),
+            }],
+        );
+
+        let spell_funding = chain.fund(
+            &address(2, Network::Regtest),
+            Amount::from_sat(50_000),
+            Some(1),
+        );
+        let mut spell_tx = spending_tx(
+            &[spell_funding, OutPoint::new(commit_tx.compute_txid(), 0)],
+            vec![
+                TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: address(3, Network::Regtest).script_pubkey(),
+                },
+                TxOut {
+                    value: Amount::from_sat(55_000),
+                    script_pubkey: address(2, Network::Regtest).script_pubkey(),
+                },
+            ],
+        );
+
+        let leaf_hash = TapLeafHash::from_script(&script, LeafVersion::TapScript);
+        let sighash = SighashCache::new(&spell_tx)
+            .taproot_script_spend_signature_hash(
+                1,
+                &Prevouts::One(1, &commit_tx.output[0]),
+                leaf_hash,
+                TapSighashType::AllPlusAnyoneCanPay,
+            )
+            .unwrap();
+        let sig = taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&Message::from(sighash), &keypair),
+            sighash_type: TapSighashType::AllPlusAnyoneCanPay,
+        };
+        spell_tx.input[1].witness =
+            Witness::from_slice(&[sig.to_vec(), script.to_bytes(), control_block.serialize()]);
+
+        Fixture {
+            response: json!([serialize_hex(&commit_tx), serialize_hex(&spell_tx)]),
+            commit_tx,
+            spell_tx,
+            key,
+            script,
+            control_block,
+            sig,
+        }
+    }
+
+    #[test]
+    fn reads_the_leaf_from_the_spell_witness() {
+        let fixture = prover_response(&MemoryBackend::new(Network::Regtest));
+        let proof = SpellProofResult::from_response(&fixture.response).unwrap();
+
+        assert_eq!(proof.commit_tx, serialize_hex(&fixture.commit_tx));
+        assert_eq!(proof.spell_tx, serialize_hex(&fixture.spell_tx));
+        assert_eq!(proof.taproot_script, fixture.script.to_hex_string());
+        assert_eq!(
+            proof.control_block,
+            fixture.control_block.serialize().to_lower_hex_string()
+        );
+    }
+
+    #[test]
+    fn rejects_a_spell_tx_that_skips_the_commit_output() {
+        let fixture = prover_response(&MemoryBackend::new(Network::Regtest));
+        let response = json!([
+            serialize_hex(&fixture.commit_tx),
+            serialize_hex(&fixture.commit_tx)
+        ]);
+
+        let result = SpellProofResult::from_response(&response);
+        assert!(matches!(result, Err(WalletError::SpellError(_))));
+    }
+
+    #[tokio::test]
+    async fn spell_psbt_restores_the_prover_witness() {
+        let chain = MemoryBackend::new(Network::Regtest);
+        let fixture = prover_response(&chain);
+        let proof = SpellProofResult::from_response(&fixture.response).unwrap();
+        let (commit_psbt, mut spell_psbt) = proof.psbts(&chain).await.unwrap();
+
+        let leaf_hash = TapLeafHash::from_script(&fixture.script, LeafVersion::TapScript);
+        let input = &spell_psbt.inputs[1];
+        assert_eq!(
+            input.tap_scripts.get(&fixture.control_block),
+            Some(&(fixture.script.clone(), LeafVersion::TapScript))
+        );
+        assert_eq!(input.tap_key_origins[&fixture.key].0, vec![leaf_hash]);
+        assert_eq!(
+            input.witness_utxo.as_ref(),
+            Some(&fixture.commit_tx.output[0])
+        );
+        assert_eq!(
+            input.tap_script_sigs.get(&(fixture.key, leaf_hash)),
+            Some(&fixture.sig)
+        );
+        assert_eq!(
+            input.sighash_type,
+            Some(TapSighashType::AllPlusAnyoneCanPay.into())
+        );
+
+        // Wallet inputs sign over everything
+        for psbt in [&commit_psbt, &spell_psbt] {
+            assert!(psbt.inputs.iter().all(|input| input.sighash_type.is_some()));
+        }
+        assert_eq!(
+            spell_psbt.inputs[0].sighash_type,
+            Some(EcdsaSighashType::All.into())
+        );
+        assert!(commit_psbt.unsigned_tx.input[0].witness.is_empty());
+
+        // Only the wallet input is left to sign
+        assert!(!psbt::finalize(&mut spell_psbt));
+        assert_eq!(
+            spell_psbt.inputs[1].final_script_witness.as_ref(),
+            Some(&fixture.spell_tx.input[1].witness)
+        );
+        assert!(spell_psbt.inputs[0].final_script_witness.is_none());
+    }
+
+    fn payload(change_address: &str) -> Value {
+        json!({
+            "spell": { "version": 4, "outs": [] },
+            "binaries": {},
+            "prev_txs": [],
+            "funding_utxo": format!("{}:0", "11".repeat(32)),
+            "funding_utxo_value": 10_000,
+            "change_address": change_address,
+            "fee_rate": 2.0,
+        })
+    }
+
+    const REGTEST_ADDRESS: &str = "bcrt1qqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqqdku202";
+    const MAINNET_ADDRESS: &str = "bc1qar0srrr7xfkvy5l643lydnw9re59gtzzwf5mdq";
+
+    #[test]
+    fn accepts_a_complete_payload() {
+        check_payload(&payload(REGTEST_ADDRESS), Network::Regtest).unwrap();
+    }
+
+    #[test]
+    fn requires_the_funding_fields() {
+        let mut payload = payload(REGTEST_ADDRESS);
+        payload.as_object_mut().unwrap().remove("funding_utxo");
+
+        let result = check_payload(&payload, Network::Regtest);
+        assert!(matches!(result, Err(WalletError::InvalidRequest(_))));
+    }
+
+    #[test]
+    fn rejects_a_change_address_of_another_network() {
+        let result = check_payload(&payload(MAINNET_ADDRESS), Network::Regtest);
+        assert!(matches!(result, Err(WalletError::WrongNetwork(_))));
+    }
+
+    #[test]
+    fn leaves_other_chains_alone() {
+        let payload = json!({ "chain": "cardano" });
+        check_payload(&payload, Network::Regtest).unwrap();
+    }
+}
@@ -0,0 +1,336 @@
+use crate::error::{WalletError, WalletResult};
+use crate::models::{
+    AddressUtxos, Keychain, OutPointStatus, Utxo, UtxoBatchRequest, UtxoBatchResponse, UtxoQuery,
+    UtxoVerifyResponse,
+};
+use crate::services::address::parse_address;
+use crate::services::assets::classify;
+use crate::services::bitcoin_rpc::parse_outpoint;
+use crate::services::chain::ChainBackend;
+use crate::services::descriptor::KeyDescriptor;
+use bitcoin::{Address, Amount, Network, ScriptBuf};
+use std::collections::HashMap;
+
+// Bounds on the work one batch request can ask for
+const MAX_ADDRESSES: usize = 1000;
+const MAX_GAP_LIMIT: u32 = 1000;
+const MAX_DERIVED_PER_KEYCHAIN: u32 = 10_000;
+// bitcoin-cli cannot batch, so through that backend each outpoint costs a
+// process and a full batch of them takes a while
+const MAX_OUTPOINTS: usize = 10_000;
+// Outpoints per backend lookup, so no single node call grows unbounded
+const VERIFY_CHUNK: usize = 1000;
+
+const HISTORY_UNKNOWN: &str =
+    "The chain backend only sees unspent outputs, so which addresses were ever paid is unknown";
+
+/// Unspent outputs of `address` that pass the filters of `query`, oldest
+/// first and paged, together with how many passed before paging, each
+/// tagged with the assets it carries.
+///
+/// Tagging takes the transaction of every output, so only the page is
+/// tagged unless `exclude_charms` needs all of them.
+pub async fn list_unspent(
+    chain: &dyn ChainBackend,
+    address: &Address,
+    query: &UtxoQuery,
+) -> WalletResult<(Vec<Utxo>, usize)> {
+    let min_value = Amount::from_sat(query.min_value.unwrap_or(0));
+    let mut utxos: Vec<Utxo> = chain
+        .list_unspent(address)
+        .await?
+        .into_iter()
+        .filter(|utxo| {
+            utxo.confirmations >= query.min_conf
+                && query.max_conf.is_none_or(|max| utxo.confirmations <= max)
+                && utxo.value >= min_value
+                && !(query.exclude_dust && utxo.value < utxo.script_pubkey.minimal_non_dust())
+        })
+        .collect();
+
+    if query.exclude_charms {
+        // Those that could not be checked may hold charms too
+        let unmatched = classify(chain, &mut utxos).await?;
+        utxos.retain(|utxo| utxo.assets.charms.is_empty() && !unmatched.contains(&utxo.outpoint()));
+    }
+
+    // Unconfirmed outputs go last, so pages stay put as new ones arrive
+    utxos.sort_by_key(|utxo| {
+        (
+            utxo.status.block_height.unwrap_or(u64::MAX),
+            utxo.txid,
+            utxo.vout,
+        )
+    });
+    let total = utxos.len();
+    let mut page: Vec<Utxo> = utxos
+        .into_iter()
+        .skip(query.offset)
+        .take(query.limit.unwrap_or(usize::MAX))
+        .collect();
+    if !query.exclude_charms {
+        classify(chain, &mut page).await?;
+    }
+
+    Ok((page, total))
+}
+
+/// UTXOs of many addresses in one request, grouped by address.
+///
+/// A list of addresses is looked up as given. Descriptors and xpubs are
+/// scanned one keychain at a time, `gap_limit` addresses per round, until
+/// `gap_limit` addresses in a row were never paid; the first of those is
+/// the next index to hand out. Backends that only see unspent outputs,
+/// the node ones, cannot tell an address that was never paid from one that
+/// was emptied: there each keychain is scanned in full, up to the
+/// derivation bound, and no next index is given. Every UTXO is tagged with
+/// the assets it carries.
+pub async fn list_unspent_batch(
+    chain: &dyn ChainBackend,
+    request: &UtxoBatchRequest,
+    network: Network,
+) -> WalletResult<UtxoBatchResponse> {
+    let mut response = scan(chain, request, network).await?;
+    classify(
+        chain,
+        response
+            .addresses
+            .iter_mut()
+            .flat_map(|address| address.utxos.iter_mut()),
+    )
+    .await?;
+    Ok(response)
+}
+
+async fn scan(
+    chain: &dyn ChainBackend,
+    request: &UtxoBatchRequest,
+    network: Network,
+) -> WalletResult<UtxoBatchResponse> {
+    let descriptors = match (
+        request.addresses.is_empty(),
+        &request.descriptor,
+        &request.xpub,
+    ) {
+        (false, None, None) => return list_addresses(chain, &request.addresses, network).await,
+        (true, Some(descriptor), None) => {
+            let mut descriptors = vec![KeyDescriptor::parse(descriptor, network)?];
+            if let Some(change) = &request.change_descriptor {
+                descriptors.push(KeyDescriptor::parse(change, network)?);
+            }
+            descriptors
+        }
+        (true, None, Some(xpub)) => vec![KeyDescriptor::from_xpub(
+            xpub,
+            request.script_type,
+            network,
+        )?],
+        _ => {
+            return Err(WalletError::InvalidRequest(
+                "Give exactly one of addresses, descriptor or xpub".to_string(),
+            ))
+        }
+    };
+    if request.gap_limit == 0 || request.gap_limit > MAX_GAP_LIMIT {
+        return Err(WalletError::InvalidRequest(format!(
+            "gap_limit must be between 1 and {}",
+            MAX_GAP_LIMIT
+        )));
+    }
+
+    let keychains: Vec<(&KeyDescriptor, usize)> = descriptors
+        .iter()
+        .flat_map(|descriptor| (0..descriptor.keychain_count()).map(move |i| (descriptor, i)))
+        .collect();
+    if keychains.len() > 2 {
+        return Err(WalletError::InvalidRequest(
+            "Descriptors may only have a receive and a change keychain".to_string(),
+        ));
+    }
+
+    let mut addresses = Vec::new();
+    let mut next_indexes = Vec::new();
+    for ((descriptor, keychain), kind) in keychains
+        .into_iter()
+        .zip([Keychain::Receive, Keychain::Change])
+    {
+        let (found, next_index) =
+            scan_keychain(chain, descriptor, keychain, kind, request.gap_limit).await?;
+        addresses.extend(found);
+        next_indexes.push(next_index);
+    }
+
+    let history_unknown = next_indexes.contains(&None);
+    Ok(UtxoBatchResponse {
+        addresses,
+        next_receive_index: next_indexes.first().copied().flatten(),
+        next_change_index: next_indexes.get(1).copied().flatten(),
+        next_index_error: history_unknown.then(|| HISTORY_UNKNOWN.to_string()),
+    })
+}
+
+async fn list_addresses(
+    chain: &dyn ChainBackend,
+    addresses: &[String],
+    network: Network,
+) -> WalletResult<UtxoBatchResponse> {
+    if addresses.len() > MAX_ADDRESSES {
+        return Err(WalletError::InvalidRequest(format!(
+            "At most {} addresses per request",
+            MAX_ADDRESSES
+        )));
+    }
+    let parsed = addresses
+        .iter()
+        .map(|address| parse_address(address, network))
+        .collect::<WalletResult<Vec<Address>>>()?;
+
+    let grouped = chain.list_unspent_many(&parsed).await?;
+    Ok(UtxoBatchResponse {
+        addresses: parsed
+            .iter()
+            .zip(grouped)
+            .map(|(address, utxos)| AddressUtxos {
+                address: address.to_string(),
+                keychain: None,
+                index: None,
+                utxos,
+            })
+            .collect(),
+        next_receive_index: None,
+        next_change_index: None,
+        next_index_error: None,
+    })
+}
+
+// Addresses of one keychain holding UTXOs, and the first index past the
+// last one ever paid, or `None` when the backend cannot tell which were
+async fn scan_keychain(
+    chain: &dyn ChainBackend,
+    descriptor: &KeyDescriptor,
+    keychain: usize,
+    kind: Keychain,
+    gap_limit: u32,
+) -> WalletResult<(Vec<AddressUtxos>, Option<u32>)> {
+    let mut found = Vec::new();
+    let mut next_index = 0;
+    let mut start = 0;
+
+    while start < next_index + gap_limit && start < MAX_DERIVED_PER_KEYCHAIN {
+        let end = (next_index + gap_limit).min(MAX_DERIVED_PER_KEYCHAIN);
+        let addresses = descriptor.addresses(keychain, start..end)?;
+        let Some(used) = chain.addresses_used(&addresses).await? else {
+            // An empty address may still have been paid, so a gap ends
+            // nothing and the whole keychain is scanned
+            let found = scan_range(chain, descriptor, keychain, kind, gap_limit).await?;
+            return Ok((found, None));
+        };
+        let grouped = chain.list_unspent_many(&addresses).await?;
+
+        for (i, (address, utxos)) in addresses.iter().zip(grouped).enumerate() {
+            let index = start + i as u32;
+            if used[i] {
+                next_index = index + 1;
+            }
+            if !utxos.is_empty() {
+                found.push(address_utxos(address, kind, index, utxos));
+            }
+        }
+        start = end;
+    }
+
+    Ok((found, Some(next_index)))
+}
+
+// Addresses of one keychain holding UTXOs up to the derivation bound. A
+// descriptor scan finds the outputs in one go, so addresses are derived
+// only as far as the last of them.
+async fn scan_range(
+    chain: &dyn ChainBackend,
+    descriptor: &KeyDescriptor,
+    keychain: usize,
+    kind: Keychain,
+    gap_limit: u32,
+) -> WalletResult<Vec<AddressUtxos>> {
+    let scan = chain
+        .list_unspent_descriptor(&descriptor.descriptor(keychain), MAX_DERIVED_PER_KEYCHAIN)
+        .await?;
+    let Some(scan) = scan else {
+        let addresses = descriptor.addresses(keychain, 0..MAX_DERIVED_PER_KEYCHAIN)?;
+        let grouped = chain.list_unspent_many(&addresses).await?;
+        return Ok(addresses
+            .iter()
+            .zip(grouped)
+            .enumerate()
+            .filter(|(_, (_, utxos))| !utxos.is_empty())
+            .map(|(index, (address, utxos))| address_utxos(address, kind, index as u32, utxos))
+            .collect());
+    };
+
+    let mut by_script: HashMap<ScriptBuf, Vec<Utxo>> = HashMap::new();
+    for utxo in scan {
+        by_script
+            .entry(utxo.script_pubkey.clone())
+            .or_default()
+            .push(utxo);
+    }
+
+    let mut found = Vec::new();
+    let mut start = 0;
+    while !by_script.is_empty() && start < MAX_DERIVED_PER_KEYCHAIN {
+        let end = (start + gap_limit).min(MAX_DERIVED_PER_KEYCHAIN);
+        let addresses = descriptor.addresses(keychain, start..end)?;
+        for (i, address) in addresses.iter().enumerate() {
+            if let Some(mut utxos) = by_script.remove(&address.script_pubkey()) {
+                for utxo in &mut utxos {
+                    utxo.address = Some(address.to_string());
+                }
+                found.push(address_utxos(address, kind, start + i as u32, utxos));
+            }
+        }
+        start = end;
+    }
+    Ok(found)
+}
+
+fn address_utxos(address: &Address, kind: Keychain, index: u32, utxos: Vec<Utxo>) -> AddressUtxos {
+    AddressUtxos {
+        address: address.to_string(),
+        keychain: Some(kind),
+        index: Some(index),
+        utxos,
+    }
+}
+
+/// Whether each `txid:vout` is unspent, spent in the mempool, spent in a
+/// block or unknown to the backend, in the order given
+pub async fn verify(
+    chain: &dyn ChainBackend,
+    outpoints: &[String],
+) -> WalletResult<UtxoVerifyResponse> {
+    if outpoints.len() > MAX_OUTPOINTS {
+        return Err(WalletError::InvalidRequest(format!(
+            "At most {} outpoints per request",
+            MAX_OUTPOINTS
+        )));
+    }
+    let parsed = outpoints
+        .iter()
+        .map(|outpoint| parse_outpoint(outpoint))
+        .collect::<WalletResult<Vec<_>>>()?;
+
+    let mut statuses = Vec::with_capacity(parsed.len());
+    for chunk in parsed.chunks(VERIFY_CHUNK) {
+        let chunk_statuses = chain.spend_status(chunk).await?;
+        statuses.extend(chunk.iter().zip(chunk_statuses).map(|(outpoint, status)| {
+            OutPointStatus {
+                outpoint: *outpoint,
+                status,
+            }
+        }));
+    }
+
+    Ok(UtxoVerifyResponse {
+        outpoints: statuses,
+    })
+}
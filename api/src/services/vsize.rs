@@ -0,0 +1,488 @@
+use bitcoin::Script;
+use serde::{Deserialize, Serialize};
+
+// Outpoint (36) + sequence (4), before the script_sig
+const INPUT_BASE_SIZE: u64 = 36 + 4;
+// Version (4) + lock time (4), before the input and output counts
+const TX_BASE_SIZE: u64 = 4 + 4;
+// Segwit marker and flag bytes, counted as witness data
+const SEGWIT_HEADER_WEIGHT: u64 = 2;
+// ECDSA signatures are DER encoded and vary in length; the largest one plus
+// its sighash byte is used so estimates never come in under the real size
+const ECDSA_SIG_SIZE: u64 = 73;
+const COMPRESSED_PUBKEY_SIZE: u64 = 33;
+// BIP340 signature with SIGHASH_DEFAULT
+pub const SCHNORR_SIG_SIZE: u64 = 64;
+
+/// How an input is spent
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum InputType {
+    P2pkh,
+    P2wpkh,
+    P2shP2wpkh,
+    P2trKeyPath,
+    /// `witness_size` is the full serialized witness: item count, then each
+    /// item with its length prefix (see [`witness_size`])
+    P2trScriptPath {
+        witness_size: u64,
+    },
+}
+
+/// What an output pays to
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum OutputType {
+    P2pkh,
+    P2sh,
+    P2wpkh,
+    P2wsh,
+    P2tr,
+    /// `data_size` bytes pushed after `OP_RETURN`
+    OpReturn {
+        data_size: u64,
+    },
+}
+
+/// Weight and virtual size of a transaction
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct TxSize {
+    pub weight: u64,
+    pub vsize: u64,
+}
+
+fn compact_size_len(n: u64) -> u64 {
+    match n {
+        0..=0xfc => 1,
+        0xfd..=0xffff => 3,
+        0x1_0000..=0xffff_ffff => 5,
+        _ => 9,
+    }
+}
+
+// Length of a minimal script push of `len` bytes, opcode included
+fn push_len(len: u64) -> u64 {
+    let opcode = match len {
+        0..=75 => 1,
+        76..=0xff => 2,
+        0x100..=0xffff => 3,
+        _ => 5,
+    };
+    opcode + len
+}
+
+/// Serialized size of a witness with items of the given lengths, as
+/// expected by [`InputType::P2trScriptPath`]
+pub fn witness_size(items: &[u64]) -> u64 {
+    compact_size_len(items.len() as u64)
+        + items
+            .iter()
+            .map(|len| compact_size_len(*len) + len)
+            .sum::<u64>()
+}
+
+impl InputType {
+    /// Input type for spending an output with this script, or `None` when
+    /// it cannot be told from the script alone. P2SH outputs are assumed to
+    /// wrap P2WPKH, the only P2SH form wallets here produce.
+    pub fn from_script_pubkey(script: &Script) -> Option<Self> {
+        if script.is_p2pkh() {
+            Some(InputType::P2pkh)
+        } else if script.is_p2wpkh() {
+            Some(InputType::P2wpkh)
+        } else if script.is_p2sh() {
+            Some(InputType::P2shP2wpkh)
+        } else if script.is_p2tr() {
+            Some(InputType::P2trKeyPath)
+        } else {
+            None
+        }
+    }
+
+    fn script_sig_size(&self) -> u64 {
+        match self {
+            InputType::P2pkh => push_len(ECDSA_SIG_SIZE) + push_len(COMPRESSED_PUBKEY_SIZE),
+            // Push of the 22-byte v0 witness program
+            InputType::P2shP2wpkh => push_len(22),
+            _ => 0,
+        }
+    }
+
+    /// Serialized witness size, or `None` for inputs without one
+    fn witness_size(&self) -> Option<u64> {
+        match self {
+            InputType::P2pkh => None,
+            InputType::P2wpkh | InputType::P2shP2wpkh => {
+                Some(witness_size(&[ECDSA_SIG_SIZE, COMPRESSED_PUBKEY_SIZE]))
+            }
+            InputType::P2trKeyPath => Some(witness_size(&[SCHNORR_SIG_SIZE])),
+            InputType::P2trScriptPath { witness_size } => Some(*witness_size),
+        }
+    }
+
+    fn base_size(&self) -> u64 {
+        let script_sig = self.script_sig_size();
+        INPUT_BASE_SIZE + compact_size_len(script_sig) + script_sig
+    }
+
+    /// Weight the input adds to a segwit transaction, witness included
+    pub fn weight(&self) -> u64 {
+        self.base_size() * 4 + self.witness_size().unwrap_or(1)
+    }
+}
+
+impl OutputType {
+    /// Output type of a script, or `None` for non-standard ones
+    pub fn from_script_pubkey(script: &Script) -> Option<Self> {
+        if script.is_p2pkh() {
+            Some(OutputType::P2pkh)
+        } else if script.is_p2sh() {
+            Some(OutputType::P2sh)
+        } else if script.is_p2wpkh() {
+            Some(OutputType::P2wpkh)
+        } else if script.is_p2wsh() {
+            Some(OutputType::P2wsh)
+        } else if script.is_p2tr() {
+            Some(OutputType::P2tr)
+        } else if script.is_op_return() {
+            // Strip OP_RETURN and the push opcode sized as in `push_len`
+            let pushed = script.len() as u64 - 1;
+            let opcode = match pushed {
+                0..=76 => 1,
+                77..=0x101 => 2,
+                0x102..=0x1_0002 => 3,
+                _ => 5,
+            };
+            Some(OutputType::OpReturn {
+                data_size: pushed.saturating_sub(opcode),
+            })
+        } else {
+            None
+        }
+    }
+
+    fn script_pubkey_size(&self) -> u64 {
+        match self {
+            OutputType::P2pkh => 25,
+            OutputType::P2sh => 23,
+            OutputType::P2wpkh => 22,
+            OutputType::P2wsh | OutputType::P2tr => 34,
+            OutputType::OpReturn { data_size } => 1 + push_len(*data_size),
+        }
+    }
+
+    fn size(&self) -> u64 {
+        let script = self.script_pubkey_size();
+        8 + compact_size_len(script) + script
+    }
+
+    /// Weight the output adds to a transaction
+    pub fn weight(&self) -> u64 {
+        self.size() * 4
+    }
+}
+
+/// Weight and vsize of a transaction spending `inputs` into `outputs`.
+///
+/// Sizes are exact for every type except the ECDSA ones (P2PKH, P2WPKH,
+/// P2SH-P2WPKH), which assume the largest signature encoding.
+pub fn estimate_tx_size(inputs: &[InputType], outputs: &[OutputType]) -> TxSize {
+    let base_size = TX_BASE_SIZE
+        + compact_size_len(inputs.len() as u64)
+        + compact_size_len(outputs.len() as u64)
+        + inputs.iter().map(InputType::base_size).sum::<u64>()
+        + outputs.iter().map(OutputType::size).sum::<u64>();
+
+    let mut weight = base_size * 4;
+    if inputs.iter().any(|input| input.witness_size().is_some()) {
+        // Inputs without a witness still need an empty stack in a segwit tx
+        weight += SEGWIT_HEADER_WEIGHT
+            + inputs
+                .iter()
+                .map(|input| input.witness_size().unwrap_or(1))
+                .sum::<u64>();
+    }
+
+    TxSize {
+        weight,
+        vsize: weight.div_ceil(4),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bitcoin::{
+        absolute::LockTime,
+        ecdsa,
+        hashes::Hash,
+        key::{Keypair, TapTweak},
+        opcodes::all::OP_CHECKSIG,
+        script::{Builder, PushBytes, PushBytesBuf},
+        secp256k1::{Message, Secp256k1, SecretKey, XOnlyPublicKey},
+        sighash::{EcdsaSighashType, Prevouts, SighashCache, TapSighashType},
+        taproot::{self, LeafVersion, TapLeafHash, TaprootBuilder},
+        transaction::Version,
+        Amount, CompressedPublicKey, OutPoint, PubkeyHash, PublicKey, ScriptBuf, ScriptHash,
+        Sequence, Transaction, TxIn, TxOut, Txid, WPubkeyHash, WScriptHash, Witness,
+    };
+
+    // Low-S signatures are 71 or 72 bytes with the sighash byte, so an
+    // ECDSA estimate may be up to two bytes over
+    const ECDSA_SLACK: u64 = 2;
+
+    fn leaf_script(key: XOnlyPublicKey) -> ScriptBuf {
+        Builder::new()
+            .push_x_only_key(&key)
+            .push_opcode(OP_CHECKSIG)
+            .into_script()
+    }
+
+    // Script path input spending `leaf_script` from a single-leaf tree
+    fn script_path() -> InputType {
+        let leaf = 1 + 32 + 1;
+        let control_block = 1 + 32;
+        InputType::P2trScriptPath {
+            witness_size: witness_size(&[SCHNORR_SIG_SIZE, leaf, control_block]),
+        }
+    }
+
+    fn output_script(output: &OutputType, key: XOnlyPublicKey) -> ScriptBuf {
+        let secp = Secp256k1::verification_only();
+        match output {
+            OutputType::P2pkh => ScriptBuf::new_p2pkh(&PubkeyHash::all_zeros()),
+            OutputType::P2sh => ScriptBuf::new_p2sh(&ScriptHash::all_zeros()),
+            OutputType::P2wpkh => ScriptBuf::new_p2wpkh(&WPubkeyHash::all_zeros()),
+            OutputType::P2wsh => ScriptBuf::new_p2wsh(&WScriptHash::all_zeros()),
+            OutputType::P2tr => ScriptBuf::new_p2tr(&secp, key, None),
+            OutputType::OpReturn { data_size } => ScriptBuf::new_op_return(
+                PushBytesBuf::try_from(vec![0; *data_size as usize]).unwrap(),
+            ),
+        }
+    }
+
+    // A transaction with every input signed the way its type spends
+    fn signed(inputs: &[InputType], outputs: &[OutputType]) -> Transaction {
+        let secp = Secp256k1::new();
+        let keypair = Keypair::from_secret_key(&secp, &SecretKey::from_slice(&[1; 32]).unwrap());
+        let pubkey = CompressedPublicKey(keypair.public_key());
+        let (xonly, _) = keypair.x_only_public_key();
+        let wpkh = ScriptBuf::new_p2wpkh(&pubkey.wpubkey_hash());
+        let leaf = leaf_script(xonly);
+        let tree = TaprootBuilder::new()
+            .add_leaf(0, leaf.clone())
+            .unwrap()
+            .finalize(&secp, xonly)
+            .unwrap();
+
+        let prevouts: Vec<TxOut> = inputs
+            .iter()
+            .map(|input| TxOut {
+                value: Amount::from_sat(100_000),
+                script_pubkey: match input {
+                    InputType::P2pkh => {
+                        ScriptBuf::new_p2pkh(&PublicKey::from(pubkey).pubkey_hash())
+                    }
+                    InputType::P2wpkh => wpkh.clone(),
+                    InputType::P2shP2wpkh => ScriptBuf::new_p2sh(&wpkh.script_hash()),
+                    InputType::P2trKeyPath => ScriptBuf::new_p2tr(&secp, xonly, None),
+                    InputType::P2trScriptPath { .. } => {
+                        ScriptBuf::new_p2tr_tweaked(tree.output_key())
+                    }
+                },
+            })
+            .collect();
+
+        let mut tx = Transaction {
+            version: Version::TWO,
+            lock_time: LockTime::ZERO,
+            input: (0..inputs.len())
+                .map(|vout| TxIn {
+                    previous_output: OutPoint::new(Txid::all_zeros(), vout as u32),
+                    sequence: Sequence::MAX,
+                    ..Default::default()
+                })
+                .collect(),
+            output: outputs
+                .iter()
+                .map(|output| TxOut {
+                    value: Amount::from_sat(1_000),
+                    script_pubkey: output_script(output, xonly),
+                })
+                .collect(),
+        };
+
+        let mut cache = SighashCache::new(tx.clone());
+        let prevouts_all = Prevouts::All(&prevouts);
+        let sign_ecdsa = |message: Message| {
+            ecdsa::Signature::sighash_all(secp.sign_ecdsa(&message, &keypair.secret_key()))
+        };
+        let sign_schnorr = |message: Message, keypair: &Keypair| taproot::Signature {
+            signature: secp.sign_schnorr_no_aux_rand(&message, keypair),
+            sighash_type: TapSighashType::Default,
+        };
+
+        for (index, input) in inputs.iter().enumerate() {
+            let prevout = &prevouts[index];
+            let (script_sig, witness) = match input {
+                InputType::P2pkh => {
+                    let sighash = cache
+                        .legacy_signature_hash(
+                            index,
+                            &prevout.script_pubkey,
+                            EcdsaSighashType::All.to_u32(),
+                        )
+                        .unwrap();
+                    let script_sig = Builder::new()
+                        .push_slice(sign_ecdsa(sighash.into()).serialize())
+                        .push_key(&PublicKey::from(pubkey))
+                        .into_script();
+                    (script_sig, Witness::new())
+                }
+                InputType::P2wpkh | InputType::P2shP2wpkh => {
+                    let sighash = cache
+                        .p2wpkh_signature_hash(index, &wpkh, prevout.value, EcdsaSighashType::All)
+                        .unwrap();
+                    let witness = Witness::p2wpkh(&sign_ecdsa(sighash.into()), &pubkey.0);
+                    let script_sig = match input {
+                        InputType::P2shP2wpkh => {
+                            let redeem_script = <&PushBytes>::try_from(wpkh.as_bytes()).unwrap();
+                            Builder::new().push_slice(redeem_script).into_script()
+                        }
+                        _ => ScriptBuf::new(),
+                    };
+                    (script_sig, witness)
+                }
+                InputType::P2trKeyPath => {
+                    let sighash = cache
+                        .taproot_key_spend_signature_hash(
+                            index,
+                            &prevouts_all,
+                            TapSighashType::Default,
+                        )
+                        .unwrap();
+                    let tweaked = keypair.tap_tweak(&secp, None).to_keypair();
+                    let signature = sign_schnorr(sighash.into(), &tweaked);
+                    (ScriptBuf::new(), Witness::p2tr_key_spend(&signature))
+                }
+                InputType::P2trScriptPath { .. } => {
+                    let leaf_hash = TapLeafHash::from_script(&leaf, LeafVersion::TapScript);
+                    let sighash = cache
+                        .taproot_script_spend_signature_hash(
+                            index,
+                            &prevouts_all,
+                            leaf_hash,
+                            TapSighashType::Default,
+                        )
+                        .unwrap();
+                    let control_block = tree
+                        .control_block(&(leaf.clone(), LeafVersion::TapScript))
+                        .unwrap();
+                    let signature = sign_schnorr(sighash.into(), &keypair);
+                    let witness = Witness::from_slice(&[
+                        signature.to_vec(),
+                        leaf.to_bytes(),
+                        control_block.serialize(),
+                    ]);
+                    (ScriptBuf::new(), witness)
+                }
+            };
+            tx.input[index].script_sig = script_sig;
+            tx.input[index].witness = witness;
+        }
+        tx
+    }
+
+    // The estimate never comes in under the signed weight, and only goes
+    // over it by the ECDSA signatures' slack
+    fn assert_matches_signed(inputs: &[InputType], outputs: &[OutputType]) {
+        let estimate = estimate_tx_size(inputs, outputs);
+        let weight = signed(inputs, outputs).weight().to_wu();
+        let slack: u64 = inputs
+            .iter()
+            .map(|input| match input {
+                InputType::P2pkh => ECDSA_SLACK * 4,
+                InputType::P2wpkh | InputType::P2shP2wpkh => ECDSA_SLACK,
+                _ => 0,
+            })
+            .sum();
+
+        assert!(
+            estimate.weight >= weight && estimate.weight - weight <= slack,
+            "estimated {} WU for {} WU spending {:?} into {:?}",
+            estimate.weight,
+            weight,
+            inputs,
+            outputs
+        );
+        assert_eq!(estimate.vsize, estimate.weight.div_ceil(4));
+    }
+
+    #[test]
+    fn each_input_type_matches_a_signed_spend() {
+        for input in [
+            InputType::P2pkh,
+            InputType::P2wpkh,
+            InputType::P2shP2wpkh,
+            InputType::P2trKeyPath,
+            script_path(),
+        ] {
+            assert_matches_signed(&[input], &[OutputType::P2wpkh]);
+        }
+    }
+
+    #[test]
+    fn each_output_type_matches_its_script() {
+        let key =
+            Keypair::from_secret_key(&Secp256k1::new(), &SecretKey::from_slice(&[1; 32]).unwrap())
+                .x_only_public_key()
+                .0;
+
+        for output in [
+            OutputType::P2pkh,
+            OutputType::P2sh,
+            OutputType::P2wpkh,
+            OutputType::P2wsh,
+            OutputType::P2tr,
+            // Either side of the OP_PUSHDATA1 and OP_PUSHDATA2 thresholds
+            OutputType::OpReturn { data_size: 75 },
+            OutputType::OpReturn { data_size: 76 },
+            OutputType::OpReturn { data_size: 255 },
+            OutputType::OpReturn { data_size: 256 },
+        ] {
+            assert_matches_signed(&[InputType::P2trKeyPath], std::slice::from_ref(&output));
+
+            let script = output_script(&output, key);
+            assert_eq!(output.script_pubkey_size(), script.len() as u64);
+            assert_eq!(OutputType::from_script_pubkey(&script), Some(output));
+        }
+    }
+
+    #[test]
+    fn mixed_legacy_and_segwit_inputs() {
+        assert_matches_signed(
+            &[
+                InputType::P2pkh,
+                InputType::P2shP2wpkh,
+                InputType::P2wpkh,
+                InputType::P2trKeyPath,
+                script_path(),
+            ],
+            &[
+                OutputType::P2tr,
+                OutputType::P2pkh,
+                OutputType::OpReturn { data_size: 80 },
+            ],
+        );
+    }
+
+    #[test]
+    fn legacy_only_transactions_have_no_witness() {
+        let inputs = [InputType::P2pkh, InputType::P2pkh];
+        assert_matches_signed(&inputs, &[OutputType::P2pkh]);
+        assert_eq!(
+            estimate_tx_size(&inputs, &[OutputType::P2pkh]).weight % 4,
+            0
+        );
+    }
+}
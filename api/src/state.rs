@@ -0,0 +1,72 @@
+use crate::config::{network_name, parse_network};
+use crate::error::{WalletError, WalletResult};
+use crate::services::chain::ChainBackend;
+use crate::services::signing::SigningPolicy;
+use crate::services::spell::SpellProver;
+use bitcoin::Network;
+use std::{collections::HashMap, sync::Arc};
+
+/// Shared state handed to every handler through axum's `State` extractor.
+///
+/// There is one per configured network, each with its own backends and
+/// prover, so a request can only ever reach the network of its route.
+#[derive(Clone)]
+pub struct AppState {
+    /// Network served by this state
+    pub network: Network,
+    pub chain: Arc<dyn ChainBackend>,
+    /// Backends bound to named node wallets, selected with `?wallet=`
+    pub wallets: HashMap<String, Arc<dyn ChainBackend>>,
+    pub prover: Arc<SpellProver>,
+    /// Checked before the node wallet signs anything
+    pub signing: Arc<SigningPolicy>,
+}
+
+impl AppState {
+    pub fn new(
+        network: Network,
+        chain: Arc<dyn ChainBackend>,
+        prover: SpellProver,
+        signing: SigningPolicy,
+    ) -> Self {
+        Self {
+            network,
+            chain,
+            wallets: HashMap::new(),
+            prover: Arc::new(prover),
+            signing: Arc::new(signing),
+        }
+    }
+
+    pub fn with_wallets(mut self, wallets: HashMap<String, Arc<dyn ChainBackend>>) -> Self {
+        self.wallets = wallets;
+        self
+    }
+
+    /// The backend for a named wallet, or the default one
+    pub fn chain_for(&self, wallet: Option<&str>) -> WalletResult<Arc<dyn ChainBackend>> {
+        match wallet {
+            None => Ok(self.chain.clone()),
+            Some(name) => self
+                .wallets
+                .get(name)
+                .cloned()
+                .ok_or_else(|| WalletError::BitcoinError(format!("Unknown wallet: {}", name))),
+        }
+    }
+
+    /// Reject a network named in a request body that is not this state's
+    pub fn check_network(&self, name: &str) -> WalletResult<()> {
+        let network = parse_network(name)
+            .map_err(|_| WalletError::WrongNetwork(format!("Unknown network: {}", name)))?;
+
+        if network != self.network {
+            return Err(WalletError::WrongNetwork(format!(
+                "Request is for {} but this endpoint serves {}",
+                network_name(network),
+                network_name(self.network)
+            )));
+        }
+        Ok(())
+    }
+}